@@ -0,0 +1,257 @@
+//! `#[delegated]`, see `libdlock::dlock2::delegated`.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, parse_quote, spanned::Spanned, Error, FnArg, Ident, ImplItem, ImplItemFn,
+    ItemImpl, Pat, ReturnType, Type, Visibility,
+};
+
+/// Turns the `&mut self` methods of an inherent impl block into requests of a
+/// `DLock2`. For `impl Foo` it generates
+///
+/// - `FooOp`, one variant per method holding its arguments,
+/// - `FooOutput`, one variant per method holding its return value,
+/// - `Delegatable for Foo`, whose `delegate` runs a `FooOp` on `Foo`,
+/// - `DelegatedFoo`, the same methods taking `&self`, implemented by
+///   `Delegated<Foo, L>` for every `L: DLock2<FooOp, FooOutput>`.
+///
+/// The generated items are as visible as the first method. Arguments and
+/// return values must be `Send + Sync + Debug + 'static` and can't name the
+/// generic parameters of the impl block. Methods without a `&mut self`
+/// receiver are left alone.
+#[proc_macro_attribute]
+pub fn delegated(args: TokenStream, item: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return Error::new(Span::call_site(), "#[delegated] takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let item = parse_macro_input!(item as ItemImpl);
+
+    match expand(&item) {
+        Ok(expanded) => quote!(#item #expanded).into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct Method<'a> {
+    func: &'a ImplItemFn,
+    variant: Ident,
+    args: Vec<(Ident, &'a Type)>,
+    output: TokenStream2,
+}
+
+fn expand(item: &ItemImpl) -> syn::Result<TokenStream2> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(Error::new(
+            path.span(),
+            "#[delegated] only applies to inherent impl blocks",
+        ));
+    }
+
+    let name = match &*item.self_ty {
+        Type::Path(path) if path.qself.is_none() => &path.path.segments.last().unwrap().ident,
+        ty => {
+            return Err(Error::new(
+                ty.span(),
+                "#[delegated] needs a named type to implement",
+            ))
+        }
+    };
+
+    let methods = item
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            ImplItem::Fn(func) if takes_mut_self(func) => Some(method(func)),
+            _ => None,
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    if methods.is_empty() {
+        return Err(Error::new(
+            item.self_ty.span(),
+            "#[delegated] found no `&mut self` method to delegate",
+        ));
+    }
+
+    let vis = match &methods[0].func.vis {
+        Visibility::Inherited => quote!(),
+        vis => quote!(#vis),
+    };
+    let op = format_ident!("{}Op", name);
+    let output = format_ident!("{}Output", name);
+    let handle = format_ident!("Delegated{}", name);
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+
+    let mut handle_generics = item.generics.clone();
+    handle_generics.params.push(parse_quote! {
+        __DelegatedLock: ::libdlock::dlock2::DLock2<#op, #output>
+    });
+    let (handle_generics, _, _) = handle_generics.split_for_impl();
+
+    let op_variants = methods.iter().map(|method| {
+        let variant = &method.variant;
+        let fields = method.args.iter().map(|(arg, ty)| quote!(#arg: #ty));
+        if method.args.is_empty() {
+            quote!(#variant)
+        } else {
+            quote!(#variant { #(#fields),* })
+        }
+    });
+
+    let output_variants = methods.iter().map(|method| {
+        let variant = &method.variant;
+        let ty = &method.output;
+        quote!(#variant(#ty))
+    });
+
+    let dispatch = methods.iter().map(|method| {
+        let variant = &method.variant;
+        let ident = &method.func.sig.ident;
+        let args = method.args.iter().map(|(arg, _)| arg).collect::<Vec<_>>();
+        quote! {
+            #op::#variant { #(#args),* } => #output::#variant(self.#ident(#(#args),*)),
+        }
+    });
+
+    let signatures = methods.iter().map(|method| {
+        let ident = &method.func.sig.ident;
+        let args = method.args.iter().map(|(arg, ty)| quote!(#arg: #ty));
+        let ty = &method.output;
+        quote!(fn #ident(&self, #(#args),*) -> #ty)
+    });
+
+    let declarations = methods.iter().zip(signatures.clone()).map(|(method, sig)| {
+        let docs = method
+            .func
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("doc"));
+        quote! {
+            #(#docs)*
+            #sig;
+        }
+    });
+
+    let definitions = methods.iter().zip(signatures).map(|(method, sig)| {
+        let variant = &method.variant;
+        let args = method.args.iter().map(|(arg, _)| arg);
+        quote! {
+            #sig {
+                match self.call(#op::#variant { #(#args),* }) {
+                    #output::#variant(output) => output,
+                    #[allow(unreachable_patterns)]
+                    _ => unreachable!("the delegate answered a different method"),
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        #[derive(Debug)]
+        #vis enum #op {
+            #(#op_variants),*
+        }
+
+        // only read through the handle, a method nobody calls is already
+        // reported on its op variant
+        #[allow(dead_code)]
+        #[derive(Debug)]
+        #vis enum #output {
+            #(#output_variants),*
+        }
+
+        impl #impl_generics ::libdlock::dlock2::delegated::Delegatable for #self_ty #where_clause {
+            type Op = #op;
+            type Output = #output;
+
+            fn delegate(&mut self, op: #op) -> #output {
+                match op {
+                    #(#dispatch)*
+                }
+            }
+        }
+
+        #vis trait #handle {
+            #(#declarations)*
+        }
+
+        impl #handle_generics #handle
+            for ::libdlock::dlock2::delegated::Delegated<#self_ty, __DelegatedLock>
+            #where_clause
+        {
+            #(#definitions)*
+        }
+    })
+}
+
+fn takes_mut_self(func: &ImplItemFn) -> bool {
+    matches!(
+        func.sig.inputs.first(),
+        Some(FnArg::Receiver(receiver)) if receiver.reference.is_some() && receiver.mutability.is_some()
+    )
+}
+
+fn method(func: &ImplItemFn) -> syn::Result<Method<'_>> {
+    let sig = &func.sig;
+
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new(
+            sig.generics.span(),
+            "delegated methods can't be generic",
+        ));
+    }
+
+    if sig.asyncness.is_some() {
+        return Err(Error::new(sig.span(), "delegated methods can't be async"));
+    }
+
+    let args = sig
+        .inputs
+        .iter()
+        .skip(1)
+        .map(|arg| match arg {
+            FnArg::Typed(arg) => match &*arg.pat {
+                Pat::Ident(pat) if pat.by_ref.is_none() && pat.subpat.is_none() => {
+                    Ok((pat.ident.clone(), &*arg.ty))
+                }
+                pat => Err(Error::new(
+                    pat.span(),
+                    "arguments of delegated methods must be plain identifiers",
+                )),
+            },
+            FnArg::Receiver(receiver) => Err(Error::new(receiver.span(), "unexpected receiver")),
+        })
+        .collect::<syn::Result<_>>()?;
+
+    let output = match &sig.output {
+        ReturnType::Default => quote!(()),
+        ReturnType::Type(_, ty) => quote!(#ty),
+    };
+
+    Ok(Method {
+        func,
+        variant: Ident::new(&upper_camel_case(&sig.ident.to_string()), sig.ident.span()),
+        args,
+        output,
+    })
+}
+
+fn upper_camel_case(name: &str) -> String {
+    name.trim_start_matches("r#")
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
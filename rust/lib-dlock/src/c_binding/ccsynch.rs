@@ -1,40 +1,39 @@
-use std::{
-    cell::SyncUnsafeCell,
-    ffi::c_void,
-    mem::{MaybeUninit},
-    ptr::{self},
-};
-
-
+use std::{cell::SyncUnsafeCell, ffi::c_void, mem::MaybeUninit};
 
-use crate::{cc_synch_init, cc_synch_lock, cc_synch_t, dlock2::{DLock2, DLock2Delegate}};
+use crate::{
+    cc_synch_init, cc_synch_lock, cc_synch_t,
+    dlock2::{slot::Slot, DLock2, DLock2Delegate},
+};
 
 #[derive(Debug)]
-pub struct CCCSynch<T, F, I>
+pub struct CCCSynch<T, F, I, O>
 where
     T: Sized,
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
     I: Send,
+    O: Send,
 {
     job: F,
     data: SyncUnsafeCell<T>,
     lock: SyncUnsafeCell<cc_synch_t>,
-    phantom: std::marker::PhantomData<I>,
+    phantom: std::marker::PhantomData<fn(I) -> O>,
 }
 
-unsafe impl<T, F, I> Sync for CCCSynch<T, F, I>
+unsafe impl<T, F, I, O> Sync for CCCSynch<T, F, I, O>
 where
     T: Sized,
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
     I: Send,
+    O: Send,
 {
 }
 
-impl<T, F, I> CCCSynch<T, F, I>
+impl<T, F, I, O> CCCSynch<T, F, I, O>
 where
     T: Sized,
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
     I: Send,
+    O: Send,
 {
     pub fn new(data: T, job: F) -> Self {
         unsafe {
@@ -52,62 +51,69 @@ where
     }
 }
 
-unsafe impl<T, F, I> DLock2<I> for CCCSynch<T, F, I>
+unsafe impl<T, F, I, O> DLock2<I, O> for CCCSynch<T, F, I, O>
 where
     T: Sized + Send + Sync + 'static,
-    F: DLock2Delegate<T, I> + 'static,
+    F: DLock2Delegate<T, I, O> + 'static,
     I: Send + 'static,
+    O: Send + 'static,
 {
     #[cfg(feature = "combiner_stat")]
     fn get_combine_time(&self) -> Option<u64> {
         None
     }
 
-    fn lock(&self, input: I) -> I {
+    fn lock(&self, input: I) -> O {
         unsafe {
-            let mut wrapper = Wrapper { inner: self, input };
+            let mut wrapper = Wrapper {
+                inner: self,
+                slot: Slot::input(input),
+            };
 
             let value = cc_synch_lock(
                 self.lock.get(),
-                Some(callback::<T, F, I>),
-                &mut wrapper as *mut _ as *mut c_void,
+                Some(callback::<T, F, I, O>),
+                &mut wrapper as *mut Wrapper<T, F, I, O> as *mut c_void,
             );
 
             if value.is_null() {
                 panic!("fc_lock failed")
             }
 
-            let result = value as *mut I;
+            // the callback wrote the result back into the wrapper
+            let wrapper = &*(value as *mut Wrapper<T, F, I, O>);
 
-            result.read()
+            wrapper.slot.take_output()
         }
     }
 }
 
-pub struct Wrapper<'a, T, F, I>
+pub struct Wrapper<'a, T, F, I, O>
 where
     T: Sized,
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
     I: Send,
+    O: Send,
 {
-    inner: &'a CCCSynch<T, F, I>,
-    input: I,
+    inner: &'a CCCSynch<T, F, I, O>,
+    slot: Slot<I, O>,
 }
 
-unsafe extern "C" fn callback<T, F, I>(wrapper: *mut c_void) -> *mut c_void
+unsafe extern "C" fn callback<T, F, I, O>(wrapper: *mut c_void) -> *mut c_void
 where
     T: Sized,
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
     I: Send,
+    O: Send,
 {
-    let wrapper = wrapper as *mut Wrapper<T, F, I>;
+    let wrapper = wrapper as *mut Wrapper<T, F, I, O>;
 
     let result = ((*wrapper).inner.job)(
         (*wrapper).inner.data.get().as_mut().unwrap(),
-        ptr::read(&(*wrapper).input),
+        (*wrapper).slot.take_input(),
     );
 
-    (*wrapper).input = result;
+    (*wrapper).slot = Slot::output(result);
 
     wrapper as *mut c_void
 }
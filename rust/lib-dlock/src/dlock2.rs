@@ -1,6 +1,8 @@
 use std::cmp::Reverse;
 use std::fmt::{Binary, Debug};
 use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+use std::any::type_name;
+use std::time::Duration;
 
 use crate::{
     c_binding::{ccsynch::CCCSynch, flatcombining::CFlatCombining},
@@ -11,53 +13,335 @@ use enum_dispatch::enum_dispatch;
 use strum::Display;
 
 use self::{
-    cc_ban::CCBan, dsm::DSMSynch, fc_ban::FCBan, fc_pq::UsageNode, fc_sl::FCSL, mutex::DLock2Mutex, spinlock::DLock2Wrapper, uscl::DLock2USCL
+    ban_policy::AdaptiveBan,
+    batch::BatchDelegate,
+    cc_ban::CCBan,
+    cohort::{CBoMcs, CTktTkt},
+    dsm::DSMSynch,
+    fc_ban::FCBan,
+    fc_edf::FCEDF,
+    fc_pq::{
+        admission::{SoftBan, WorkConserving},
+        policy::{Fifo, Lottery, Stride},
+        UsageNode,
+    },
+    fc_sl::FCSL,
+    ffwd::Ffwd,
+    group::Groups,
+    hsynch::HSynch,
+    mutex::DLock2Mutex,
+    shfl::{
+        policy::{NumaGrouping, UsageFair},
+        ShflLock,
+    },
+    shm::ShmCC,
+    spinlock::DLock2Wrapper,
+    tclock::DLock2TCLock,
+    ticket::{Ticket, TicketState},
+    uscl::DLock2USCL,
 };
 
+pub mod ban_policy;
+pub mod batch;
 pub mod cc;
 pub mod cc_ban;
+pub mod claim;
+pub mod cohort;
+pub mod condition;
+pub mod delegated;
 pub mod dsm;
 pub mod fc;
 pub mod fc_ban;
+pub mod fc_edf;
 pub mod fc_sl;
+pub mod ffwd;
+pub mod group;
+pub mod hsynch;
 pub mod rcl;
+pub mod seqlock;
+pub mod shfl;
+pub mod shm;
+pub mod slot;
 
 pub mod mutex;
+pub mod nesting;
 pub mod spinlock;
 pub mod uscl;
 pub mod fc_pq;
+pub mod tclock;
+pub mod ticket;
+pub mod usage_decay;
 
-pub trait DLock2Delegate<T, I>: Fn(&mut T, I) -> I + Send + Sync {}
-impl<T, I, F> DLock2Delegate<T, I> for F where F: Fn(&mut T, I) -> I + Send + Sync {}
+pub trait DLock2Delegate<T, I, O>: Fn(&mut T, I) -> O + Send + Sync {}
+impl<T, I, O, F> DLock2Delegate<T, I, O> for F where F: Fn(&mut T, I) -> O + Send + Sync {}
 
 // We probably should have a slightly more restrictive bound on the trait
-#[enum_dispatch(DLock2Impl<T, I, F>)]
-pub unsafe trait DLock2<I>: Send + Sync {
-    fn lock(&self, data: I) -> I;
+#[enum_dispatch(DLock2Impl<T, I, O, F>)]
+pub unsafe trait DLock2<I, O>: Send + Sync {
+    fn lock(&self, data: I) -> O;
 
     #[cfg(feature = "combiner_stat")]
     fn get_combine_time(&self) -> Option<u64>;
+
+    /// How often the current thread was held out by soft-ban admission
+    fn get_soft_ban_count(&self) -> Option<u64> {
+        None
+    }
+
+    /// How many deadlines the current thread missed, for locks that take
+    /// deadlines
+    fn get_deadline_miss_count(&self) -> Option<u64> {
+        None
+    }
+
+    /// Move the current thread into the group called `name`, creating it
+    /// with `weight` if needed. Returns the group id, or `None` if the lock
+    /// has no groups.
+    fn join_group(&self, _name: &str, _weight: u64) -> Option<usize> {
+        None
+    }
+
+    /// The groups of this lock, if it has any.
+    fn groups(&self) -> Option<&Groups> {
+        None
+    }
+
+    /// CPU the lock's dedicated server thread is pinned to, which the
+    /// threads using the lock should leave free.
+    fn server_cpu(&self) -> Option<usize> {
+        None
+    }
+
+    /// Hand `data` to the lock without waiting for its output, which
+    /// `wait` returns later. Locks that cannot keep a request in flight run
+    /// it right away. Requests a thread has in flight at once may be served
+    /// in any order.
+    fn submit(&self, data: I) -> Ticket<'_, O> {
+        Ticket::ready(self, self.lock(data))
+    }
+
+    /// The output of a request the calling thread submitted to this lock.
+    fn wait(&self, ticket: Ticket<'_, O>) -> O {
+        match ticket.redeem(self) {
+            TicketState::Ready(output) => output,
+            TicketState::Pending(_) => unreachable!("lock without slots handed out a pending ticket"),
+        }
+    }
+
+    /// Submit `data` and drop its output once it is served.
+    fn lock_detached(&self, data: I) {
+        self.lock(data);
+    }
+
+    /// Whether `try_lock` and `lock_timeout` can withdraw a request on this
+    /// lock.
+    fn supports_timeout(&self) -> bool {
+        false
+    }
+
+    /// Run `data` only if the calling thread can combine or gets it served
+    /// right away, otherwise hand it back as `Err`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock cannot withdraw requests, see `supports_timeout`.
+    fn try_lock(&self, data: I) -> Result<O, I> {
+        self.lock_timeout(data, Duration::ZERO)
+    }
+
+    /// Run `data` unless it is still waiting after `timeout`, it is then
+    /// withdrawn and handed back as `Err`. A request a combiner took up
+    /// before the timeout is waited for and returns `Ok`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock cannot withdraw requests, see `supports_timeout`.
+    fn lock_timeout(&self, _data: I, _timeout: Duration) -> Result<O, I> {
+        panic!("{} cannot withdraw requests", type_name::<Self>())
+    }
 }
 
 #[enum_dispatch]
 #[derive(Debug, Display)]
-pub enum DLock2Impl<T, I, F>
+pub enum DLock2Impl<T, I, O, F>
 where
     T: Send + Sync + 'static,
     I: Send + Sync + Debug + 'static,
-    F: DLock2Delegate<T, I> + 'static,
+    O: Send + Sync + Debug + 'static,
+    F: DLock2Delegate<T, I, O> + 'static,
 {
-    FC(FC<T, I, F>),
-    FCBan(FCBan<T, I, F>),
-    CC(CCSynch<T, I, F>),
-    DSM(DSMSynch<T, I, F>),
-    CCBan(CCBan<T, I, F>),
-    FC_SL(FCSL<T, I, F, RawSpinLock>),
-    FC_PQ_BTree(fc_pq::FCPQ<T, I, BTreeSet<UsageNode<'static, I>>, F, RawSpinLock>),
-    FC_PQ_BHeap(fc_pq::FCPQ<T, I, BinaryHeap<Reverse<UsageNode<'static, I>>>, F, RawSpinLock>),
-    SpinLock(DLock2Wrapper<T, I, F, RawSpinLock>),
-    Mutex(DLock2Mutex<T, I, F>),
-    USCL(DLock2USCL<T, I, F>),
-    C_FC(CFlatCombining<T, F, I>),
-    C_CC(CCCSynch<T, F, I>),
+    FC(FC<T, I, O, F>),
+    FCBan(FCBan<T, I, O, F>),
+    FCBanAdaptive(FCBan<T, I, O, F, RawSpinLock, AdaptiveBan>),
+    CC(CCSynch<T, I, O, F>),
+    DSM(DSMSynch<T, I, O, F>),
+    CCBan(CCBan<T, I, O, F>),
+    CCBanAdaptive(CCBan<T, I, O, F, AdaptiveBan>),
+    HSynch(HSynch<T, I, O, F>),
+    Ffwd(Ffwd<T, I, O, F>),
+    ShmCC(ShmCC<T, I, O, F>),
+    C_BO_MCS(CBoMcs<T, I, O, F>),
+    C_TKT_TKT(CTktTkt<T, I, O, F>),
+    FC_EDF(FCEDF<T, I, O, F>),
+    FC_SL(FCSL<T, I, O, F, RawSpinLock>),
+    FC_PQ_BTree(fc_pq::FCPQ<T, I, O, BTreeSet<UsageNode<'static, I, O>>, F, RawSpinLock>),
+    FC_PQ_BHeap(
+        fc_pq::FCPQ<T, I, O, BinaryHeap<Reverse<UsageNode<'static, I, O>>>, F, RawSpinLock>,
+    ),
+    FC_PQ_BTree_Fifo(
+        fc_pq::FCPQ<
+            T,
+            I,
+            O,
+            BTreeSet<UsageNode<'static, I, O>>,
+            F,
+            RawSpinLock,
+            WorkConserving,
+            Fifo,
+        >,
+    ),
+    FC_PQ_BTree_Stride(
+        fc_pq::FCPQ<
+            T,
+            I,
+            O,
+            BTreeSet<UsageNode<'static, I, O>>,
+            F,
+            RawSpinLock,
+            WorkConserving,
+            Stride,
+        >,
+    ),
+    FC_PQ_BTree_Lottery(
+        fc_pq::FCPQ<
+            T,
+            I,
+            O,
+            BTreeSet<UsageNode<'static, I, O>>,
+            F,
+            RawSpinLock,
+            WorkConserving,
+            Lottery,
+        >,
+    ),
+    FC_PQ_BTree_SoftBan(
+        fc_pq::FCPQ<T, I, O, BTreeSet<UsageNode<'static, I, O>>, F, RawSpinLock, SoftBan>,
+    ),
+    FC_PQ_BHeap_SoftBan(
+        fc_pq::FCPQ<
+            T,
+            I,
+            O,
+            BinaryHeap<Reverse<UsageNode<'static, I, O>>>,
+            F,
+            RawSpinLock,
+            SoftBan,
+        >,
+    ),
+    SpinLock(DLock2Wrapper<T, I, O, F, RawSpinLock>),
+    ShflLock(ShflLock<T, I, O, F>),
+    ShflLock_Numa(ShflLock<T, I, O, F, NumaGrouping>),
+    ShflLock_UsageFair(ShflLock<T, I, O, F, UsageFair>),
+    Mutex(DLock2Mutex<T, I, O, F>),
+    USCL(DLock2USCL<T, I, O, F>),
+    TCLock(DLock2TCLock<T, I, O, F>),
+    C_FC(CFlatCombining<T, F, I, O>),
+    C_CC(CCCSynch<T, F, I, O>),
+}
+
+impl<T, I, O, F> DLock2Impl<T, I, O, F>
+where
+    T: Send + Sync + 'static,
+    I: Send + Sync + Debug + 'static,
+    O: Send + Sync + Debug + 'static,
+    F: DLock2Delegate<T, I, O> + 'static,
+{
+    /// The adaptive ban controller, if this lock has one.
+    pub fn ban_controller(&self) -> Option<&AdaptiveBan> {
+        match self {
+            DLock2Impl::FCBanAdaptive(lock) => Some(lock.ban_policy()),
+            DLock2Impl::CCBanAdaptive(lock) => Some(lock.ban_policy()),
+            _ => None,
+        }
+    }
+
+    /// Submit a request due at `deadline_tsc`. Locks that do not schedule by
+    /// deadline ignore it.
+    pub fn lock_with_deadline(&self, data: I, deadline_tsc: u64) -> O {
+        match self {
+            DLock2Impl::FC_EDF(lock) => lock.lock_with_deadline(data, deadline_tsc),
+            _ => self.lock(data),
+        }
+    }
+
+    /// Hand the requests of every combining pass to `batch` at once. Only
+    /// FC, CC, DSM and FC-PQ take a batch delegate, other locks are returned
+    /// as they are.
+    pub fn with_batch_delegate(self, batch: impl BatchDelegate<T, I, O> + 'static) -> Self {
+        match self {
+            DLock2Impl::FC(lock) => lock.with_batch_delegate(batch).into(),
+            DLock2Impl::CC(lock) => lock.with_batch_delegate(batch).into(),
+            DLock2Impl::DSM(lock) => lock.with_batch_delegate(batch).into(),
+            DLock2Impl::FC_PQ_BTree(lock) => lock.with_batch_delegate(batch).into(),
+            DLock2Impl::FC_PQ_BHeap(lock) => lock.with_batch_delegate(batch).into(),
+            DLock2Impl::FC_PQ_BTree_Fifo(lock) => lock.with_batch_delegate(batch).into(),
+            DLock2Impl::FC_PQ_BTree_Stride(lock) => lock.with_batch_delegate(batch).into(),
+            DLock2Impl::FC_PQ_BTree_Lottery(lock) => lock.with_batch_delegate(batch).into(),
+            DLock2Impl::FC_PQ_BTree_SoftBan(lock) => lock.with_batch_delegate(batch).into(),
+            DLock2Impl::FC_PQ_BHeap_SoftBan(lock) => lock.with_batch_delegate(batch).into(),
+            lock => lock,
+        }
+    }
+
+    /// Whether combining passes go through a batch delegate.
+    pub fn is_batched(&self) -> bool {
+        match self {
+            DLock2Impl::FC(lock) => lock.is_batched(),
+            DLock2Impl::CC(lock) => lock.is_batched(),
+            DLock2Impl::DSM(lock) => lock.is_batched(),
+            DLock2Impl::FC_PQ_BTree(lock) => lock.is_batched(),
+            DLock2Impl::FC_PQ_BHeap(lock) => lock.is_batched(),
+            DLock2Impl::FC_PQ_BTree_Fifo(lock) => lock.is_batched(),
+            DLock2Impl::FC_PQ_BTree_Stride(lock) => lock.is_batched(),
+            DLock2Impl::FC_PQ_BTree_Lottery(lock) => lock.is_batched(),
+            DLock2Impl::FC_PQ_BTree_SoftBan(lock) => lock.is_batched(),
+            DLock2Impl::FC_PQ_BHeap_SoftBan(lock) => lock.is_batched(),
+            _ => false,
+        }
+    }
+
+    /// Whether `lock_when` can park requests on this lock, only FC and CC
+    /// can.
+    pub fn supports_lock_when(&self) -> bool {
+        matches!(self, DLock2Impl::FC(_) | DLock2Impl::CC(_))
+    }
+
+    /// Run `data` once `predicate` holds on the data, without submitting it
+    /// again meanwhile.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lock cannot park requests, see `supports_lock_when`.
+    pub fn lock_when(&self, data: I, predicate: impl Fn(&T) -> bool + Sync) -> O {
+        match self {
+            DLock2Impl::FC(lock) => lock.lock_when(data, predicate),
+            DLock2Impl::CC(lock) => lock.lock_when(data, predicate),
+            lock => panic!("{} cannot park requests", lock),
+        }
+    }
+
+    /// Run `reader` on the data without delegating it. Only FC and CC read
+    /// optimistically, `None` means the read has to be delegated.
+    ///
+    /// # Safety
+    ///
+    /// `reader` may see the data in the middle of a combining pass, it must
+    /// neither crash nor hang on such a state.
+    pub unsafe fn try_read<R>(&self, reader: impl Fn(&T) -> R) -> Option<R> {
+        match self {
+            DLock2Impl::FC(lock) => lock.try_read(reader),
+            DLock2Impl::CC(lock) => lock.try_read(reader),
+            _ => None,
+        }
+    }
 }
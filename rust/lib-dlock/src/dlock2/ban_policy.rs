@@ -0,0 +1,234 @@
+//! Penalty policies shared by the banning locks (`FCBan` and `CCBan`).
+//!
+//! After serving a request the combiner asks the policy how long the owner
+//! of the request should be banned for. `FixedBan` keeps the original
+//! `cs * num_waiting_threads` rule. `AdaptiveBan` is a feedback controller:
+//! it counts the threads that were actually served within a window of
+//! requests, measures each thread's share of the critical-section time in
+//! that window, and scales the penalty multiplier until the shares even out.
+
+use std::{
+    arch::x86_64::__rdtscp,
+    collections::VecDeque,
+    sync::atomic::{AtomicUsize, Ordering::*},
+};
+
+use crate::spin_lock::SpinLock;
+
+pub trait BanPolicy: Send + Sync {
+    /// Called once per thread, the returned id is passed back to `penalty`.
+    fn register(&self) -> usize;
+
+    /// Penalty (in tsc) for a request of thread `id` that held the lock for
+    /// `cs` cycles. `num_waiting_threads` is the lock's own thread count.
+    fn penalty(&self, id: usize, cs: u64, num_waiting_threads: u64) -> u64;
+}
+
+/// Take up to `credit` cycles off a ban that is still running at `now`.
+/// A ban that already expired is left alone so that credit cannot be banked.
+#[inline]
+pub(crate) fn credit_ban(banned_until: &mut u64, credit: u64, now: u64) {
+    if *banned_until > now {
+        *banned_until = banned_until.saturating_sub(credit).max(now);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FixedBan;
+
+impl BanPolicy for FixedBan {
+    #[inline(always)]
+    fn register(&self) -> usize {
+        0
+    }
+
+    #[inline(always)]
+    fn penalty(&self, _: usize, cs: u64, num_waiting_threads: u64) -> u64 {
+        cs * num_waiting_threads
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveBanConfig {
+    /// Number of served requests per window
+    pub window: u64,
+    /// Proportional gain applied to the unfairness error
+    pub gain: f64,
+    /// Unfairness below which the multiplier relaxes towards `min_multiplier`
+    pub tolerance: f64,
+    pub initial_multiplier: f64,
+    pub min_multiplier: f64,
+    pub max_multiplier: f64,
+    /// Number of most recent windows kept in the history, older samples are
+    /// dropped
+    pub history: usize,
+}
+
+impl Default for AdaptiveBanConfig {
+    fn default() -> Self {
+        Self {
+            window: 1024,
+            gain: 0.1,
+            tolerance: 0.05,
+            initial_multiplier: 1.0,
+            min_multiplier: 0.25,
+            max_multiplier: 16.0,
+            history: 4096,
+        }
+    }
+}
+
+/// State of the controller at the end of a window.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BanSample {
+    pub window: u64,
+    pub tsc: u64,
+    pub active_threads: u64,
+    /// Largest and smallest share of critical-section time among the
+    /// active threads
+    pub max_share: f64,
+    pub min_share: f64,
+    /// `max_share * active_threads - 1`, zero when the shares are equal
+    pub error: f64,
+    /// Multiplier used for the next window
+    pub multiplier: f64,
+}
+
+#[derive(Debug)]
+struct ControllerState {
+    usage: Vec<u64>,
+    served: u64,
+    seen: u64,
+    active_threads: u64,
+    multiplier: f64,
+    /// Windows completed so far
+    windows: u64,
+    history: VecDeque<BanSample>,
+}
+
+#[derive(Debug)]
+pub struct AdaptiveBan {
+    config: AdaptiveBanConfig,
+    next_id: AtomicUsize,
+    state: SpinLock<ControllerState>,
+}
+
+impl AdaptiveBan {
+    pub fn new(config: AdaptiveBanConfig) -> Self {
+        assert!(config.window > 0, "window must not be empty");
+        assert!(config.min_multiplier <= config.max_multiplier);
+
+        Self {
+            config,
+            next_id: AtomicUsize::new(0),
+            state: SpinLock::new(ControllerState {
+                usage: Vec::new(),
+                served: 0,
+                seen: 0,
+                active_threads: 0,
+                multiplier: config
+                    .initial_multiplier
+                    .clamp(config.min_multiplier, config.max_multiplier),
+                windows: 0,
+                history: VecDeque::with_capacity(config.history.min(4096)),
+            }),
+        }
+    }
+
+    pub fn config(&self) -> &AdaptiveBanConfig {
+        &self.config
+    }
+
+    pub fn multiplier(&self) -> f64 {
+        self.state.lock().multiplier
+    }
+
+    /// One sample per completed window, in order, limited to the last
+    /// `config.history` windows.
+    pub fn history(&self) -> Vec<BanSample> {
+        self.state.lock().history.iter().copied().collect()
+    }
+
+    fn end_window(&self, state: &mut ControllerState) {
+        let total: u64 = state.usage.iter().sum();
+        let active = state.usage.iter().filter(|&&u| u > 0);
+
+        let (max, min) = active
+            .clone()
+            .fold((0, u64::MAX), |(max, min), &u| (max.max(u), min.min(u)));
+        let active_threads = active.count() as u64;
+
+        let (max_share, min_share) = if total == 0 {
+            (0.0, 0.0)
+        } else {
+            (max as f64 / total as f64, min as f64 / total as f64)
+        };
+
+        let error = (max_share * active_threads as f64 - 1.0).max(0.0);
+
+        let config = &self.config;
+        state.multiplier = if error > config.tolerance {
+            state.multiplier * (1.0 + config.gain * error)
+        } else {
+            state.multiplier * (1.0 - config.gain)
+        }
+        .clamp(config.min_multiplier, config.max_multiplier);
+
+        if state.history.len() >= config.history {
+            state.history.pop_front();
+        }
+        if config.history > 0 {
+            state.history.push_back(BanSample {
+                window: state.windows,
+                tsc: unsafe { __rdtscp(&mut 0) },
+                active_threads,
+                max_share,
+                min_share,
+                error,
+                multiplier: state.multiplier,
+            });
+        }
+        state.windows += 1;
+
+        state.usage.iter_mut().for_each(|u| *u = 0);
+        state.active_threads = active_threads;
+        state.served = 0;
+        state.seen = 0;
+    }
+}
+
+impl Default for AdaptiveBan {
+    fn default() -> Self {
+        Self::new(AdaptiveBanConfig::default())
+    }
+}
+
+impl BanPolicy for AdaptiveBan {
+    fn register(&self) -> usize {
+        self.next_id.fetch_add(1, Relaxed)
+    }
+
+    fn penalty(&self, id: usize, cs: u64, _: u64) -> u64 {
+        let mut state = self.state.lock();
+
+        if state.usage.len() <= id {
+            state.usage.resize(id + 1, 0);
+        }
+
+        if state.usage[id] == 0 {
+            state.seen += 1;
+        }
+        state.usage[id] += cs.max(1);
+        state.served += 1;
+
+        // threads that joined during this window count before it ends
+        let active_threads = state.active_threads.max(state.seen);
+        let penalty = (cs as f64 * active_threads as f64 * state.multiplier) as u64;
+
+        if state.served >= self.config.window {
+            self.end_window(&mut state);
+        }
+
+        penalty
+    }
+}
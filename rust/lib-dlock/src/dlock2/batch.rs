@@ -0,0 +1,149 @@
+//! Combiner-side fusion of the requests collected in one combining pass.
+//!
+//! A lock with a batch delegate does not run the delegate as it walks its
+//! pending requests. It moves them into a batch and hands the whole batch to
+//! the batch delegate once the pass is over, which may merge requests: sum
+//! counter increments, match pushes with pops, fold multiplications. The
+//! requests it leaves unanswered are run with the plain delegate, then the
+//! waiters are released.
+
+use std::{cell::SyncUnsafeCell, fmt::Debug, ptr::NonNull};
+
+pub trait BatchDelegate<T, I, O>: Send + Sync {
+    /// Answer requests of `requests` on `data`. The outputs and the final
+    /// `data` must be those of running the answered requests one after
+    /// another in some order. Requests left unanswered are then run with the
+    /// delegate of the lock, in order.
+    fn delegate_batch(&self, data: &mut T, requests: &mut [BatchRequest<I, O>]);
+}
+
+impl<T, I, O, F> BatchDelegate<T, I, O> for F
+where
+    F: Fn(&mut T, &mut [BatchRequest<I, O>]) + Send + Sync,
+{
+    fn delegate_batch(&self, data: &mut T, requests: &mut [BatchRequest<I, O>]) {
+        self(data, requests)
+    }
+}
+
+/// A request of a batch, answering it consumes its input.
+#[derive(Debug)]
+pub struct BatchRequest<I, O> {
+    input: Option<I>,
+    output: Option<O>,
+}
+
+impl<I, O> BatchRequest<I, O> {
+    /// The input of the request, `None` once it is answered.
+    pub fn input(&self) -> Option<&I> {
+        self.input.as_ref()
+    }
+
+    pub fn is_answered(&self) -> bool {
+        self.input.is_none()
+    }
+
+    /// Answer the request with the output `f` makes of its input. An
+    /// answered request is left alone.
+    pub fn answer(&mut self, f: impl FnOnce(I) -> O) {
+        if let Some(input) = self.input.take() {
+            self.output = Some(f(input));
+        }
+    }
+
+    /// The answer, or the output of `delegate` for an unanswered request.
+    fn output<T>(self, data: &mut T, delegate: &impl Fn(&mut T, I) -> O) -> O {
+        match (self.input, self.output) {
+            (Some(input), _) => delegate(data, input),
+            (None, Some(output)) => output,
+            (None, None) => unreachable!("answered requests hold an output"),
+        }
+    }
+}
+
+/// The batch delegate of a lock and the requests of the current pass, along
+/// with the node every output goes back to. Only the combiner touches the
+/// batch.
+pub(crate) struct Batcher<T, I, O, N> {
+    delegate: Option<Box<dyn BatchDelegate<T, I, O>>>,
+    requests: SyncUnsafeCell<Vec<BatchRequest<I, O>>>,
+    nodes: SyncUnsafeCell<Vec<NonNull<N>>>,
+}
+
+// requests only move between threads the way they do through the nodes of
+// the lock owning the batch
+unsafe impl<T, I, O, N> Send for Batcher<T, I, O, N> {}
+unsafe impl<T, I, O, N> Sync for Batcher<T, I, O, N> {}
+
+impl<T, I, O, N> Batcher<T, I, O, N> {
+    pub fn new() -> Self {
+        Self {
+            delegate: None,
+            requests: Vec::new().into(),
+            nodes: Vec::new().into(),
+        }
+    }
+
+    pub fn set(&mut self, delegate: impl BatchDelegate<T, I, O> + 'static) {
+        self.delegate = Some(Box::new(delegate));
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.delegate.is_some()
+    }
+
+    /// Add the request taken out of `node` to the batch.
+    ///
+    /// # Safety
+    ///
+    /// Only the combiner may call this, and `node` must stay valid until the
+    /// batch is run.
+    pub unsafe fn push(&self, node: &N, request: I) {
+        (*self.requests.get()).push(BatchRequest {
+            input: Some(request),
+            output: None,
+        });
+        (*self.nodes.get()).push(NonNull::from(node));
+    }
+
+    /// Run the batch delegate on the collected requests, and `delegate` on
+    /// those it left unanswered, then hand every output to `complete` along
+    /// with its node. Returns the batch size.
+    ///
+    /// # Safety
+    ///
+    /// Only the combiner may call this.
+    pub unsafe fn run(
+        &self,
+        data: &mut T,
+        delegate: &impl Fn(&mut T, I) -> O,
+        mut complete: impl FnMut(&N, O),
+    ) -> usize {
+        let requests = &mut *self.requests.get();
+        let nodes = &mut *self.nodes.get();
+        let len = requests.len();
+
+        if len == 0 {
+            return 0;
+        }
+
+        self.delegate
+            .as_ref()
+            .unwrap_unchecked()
+            .delegate_batch(data, requests);
+
+        for (node, request) in nodes.drain(..).zip(requests.drain(..)) {
+            complete(node.as_ref(), request.output(data, delegate));
+        }
+
+        len
+    }
+}
+
+impl<T, I, O, N> Debug for Batcher<T, I, O, N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Batcher")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
@@ -1,4 +1,4 @@
 mod lock;
-mod node;
+pub(crate) mod node;
 
-pub type CCSynch<T, I, F> = lock::CCSynch<T, I, F>;
+pub type CCSynch<T, I, O, F> = lock::CCSynch<T, I, O, F>;
@@ -3,36 +3,65 @@ use std::{
     arch::x86_64::__rdtscp,
     cell::SyncUnsafeCell,
     hint::spin_loop,
-    mem::MaybeUninit,
     ptr::{self, NonNull},
     sync::atomic::{AtomicPtr, Ordering::*},
+    time::{Duration, Instant},
 };
 
 use thread_local::ThreadLocal;
 
-use super::node::Node;
-use crate::dlock2::DLock2Delegate;
+use super::node::{Node, AWAY, DONE, HANDOFF, WAITING};
+use crate::dlock2::{
+    batch::{BatchDelegate, Batcher},
+    condition::{Condition, Predicate},
+    nesting,
+    seqlock::SeqLock,
+    slot::Slot,
+    ticket::{Ticket, TicketState, MAX_DETACHED},
+    DLock2Delegate,
+};
 
 #[derive(Debug)]
-struct ThreadData<T> {
-    node: AtomicPtr<Node<T>>,
+struct ThreadData<I, O> {
+    node: AtomicPtr<Node<I, O>>,
     combiner_time_stat: SyncUnsafeCell<u64>,
+    /// Nodes of the thread not holding a request
+    free: SyncUnsafeCell<Vec<*mut Node<I, O>>>,
+    /// Nodes of detached requests, oldest first
+    detached: SyncUnsafeCell<Vec<*mut Node<I, O>>>,
+    /// Nodes of withdrawn requests the combiner has yet to pass
+    withdrawn: SyncUnsafeCell<Vec<*mut Node<I, O>>>,
 }
 
+// the nodes are handed between threads through the queue anyway
+unsafe impl<I, O> Send for ThreadData<I, O> {}
+
+/// Requests left out of the queue until their predicate holds, only touched
+/// by the combiner
+#[derive(Debug)]
+struct Parked<I, O>(SyncUnsafeCell<Vec<NonNull<Node<I, O>>>>);
+
+// the nodes belong to threads waiting for them
+unsafe impl<I, O> Send for Parked<I, O> {}
+unsafe impl<I, O> Sync for Parked<I, O> {}
+
 #[derive(Debug)]
-pub struct CCSynch<T, I, F, const H: u32 = 64>
+pub struct CCSynch<T, I, O, F, const H: u32 = 64>
 where
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
 {
     delegate: F,
     data: SyncUnsafeCell<T>,
-    tail: AtomicPtr<Node<I>>,
-    local_node: ThreadLocal<ThreadData<I>>,
+    tail: AtomicPtr<Node<I, O>>,
+    local_node: ThreadLocal<ThreadData<I, O>>,
+    batch: Batcher<T, I, O, Node<I, O>>,
+    seqlock: SeqLock,
+    parked: Parked<I, O>,
 }
 
-impl<T, I, F> CCSynch<T, I, F>
+impl<T, I, O, F> CCSynch<T, I, O, F>
 where
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
 {
     pub fn new(data: T, delegate: F) -> Self {
         Self {
@@ -40,94 +69,430 @@ where
             data: SyncUnsafeCell::new(data),
             tail: AtomicPtr::new(Box::leak(Box::new(Node::default()))),
             local_node: ThreadLocal::new(),
+            batch: Batcher::new(),
+            seqlock: SeqLock::new(),
+            parked: Parked(Vec::new().into()),
         }
     }
+
+    /// Hand all requests of a combining pass to `batch` at once instead of
+    /// running the delegate on every request.
+    pub fn with_batch_delegate(mut self, batch: impl BatchDelegate<T, I, O> + 'static) -> Self {
+        self.batch.set(batch);
+        self
+    }
+
+    pub fn is_batched(&self) -> bool {
+        self.batch.is_enabled()
+    }
 }
 
-unsafe impl<T, I, F, const H: u32> DLock2<I> for CCSynch<T, I, F, H>
+impl<T, I, O, F, const H: u32> CCSynch<T, I, O, F, H>
 where
-    T: Send + Sync,
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
 {
-    fn lock(&self, data: I) -> I {
-        let thread_data = self.local_node.get_or(|| ThreadData {
+    /// Run `reader` on the data on the calling thread, concurrently with
+    /// other readers and without joining the queue. Returns `None` if every
+    /// attempt overlapped with a combining pass, the read then has to be
+    /// delegated.
+    ///
+    /// # Safety
+    ///
+    /// `reader` may see the data in the middle of a combining pass, it must
+    /// neither crash nor hang on such a state.
+    pub unsafe fn try_read<R>(&self, reader: impl Fn(&T) -> R) -> Option<R> {
+        self.seqlock.read(|| reader(&*self.data.get()))
+    }
+
+    /// Run `data` once `predicate` holds on the data. A combiner that finds
+    /// the predicate false takes the request out of the queue, it is served
+    /// by the first combining pass after which the predicate holds.
+    pub fn lock_when(&self, data: I, predicate: impl Fn(&T) -> bool + Sync) -> O {
+        nesting::enter(self);
+
+        let predicate: Predicate<'_, T> = &predicate;
+        let thread_data = self.thread_data();
+
+        unsafe {
+            let current_node = self.enqueue(
+                thread_data,
+                data,
+                Some(Condition::new(&predicate)),
+                false,
+                ptr::null_mut(),
+            );
+            self.wait_node(thread_data, current_node)
+        }
+    }
+
+    fn thread_data(&self) -> &ThreadData<I, O> {
+        self.local_node.get_or(|| ThreadData {
             node: AtomicPtr::new(Box::leak(Box::new(Node::default()))),
             combiner_time_stat: 0.into(),
-        });
-        let mut aux = 0;
+            free: Vec::new().into(),
+            detached: Vec::new().into(),
+            withdrawn: Vec::new().into(),
+        })
+    }
+
+    /// Put `data` into the queue, returns the node holding it. The node the
+    /// thread brings along for the next request is replaced by `spare`.
+    unsafe fn enqueue(
+        &self,
+        thread_data: &ThreadData<I, O>,
+        data: I,
+        condition: Option<Condition>,
+        timed: bool,
+        spare: *mut Node<I, O>,
+    ) -> &Node<I, O> {
         // use thread local node as next node
-        let next_node = unsafe { &mut *thread_data.node.load(Acquire) };
+        let next_node = &mut *thread_data.node.load(Acquire);
 
-        next_node.next.store(std::ptr::null_mut(), Release);
-        next_node.wait.store(true, Release);
-        next_node.completed.store(false, Release);
+        next_node.next.store(ptr::null_mut(), Release);
+        next_node.state.store(WAITING, Release);
 
         let current_ptr = self.tail.swap(next_node, AcqRel);
-        let current_node = unsafe { current_ptr.as_ref().unwrap_unchecked() };
+        let current_node = current_ptr.as_ref().unwrap_unchecked();
+
+        current_node.data.get().write(Slot::input(data));
+        current_node.condition.get().write(condition);
+        current_node.claim.reset(timed);
+        current_node.next.store(next_node, Release);
+        thread_data
+            .node
+            .store(if spare.is_null() { current_ptr } else { spare }, Relaxed);
+
+        current_node
+    }
+
+    /// Wait until the request in `node` is served or the thread becomes the
+    /// combiner, returns the output.
+    unsafe fn wait_node(&self, thread_data: &ThreadData<I, O>, node: &Node<I, O>) -> O {
+        loop {
+            // wait for the current node to be waked
+            while node.state.load(Acquire) == WAITING {
+                spin_loop()
+            }
+
+            // check whether the current node is completed, a parked request
+            // is waited for again
+            if node.state.load(Acquire) == DONE {
+                break;
+            }
+
+            self.combine(thread_data, node);
+        }
+
+        (*node.data.get()).take_output()
+    }
+
+    fn serve(&self, node: &Node<I, O>) {
+        if !node.claim.serve() {
+            // withdrawn, the owner only waits for the node to be passed
+            node.state.store(DONE, Release);
+            return;
+        }
 
         unsafe {
-            current_node.data.get().write(MaybeUninit::new(data));
-            current_node.next.store(next_node, Release);
-            self.local_node
-                .get()
-                .unwrap_unchecked()
-                .node
-                .store(current_ptr, Relaxed)
+            let data = self.data.get().as_mut().unwrap_unchecked();
+
+            match *node.condition.get() {
+                Some(condition) if !condition.holds(&*data) => {
+                    // the owner may be the combiner itself
+                    node.state.store(WAITING, Relaxed);
+                    (*self.parked.0.get()).push(NonNull::from(node));
+                }
+                // the predicate only holds on the data as it is now
+                Some(_) => self.run(node, data),
+                None if self.batch.is_enabled() => {
+                    self.batch.push(node, (*node.data.get()).take_input())
+                }
+                None => self.run(node, data),
+            }
         }
+    }
+
+    unsafe fn run(&self, node: &Node<I, O>, data: &mut T) {
+        node.data.get().write(Slot::output((self.delegate)(
+            data,
+            (*node.data.get()).take_input(),
+        )));
+
+        node.state.store(DONE, Release);
+    }
+
+    /// Serve the parked requests whose predicate holds by now, until a
+    /// round serves none.
+    fn serve_parked(&self) {
+        unsafe {
+            let parked = &mut *self.parked.0.get();
+            let data = self.data.get().as_mut().unwrap_unchecked();
+
+            let mut served = true;
+            while served {
+                let len = parked.len();
+
+                parked.retain(|node| {
+                    let node = node.as_ref();
+
+                    if !(*node.condition.get()).unwrap_unchecked().holds(&*data) {
+                        return true;
+                    }
 
-        // wait for the current node to be waked
-        while current_node.wait.load(Acquire) {
-            spin_loop()
+                    self.run(node, data);
+                    false
+                });
+
+                served = parked.len() < len;
+            }
         }
+    }
 
-        // check whether the current node is completed
-        if current_node.completed.load(Acquire) {
-            return unsafe { current_node.data.get().read().assume_init() };
+    fn run_batch(&self) {
+        unsafe {
+            self.batch.run(
+                self.data.get().as_mut().unwrap_unchecked(),
+                &self.delegate,
+                |node, output| {
+                    node.data.get().write(Slot::output(output));
+                    node.state.store(DONE, Release);
+                },
+            );
         }
+    }
+
+    fn combine(&self, thread_data: &ThreadData<I, O>, current_node: &Node<I, O>) {
+        #[cfg(feature = "combiner_stat")]
+        let begin = unsafe { __rdtscp(&mut 0) };
 
-        // combiner
+        nesting::combining(self, || {
+            self.seqlock.write(|| self.combine_pass(current_node))
+        });
 
         #[cfg(feature = "combiner_stat")]
-        let begin = unsafe { __rdtscp(&mut aux) };
+        unsafe {
+            let end = __rdtscp(&mut 0);
 
+            *thread_data.combiner_time_stat.get() += end - begin;
+        }
+    }
+
+    fn combine_pass(&self, current_node: &Node<I, O>) {
         let mut tmp_node = current_node;
 
         let mut counter: u32 = 0;
 
-        let mut next_ptr = NonNull::new(tmp_node.next.load(Acquire));
+        loop {
+            let next_ptr = NonNull::new(tmp_node.next.load(Acquire));
+
+            if let Some(next_nonnull) = next_ptr {
+                if counter < H {
+                    counter += 1;
+                    self.serve(tmp_node);
+                    tmp_node = unsafe { next_nonnull.as_ref() };
+                    continue;
+                }
+            }
 
-        while let Some(next_nonnull) = next_ptr {
-            if counter >= H {
+            // the batch has to be done before the next combiner takes over
+            self.run_batch();
+            self.serve_parked();
+
+            if tmp_node
+                .state
+                .compare_exchange(WAITING, HANDOFF, AcqRel, Acquire)
+                .is_ok()
+            {
                 break;
             }
 
-            counter += 1;
-            let next_node = unsafe { next_nonnull.as_ref() };
+            // the owner left its request behind, which is linked by now. The
+            // owner may reuse the node as soon as it is served.
+            let next_node = tmp_node.next.load(Acquire);
+            self.serve(tmp_node);
+            tmp_node = unsafe { &*next_node };
+        }
+    }
+
+    /// Give back the nodes of detached requests that were served, waiting
+    /// for the oldest one if too many are in flight.
+    unsafe fn reclaim(&self, thread_data: &ThreadData<I, O>) {
+        let detached = &mut *thread_data.detached.get();
+        let free = &mut *thread_data.free.get();
+
+        if detached.len() >= MAX_DETACHED {
+            let node = &*detached.remove(0);
+            node.state
+                .compare_exchange(AWAY, WAITING, AcqRel, Acquire)
+                .ok();
+            drop(self.wait_node(thread_data, node));
+            free.push(node as *const Node<I, O> as *mut Node<I, O>);
+        }
+
+        detached.retain(|&node| {
+            if (*node).state.load(Acquire) != DONE {
+                return true;
+            }
+
+            drop((*(*node).data.get()).take_output());
+            free.push(node);
+            false
+        });
+    }
+
+    /// Keep the request of a dropped ticket in flight as a detached one.
+    unsafe fn detach(lock: *const (), node: NonNull<u8>) {
+        let lock = &*lock.cast::<Self>();
 
-            unsafe {
-                tmp_node.data.get().write(MaybeUninit::new((self.delegate)(
-                    self.data.get().as_mut().unwrap_unchecked(),
-                    tmp_node.data.get().read().assume_init(),
-                )));
+        (*lock.thread_data().detached.get()).push(node.cast().as_ptr());
+    }
+
+    /// Give back the nodes of withdrawn requests the combiner passed.
+    unsafe fn recycle_withdrawn(&self, thread_data: &ThreadData<I, O>) {
+        let free = &mut *thread_data.free.get();
 
-                tmp_node.completed.store(true, Release);
-                tmp_node.wait.store(false, Release);
+        (*thread_data.withdrawn.get()).retain(|&node| {
+            if (*node).state.load(Acquire) != DONE {
+                return true;
             }
 
-            tmp_node = next_node;
-            next_ptr = NonNull::new(tmp_node.next.load(Acquire));
+            free.push(node);
+            false
+        });
+    }
+}
+
+impl<T, I, O, F, const H: u32> Drop for CCSynch<T, I, O, F, H>
+where
+    F: DLock2Delegate<T, I, O>,
+{
+    fn drop(&mut self) {
+        nesting::forget(self);
+    }
+}
+
+unsafe impl<T, I, O, F, const H: u32> DLock2<I, O> for CCSynch<T, I, O, F, H>
+where
+    T: Send + Sync,
+    F: DLock2Delegate<T, I, O>,
+{
+    fn lock(&self, data: I) -> O {
+        nesting::enter(self);
+
+        let thread_data = self.thread_data();
+
+        unsafe {
+            let current_node = self.enqueue(thread_data, data, None, false, ptr::null_mut());
+            self.wait_node(thread_data, current_node)
         }
+    }
 
-        tmp_node.wait.store(false, Release);
+    /// The request stays in a node of the queue. A combiner serves it instead
+    /// of handing the combiner role to the absent thread, and a thread that
+    /// gets the role before it leaves combines right away.
+    fn submit(&self, data: I) -> Ticket<'_, O> {
+        nesting::enter(self);
+
+        let thread_data = self.thread_data();
 
-        #[cfg(feature = "combiner_stat")]
         unsafe {
-            let end = __rdtscp(&mut aux);
+            self.reclaim(thread_data);
 
-            *thread_data.combiner_time_stat.get() += end - begin;
+            let spare = (*thread_data.free.get())
+                .pop()
+                .unwrap_or_else(|| Box::leak(Box::new(Node::default())));
+            let current_node = self.enqueue(thread_data, data, None, false, spare);
+
+            if current_node
+                .state
+                .compare_exchange(WAITING, AWAY, AcqRel, Acquire)
+                == Err(HANDOFF)
+            {
+                self.combine(thread_data, current_node);
+            }
+
+            Ticket::pending(self, current_node, Self::detach)
+        }
+    }
+
+    fn wait(&self, ticket: Ticket<'_, O>) -> O {
+        let node = match ticket.redeem(self) {
+            TicketState::Ready(output) => return output,
+            TicketState::Pending(node) => unsafe { node.cast::<Node<I, O>>().as_ref() },
+        };
+        let thread_data = self.thread_data();
+
+        unsafe {
+            // back for the output, or for the combiner role
+            let _ = node.state.compare_exchange(AWAY, WAITING, AcqRel, Acquire);
+            let output = self.wait_node(thread_data, node);
+
+            (*thread_data.free.get()).push(node as *const Node<I, O> as *mut Node<I, O>);
+
+            output
         }
+    }
+
+    fn lock_detached(&self, data: I) {
+        drop(self.submit(data));
+    }
 
-        return unsafe { current_node.data.get().read().assume_init() };
+    fn supports_timeout(&self) -> bool {
+        true
+    }
+
+    /// The request goes into the queue like one of `lock`. A withdrawn
+    /// request is left behind in its node, which the combiner passes over
+    /// instead of handing the combiner role to the absent thread.
+    fn lock_timeout(&self, data: I, timeout: Duration) -> Result<O, I> {
+        nesting::enter(self);
+
+        let deadline = Instant::now() + timeout;
+        let thread_data = self.thread_data();
+
+        unsafe {
+            self.recycle_withdrawn(thread_data);
+
+            let spare = (*thread_data.free.get())
+                .pop()
+                .unwrap_or_else(|| Box::leak(Box::new(Node::default())));
+            let current_node = self.enqueue(thread_data, data, None, true, spare);
+            let current_ptr = current_node as *const Node<I, O> as *mut Node<I, O>;
+
+            loop {
+                match current_node.state.load(Acquire) {
+                    DONE => {
+                        let output = (*current_node.data.get()).take_output();
+                        (*thread_data.free.get()).push(current_ptr);
+                        return Ok(output);
+                    }
+                    HANDOFF => self.combine(thread_data, current_node),
+                    _ if Instant::now() >= deadline => break,
+                    _ => spin_loop(),
+                }
+            }
+
+            if !current_node.claim.withdraw() {
+                // a combiner took the request up before the deadline
+                let output = self.wait_node(thread_data, current_node);
+                (*thread_data.free.get()).push(current_ptr);
+                return Ok(output);
+            }
+
+            let data = (*current_node.data.get()).take_input();
+
+            if current_node
+                .state
+                .compare_exchange(WAITING, AWAY, AcqRel, Acquire)
+                == Err(HANDOFF)
+            {
+                self.combine(thread_data, current_node);
+            }
+
+            (*thread_data.withdrawn.get()).push(current_ptr);
+
+            Err(data)
+        }
     }
 
     #[cfg(feature = "combiner_stat")]
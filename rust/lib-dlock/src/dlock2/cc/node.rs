@@ -1,27 +1,40 @@
 use std::{
     cell::SyncUnsafeCell,
-    mem::MaybeUninit,
-    sync::atomic::{AtomicBool, AtomicPtr},
+    sync::atomic::{AtomicPtr, AtomicU8},
 };
 
-pub struct Node<T> {
+use crate::dlock2::{claim::Claim, condition::Condition, slot::Slot};
+
+/// The owner waits for its request to be served or for the combiner role
+pub const WAITING: u8 = 0;
+/// The owner left its request in the queue, the combiner serves it instead of
+/// handing the combiner role over
+pub const AWAY: u8 = 1;
+/// The owner takes over as the combiner
+pub const HANDOFF: u8 = 2;
+/// The request was served, its output is in the node
+pub const DONE: u8 = 3;
+
+pub struct Node<I, O> {
     pub age: SyncUnsafeCell<u32>,
-    pub data: SyncUnsafeCell<MaybeUninit<T>>,
-    pub completed: AtomicBool,
-    pub wait: AtomicBool,
-    pub next: AtomicPtr<Node<T>>,
+    pub data: SyncUnsafeCell<Slot<I, O>>,
+    pub state: AtomicU8,
+    pub next: AtomicPtr<Node<I, O>>,
+    pub condition: SyncUnsafeCell<Option<Condition>>,
+    pub claim: Claim,
     #[cfg(feature = "combiner_stat")]
     pub combiner_time_stat: u64,
 }
 
-impl<T> Default for Node<T> {
+impl<I, O> Default for Node<I, O> {
     fn default() -> Self {
         Node {
             age: SyncUnsafeCell::new(0),
-            data: SyncUnsafeCell::new(MaybeUninit::uninit()),
-            completed: AtomicBool::new(false),
-            wait: AtomicBool::new(false),
+            data: SyncUnsafeCell::new(Slot::empty()),
+            state: AtomicU8::new(HANDOFF),
             next: AtomicPtr::new(std::ptr::null_mut()),
+            condition: SyncUnsafeCell::new(None),
+            claim: Claim::new(),
             #[cfg(feature = "combiner_stat")]
             combiner_time_stat: 0,
         }
@@ -1,4 +1,6 @@
+use crate::dlock2::ban_policy::FixedBan;
+
 mod lock;
 mod node;
 
-pub type CCBan<T, I, F> = lock::CCBan<T, I, F>;
+pub type CCBan<T, I, O, F, P = FixedBan> = lock::CCBan<T, I, O, F, P>;
@@ -2,52 +2,93 @@ use crate::dlock2::DLock2;
 use std::{
     arch::x86_64::__rdtscp,
     cell::SyncUnsafeCell,
-    mem::MaybeUninit,
+    hint::spin_loop,
     ops::AddAssign,
     ptr::{self, NonNull},
     sync::atomic::{AtomicPtr, AtomicU64, Ordering::*},
+    time::{Duration, Instant},
 };
 
 use crossbeam::utils::Backoff;
 use thread_local::ThreadLocal;
 
 use super::node::Node;
-use crate::dlock2::DLock2Delegate;
+use crate::dlock2::{
+    ban_policy::{credit_ban, BanPolicy, FixedBan},
+    cc::node::{AWAY, DONE, HANDOFF, WAITING},
+    nesting,
+    slot::Slot,
+    DLock2Delegate,
+};
 
 #[derive(Debug)]
-pub struct ThreadData<T> {
-    pub(crate) node: AtomicPtr<Node<T>>,
+pub struct ThreadData<I, O> {
+    pub(crate) id: usize,
+    pub(crate) node: AtomicPtr<Node<I, O>>,
     pub(crate) banned_until: SyncUnsafeCell<u64>,
     pub combiner_time_stat: SyncUnsafeCell<u64>,
+    /// Nodes of the thread not holding a request
+    free: SyncUnsafeCell<Vec<*mut Node<I, O>>>,
+    /// Nodes of withdrawn requests the combiner has yet to pass
+    withdrawn: SyncUnsafeCell<Vec<*mut Node<I, O>>>,
 }
 
+// the nodes are handed between threads through the queue anyway
+unsafe impl<I, O> Send for ThreadData<I, O> {}
+
 #[derive(Debug)]
-pub struct CCBan<T, I, F>
+pub struct CCBan<T, I, O, F, P = FixedBan>
 where
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
+    P: BanPolicy,
 {
     delegate: F,
     data: SyncUnsafeCell<T>,
-    tail: AtomicPtr<Node<I>>,
+    tail: AtomicPtr<Node<I, O>>,
     num_waiting_threads: AtomicU64,
-    local_node: ThreadLocal<ThreadData<I>>,
+    ban_policy: P,
+    combiner_credit: f64,
+    local_node: ThreadLocal<ThreadData<I, O>>,
 }
 
-impl<T, I, F> CCBan<T, I, F>
+impl<T, I, O, F> CCBan<T, I, O, F, FixedBan>
 where
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
 {
     pub fn new(data: T, delegate: F) -> Self {
+        Self::with_ban_policy(data, delegate, FixedBan)
+    }
+}
+
+impl<T, I, O, F, P> CCBan<T, I, O, F, P>
+where
+    F: DLock2Delegate<T, I, O>,
+    P: BanPolicy,
+{
+    pub fn with_ban_policy(data: T, delegate: F, ban_policy: P) -> Self {
         Self {
             delegate,
             data: SyncUnsafeCell::new(data),
             tail: AtomicPtr::new(Box::leak(Box::new(Node::default()))),
             local_node: ThreadLocal::new(),
             num_waiting_threads: AtomicU64::new(0),
+            ban_policy,
+            combiner_credit: 0.0,
         }
     }
 
-    fn ban(&self, data: &ThreadData<I>, panelty: u64) {
+    pub fn ban_policy(&self) -> &P {
+        &self.ban_policy
+    }
+
+    /// Shorten the ban of a combiner by the given fraction of the time it
+    /// spent serving other threads.
+    pub fn with_combiner_credit(mut self, fraction: f64) -> Self {
+        self.combiner_credit = fraction;
+        self
+    }
+
+    fn ban(&self, data: &ThreadData<I, O>, panelty: u64) {
         unsafe {
             data.banned_until
                 .get()
@@ -56,17 +97,9 @@ where
                 .add_assign(panelty);
         }
     }
-}
-
-const H: u32 = 16;
 
-unsafe impl<T, I, F> DLock2<I> for CCBan<T, I, F>
-where
-    T: Send + Sync,
-    F: DLock2Delegate<T, I>,
-{
-    fn lock(&self, data: I) -> I {
-        let thread_data = self.local_node.get_or(|| {
+    fn thread_data(&self) -> &ThreadData<I, O> {
+        self.local_node.get_or(|| {
             self.num_waiting_threads.fetch_add(1, Relaxed);
 
             let current_tsc = unsafe {
@@ -75,12 +108,19 @@ where
             };
 
             ThreadData {
+                id: self.ban_policy.register(),
                 node: AtomicPtr::new(Box::leak(Box::new(Node::default()))),
                 banned_until: current_tsc.into(),
                 combiner_time_stat: 0.into(),
+                free: Vec::new().into(),
+                withdrawn: Vec::new().into(),
             }
-        });
+        })
+    }
 
+    /// Spin until the ban of the thread ends or `deadline` passes. Returns
+    /// whether the ban ended.
+    fn wait_ban(&self, thread_data: &ThreadData<I, O>, deadline: Option<Instant>) -> bool {
         let mut aux = 0;
 
         unsafe {
@@ -91,109 +131,263 @@ where
                 let current = __rdtscp(&mut aux);
 
                 if current >= banned_until {
-                    break;
+                    return true;
+                }
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return false;
                 }
                 backoff.snooze();
             }
         }
+    }
 
-        let mut aux = 0;
+    /// Put `data` into the queue, returns the node holding it. The node the
+    /// thread brings along for the next request is replaced by `spare`.
+    unsafe fn enqueue(
+        &self,
+        thread_data: &ThreadData<I, O>,
+        data: I,
+        timed: bool,
+        spare: *mut Node<I, O>,
+    ) -> &Node<I, O> {
         // use thread local node as next node
-        let next_node = unsafe { &mut *thread_data.node.load(Acquire) };
+        let next_node = &mut *thread_data.node.load(Acquire);
 
-        next_node.next.store(std::ptr::null_mut(), Release);
-        next_node.wait.store(true, Release);
-        next_node.completed.store(false, Release);
+        next_node.next.store(ptr::null_mut(), Release);
+        next_node.state.store(WAITING, Release);
 
         let current_ptr = self.tail.swap(next_node, AcqRel);
-        let current_node = unsafe { current_ptr.as_ref().unwrap_unchecked() };
+        let current_node = current_ptr.as_ref().unwrap_unchecked();
 
-        unsafe {
-            current_node.data.get().write(MaybeUninit::new(data));
-            current_node.next.store(next_node, Release);
-            self.local_node
-                .get()
-                .unwrap_unchecked()
-                .node
-                .store(current_ptr, Relaxed)
-        }
+        current_node.data.get().write(Slot::input(data));
+        current_node.owner.get().write(thread_data.id);
+        current_node.claim.reset(timed);
+        current_node.next.store(next_node, Release);
+        thread_data
+            .node
+            .store(if spare.is_null() { current_ptr } else { spare }, Relaxed);
+
+        current_node
+    }
 
+    /// Wait until the request in `node` is served or the thread becomes the
+    /// combiner, returns the output.
+    unsafe fn wait_node(&self, thread_data: &ThreadData<I, O>, node: &Node<I, O>) -> O {
         // wait for the current node to be waked
-        while current_node.wait.load(Acquire) {
-            // spin
+        while node.state.load(Acquire) == WAITING {
+            spin_loop()
         }
 
         // check whether the current node is completed
-        if current_node.completed.load(Acquire) {
-            unsafe {
-                self.ban(thread_data, current_node.panelty.get().read());
-            }
-            return unsafe { current_node.data.get().read().assume_init() };
+        if node.state.load(Acquire) == DONE {
+            self.ban(thread_data, node.panelty.get().read());
+        } else {
+            self.combine(thread_data, node);
         }
 
-        // combiner
+        (*node.data.get()).take_output()
+    }
+
+    /// Serve the request in `node`, returns its critical-section length.
+    /// The critical section is taken to start at `work_begin`, which is
+    /// moved to its end.
+    unsafe fn serve(&self, node: &Node<I, O>, work_begin: &mut u64) -> u64 {
+        if !node.claim.serve() {
+            // withdrawn, the owner only waits for the node to be passed
+            node.panelty.get().write(0);
+            node.state.store(DONE, Release);
+            return 0;
+        }
+
+        let mut aux = 0;
+
+        node.data.get().write(Slot::output((self.delegate)(
+            self.data.get().as_mut().unwrap_unchecked(),
+            (*node.data.get()).take_input(),
+        )));
+        let work_end = __rdtscp(&mut aux);
+
+        let cs = work_end - *work_begin;
+        node.panelty.get().write(self.ban_policy.penalty(
+            node.owner.get().read(),
+            cs,
+            self.num_waiting_threads.load(Relaxed),
+        ));
+        node.state.store(DONE, Release);
+
+        *work_begin = work_end;
+
+        cs
+    }
+
+    fn combine(&self, thread_data: &ThreadData<I, O>, current_node: &Node<I, O>) {
+        let _combining = nesting::combining_guard(self);
+        let mut aux = 0;
 
-        #[cfg(feature = "combiner_stat")]
         let begin = unsafe { __rdtscp(&mut aux) };
+        let mut own_cs = 0;
 
         let mut tmp_node = current_node;
 
         let mut counter: u32 = 0;
 
-        let mut next_ptr = NonNull::new(tmp_node.next.load(Acquire));
+        let mut work_begin = begin;
 
-        let mut work_begin: u64;
-        unsafe {
-            work_begin = __rdtscp(&mut aux);
-        }
+        loop {
+            let next_ptr = NonNull::new(tmp_node.next.load(Acquire));
+
+            if let Some(next_nonnull) = next_ptr {
+                if counter < H {
+                    counter += 1;
+
+                    let cs = unsafe { self.serve(tmp_node, &mut work_begin) };
+                    if ptr::eq(tmp_node, current_node) {
+                        own_cs = cs;
+                    }
+
+                    tmp_node = unsafe { next_nonnull.as_ref() };
+                    continue;
+                }
+            }
 
-        while let Some(next_nonnull) = next_ptr {
-            if counter >= H {
+            if tmp_node
+                .state
+                .compare_exchange(WAITING, HANDOFF, AcqRel, Acquire)
+                .is_ok()
+            {
                 break;
             }
 
-            counter += 1;
-            let next_node = unsafe { next_nonnull.as_ref() };
+            // the owner withdrew its request, which is linked by now. The
+            // owner may reuse the node as soon as it is passed.
+            let next_node = tmp_node.next.load(Acquire);
+            unsafe { self.serve(tmp_node, &mut work_begin) };
+            tmp_node = unsafe { &*next_node };
+        }
 
+        unsafe {
+            self.ban(thread_data, current_node.panelty.get().read());
+        }
+
+        let end = unsafe { __rdtscp(&mut aux) };
+
+        if self.combiner_credit > 0.0 {
+            let others = (end - begin).saturating_sub(own_cs);
             unsafe {
-                tmp_node.data.get().write(MaybeUninit::new(
-                    (self.delegate)(
-                        self.data.get().as_mut().unwrap_unchecked(),
-                        tmp_node.data.get().read().assume_init(),
-                    ),
-                ));
-                let work_end = __rdtscp(&mut aux);
-
-                tmp_node.completed.store(true, Release);
-                tmp_node.wait.store(false, Release);
-
-                let cs = work_end - work_begin;
-                tmp_node
-                    .panelty
-                    .get()
-                    .write(cs * (self.num_waiting_threads.load(Relaxed)));
-
-                work_begin = work_end;
+                credit_ban(
+                    &mut *thread_data.banned_until.get(),
+                    (others as f64 * self.combiner_credit) as u64,
+                    end,
+                );
             }
+        }
 
-            tmp_node = next_node;
-            next_ptr = NonNull::new(tmp_node.next.load(Acquire));
+        #[cfg(feature = "combiner_stat")]
+        unsafe {
+            (*thread_data.combiner_time_stat.get()) += end - begin;
         }
+    }
+
+    /// Give back the nodes of withdrawn requests the combiner passed.
+    unsafe fn recycle_withdrawn(&self, thread_data: &ThreadData<I, O>) {
+        let free = &mut *thread_data.free.get();
+
+        (*thread_data.withdrawn.get()).retain(|&node| {
+            if (*node).state.load(Acquire) != DONE {
+                return true;
+            }
+
+            free.push(node);
+            false
+        });
+    }
+}
+
+const H: u32 = 16;
+
+impl<T, I, O, F, P> Drop for CCBan<T, I, O, F, P>
+where
+    F: DLock2Delegate<T, I, O>,
+    P: BanPolicy,
+{
+    fn drop(&mut self) {
+        nesting::forget(self);
+    }
+}
 
-        tmp_node.wait.store(false, Release);
+unsafe impl<T, I, O, F, P> DLock2<I, O> for CCBan<T, I, O, F, P>
+where
+    T: Send + Sync,
+    F: DLock2Delegate<T, I, O>,
+    P: BanPolicy,
+{
+    fn lock(&self, data: I) -> O {
+        nesting::enter(self);
+
+        let thread_data = self.thread_data();
+
+        self.wait_ban(thread_data, None);
 
         unsafe {
-            self.ban(thread_data, current_node.panelty.get().read());
+            let current_node = self.enqueue(thread_data, data, false, ptr::null_mut());
+            self.wait_node(thread_data, current_node)
         }
+    }
 
-        #[cfg(feature = "combiner_stat")]
-        unsafe {
-            let end = __rdtscp(&mut aux);
+    fn supports_timeout(&self) -> bool {
+        true
+    }
 
-            (*thread_data.combiner_time_stat.get()) += end - begin;
+    /// A thread banned beyond the deadline gives up without publishing the
+    /// request. Otherwise the request goes into the queue like one of
+    /// `lock`, and a withdrawn request is left behind in its node, which the
+    /// combiner passes over instead of handing the combiner role to the
+    /// absent thread.
+    fn lock_timeout(&self, data: I, timeout: Duration) -> Result<O, I> {
+        nesting::enter(self);
+
+        let deadline = Instant::now() + timeout;
+        let thread_data = self.thread_data();
+
+        if !self.wait_ban(thread_data, Some(deadline)) {
+            return Err(data);
         }
 
-        return unsafe { current_node.data.get().read().assume_init() };
+        unsafe {
+            self.recycle_withdrawn(thread_data);
+
+            let spare = (*thread_data.free.get())
+                .pop()
+                .unwrap_or_else(|| Box::leak(Box::new(Node::default())));
+            let current_node = self.enqueue(thread_data, data, true, spare);
+            let current_ptr = current_node as *const Node<I, O> as *mut Node<I, O>;
+
+            while current_node.state.load(Acquire) == WAITING && Instant::now() < deadline {
+                spin_loop()
+            }
+
+            if current_node.state.load(Acquire) != WAITING || !current_node.claim.withdraw() {
+                // served, combining, or taken up by a combiner before the
+                // deadline
+                let output = self.wait_node(thread_data, current_node);
+                (*thread_data.free.get()).push(current_ptr);
+                return Ok(output);
+            }
+
+            let data = (*current_node.data.get()).take_input();
+
+            if current_node
+                .state
+                .compare_exchange(WAITING, AWAY, AcqRel, Acquire)
+                == Err(HANDOFF)
+            {
+                self.combine(thread_data, current_node);
+            }
+
+            (*thread_data.withdrawn.get()).push(current_ptr);
+
+            Err(data)
+        }
     }
 
     #[cfg(feature = "combiner_stat")]
@@ -1,30 +1,34 @@
 use std::{
     cell::SyncUnsafeCell,
-    mem::MaybeUninit,
-    sync::atomic::{AtomicBool, AtomicPtr},
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU8},
 };
 
-pub struct Node<T> {
+use crate::dlock2::{cc::node::HANDOFF, claim::Claim, slot::Slot};
+
+pub struct Node<I, O> {
     pub age: SyncUnsafeCell<u32>,
     pub active: AtomicBool,
-    pub data: SyncUnsafeCell<MaybeUninit<T>>,
-    pub completed: AtomicBool,
-    pub wait: AtomicBool,
+    pub data: SyncUnsafeCell<Slot<I, O>>,
+    /// One of the states of `cc::node`
+    pub state: AtomicU8,
+    pub claim: Claim,
     pub panelty: SyncUnsafeCell<u64>,
-    pub next: AtomicPtr<Node<T>>,
+    pub owner: SyncUnsafeCell<usize>,
+    pub next: AtomicPtr<Node<I, O>>,
     #[cfg(feature = "combiner_stat")]
     pub combiner_time_stat: u64,
 }
 
-impl<T> Default for Node<T> {
+impl<I, O> Default for Node<I, O> {
     fn default() -> Self {
         Node {
             age: SyncUnsafeCell::new(0),
             active: AtomicBool::new(false),
-            data: SyncUnsafeCell::new(MaybeUninit::uninit()),
-            completed: AtomicBool::new(false),
-            wait: AtomicBool::new(false),
+            data: SyncUnsafeCell::new(Slot::empty()),
+            state: AtomicU8::new(HANDOFF),
+            claim: Claim::new(),
             panelty: SyncUnsafeCell::new(0),
+            owner: SyncUnsafeCell::new(0),
             next: AtomicPtr::default(),
             #[cfg(feature = "combiner_stat")]
             combiner_time_stat: 0,
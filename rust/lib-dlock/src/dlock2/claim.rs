@@ -0,0 +1,59 @@
+//! Requests whose owner may give up on them.
+//!
+//! A timed request stays open while it waits in the lock. The combiner
+//! claims it right before serving it, the owner withdraws it once its
+//! deadline passes. Whichever comes first decides: a claimed request is
+//! served and its owner waits for the output, a withdrawn request is passed
+//! over and its input goes back to the owner. Untimed requests are served
+//! without a claim.
+
+use std::sync::atomic::{AtomicU8, Ordering::*};
+
+/// The owner waits for the request however long it takes
+const UNTIMED: u8 = 0;
+/// Neither served nor given up yet
+const OPEN: u8 = 1;
+/// A combiner is serving the request
+const CLAIMED: u8 = 2;
+/// The owner gave up on the request
+const WITHDRAWN: u8 = 3;
+
+#[derive(Debug, Default)]
+pub(crate) struct Claim(AtomicU8);
+
+impl Claim {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Arm the claim for the next request of the node, before the request
+    /// is published to the combiner.
+    pub fn reset(&self, timed: bool) {
+        self.0.store(if timed { OPEN } else { UNTIMED }, Release);
+    }
+
+    /// Whether the combiner may serve the request. Only the combiner may call
+    /// this, and only right before serving it.
+    pub fn serve(&self) -> bool {
+        match self.0.load(Acquire) {
+            UNTIMED => true,
+            _ => self
+                .0
+                .compare_exchange(OPEN, CLAIMED, AcqRel, Acquire)
+                .is_ok(),
+        }
+    }
+
+    /// Whether the owner gave up on the request.
+    pub fn is_withdrawn(&self) -> bool {
+        self.0.load(Acquire) == WITHDRAWN
+    }
+
+    /// Give up on a timed request. Returns `false` if a combiner claimed it
+    /// first, the owner then has to wait for its output.
+    pub fn withdraw(&self) -> bool {
+        self.0
+            .compare_exchange(OPEN, WITHDRAWN, AcqRel, Acquire)
+            .is_ok()
+    }
+}
@@ -0,0 +1,15 @@
+//! Lock cohorting (Dice et al., PPoPP 2012) as a NUMA-aware baseline.
+//!
+//! A global lock is paired with one local lock per cluster of the
+//! [`Topology`](crate::topology::Topology). C-BO-MCS uses a backoff lock
+//! globally and MCS locks per cluster, C-TKT-TKT uses ticket locks for both.
+
+mod lock;
+pub mod primitive;
+
+pub use self::lock::DEFAULT_MAX_HANDOFFS;
+use self::primitive::{BackoffLock, McsLock, TicketLock};
+
+pub type Cohort<T, I, O, F, G, L> = lock::Cohort<T, I, O, F, G, L>;
+pub type CBoMcs<T, I, O, F> = Cohort<T, I, O, F, BackoffLock, McsLock>;
+pub type CTktTkt<T, I, O, F> = Cohort<T, I, O, F, TicketLock, TicketLock>;
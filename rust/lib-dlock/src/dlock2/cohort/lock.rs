@@ -0,0 +1,167 @@
+use std::{
+    arch::x86_64::__rdtscp,
+    cell::SyncUnsafeCell,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering::*},
+};
+
+use crossbeam::utils::CachePadded;
+use thread_local::ThreadLocal;
+
+use crate::{
+    dlock2::{DLock2, DLock2Delegate},
+    topology::Topology,
+};
+
+use super::primitive::{GlobalLock, LocalLock};
+
+/// Local handoffs before the global lock is released by default
+pub const DEFAULT_MAX_HANDOFFS: u64 = 64;
+
+#[derive(Debug)]
+struct ThreadData<N> {
+    cluster: AtomicUsize,
+    node: N,
+}
+
+#[derive(Debug)]
+struct Cluster<L> {
+    local: L,
+    /// Times the global lock was passed along in a row, only touched by the
+    /// owner
+    handoffs: SyncUnsafeCell<u64>,
+    /// Cycles the cluster held the global lock for
+    usage: AtomicU64,
+}
+
+/// Cohort lock: a thread takes the lock of its cluster, then the global lock
+/// unless the previous owner of the cluster lock passed it along. An owner
+/// keeps the global lock inside the cluster while cluster threads wait, at
+/// most `max_handoffs` times in a row.
+#[derive(Debug)]
+pub struct Cohort<T, I, O, F, G, L>
+where
+    F: DLock2Delegate<T, I, O>,
+    G: GlobalLock,
+    L: LocalLock,
+{
+    delegate: F,
+    data: SyncUnsafeCell<T>,
+    topology: Topology,
+    max_handoffs: u64,
+    global: CachePadded<G>,
+    clusters: Box<[CachePadded<Cluster<L>>]>,
+    local_node: ThreadLocal<ThreadData<L::Node>>,
+    phantom: std::marker::PhantomData<fn(I) -> O>,
+}
+
+impl<T, I, O, F, G, L> Cohort<T, I, O, F, G, L>
+where
+    F: DLock2Delegate<T, I, O>,
+    G: GlobalLock,
+    L: LocalLock,
+{
+    pub fn new(data: T, delegate: F) -> Self {
+        Self::with_topology(data, delegate, Topology::single())
+    }
+
+    pub fn with_topology(data: T, delegate: F, topology: Topology) -> Self {
+        let clusters = (0..topology.num_clusters())
+            .map(|_| {
+                CachePadded::new(Cluster {
+                    local: L::default(),
+                    handoffs: 0.into(),
+                    usage: AtomicU64::new(0),
+                })
+            })
+            .collect();
+
+        Self {
+            delegate,
+            data: SyncUnsafeCell::new(data),
+            topology,
+            max_handoffs: DEFAULT_MAX_HANDOFFS,
+            global: CachePadded::new(G::default()),
+            clusters,
+            local_node: ThreadLocal::new(),
+            phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Pass the global lock inside a cluster at most `max_handoffs` times in
+    /// a row, 0 never passes it.
+    pub fn with_max_handoffs(mut self, max_handoffs: u64) -> Self {
+        self.max_handoffs = max_handoffs;
+        self
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    /// Cycles every cluster held the global lock for.
+    pub fn cluster_usage(&self) -> Vec<u64> {
+        self.clusters
+            .iter()
+            .map(|cluster| cluster.usage.load(Relaxed))
+            .collect()
+    }
+
+    /// Put the calling thread into `cluster` instead of the cluster of the
+    /// CPU it first locked from.
+    pub fn bind_cluster(&self, cluster: usize) {
+        assert!(cluster < self.clusters.len(), "no such cluster");
+        self.thread_data().cluster.store(cluster, Relaxed);
+    }
+
+    fn thread_data(&self) -> &ThreadData<L::Node> {
+        self.local_node.get_or(|| ThreadData {
+            cluster: self.topology.current_cluster().into(),
+            node: L::Node::default(),
+        })
+    }
+}
+
+unsafe impl<T, I, O, F, G, L> DLock2<I, O> for Cohort<T, I, O, F, G, L>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
+    G: GlobalLock,
+    L: LocalLock,
+{
+    fn lock(&self, data: I) -> O {
+        let thread_data = self.thread_data();
+        let cluster = &self.clusters[thread_data.cluster.load(Relaxed)];
+        let mut aux = 0;
+
+        if !cluster.local.lock(&thread_data.node) {
+            self.global.lock();
+        }
+
+        let work_begin = unsafe { __rdtscp(&mut aux) };
+
+        let output = (self.delegate)(unsafe { &mut *self.data.get() }, data);
+
+        let work_end = unsafe { __rdtscp(&mut aux) };
+        cluster.usage.fetch_add(work_end - work_begin, Relaxed);
+
+        let handoffs = unsafe { &mut *cluster.handoffs.get() };
+
+        if *handoffs < self.max_handoffs && cluster.local.has_waiters(&thread_data.node) {
+            *handoffs += 1;
+            cluster.local.unlock(&thread_data.node, true);
+        } else {
+            *handoffs = 0;
+            self.global.unlock();
+            cluster.local.unlock(&thread_data.node, false);
+        }
+
+        output
+    }
+
+    #[cfg(feature = "combiner_stat")]
+    fn get_combine_time(&self) -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    hint::spin_loop,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicU8, Ordering::*},
+};
+
+use crossbeam::utils::Backoff;
+
+/// Lock shared by all clusters. It must be thread-oblivious: a thread of the
+/// cohort may release it on behalf of the thread that acquired it.
+pub trait GlobalLock: Default + Send + Sync {
+    fn lock(&self);
+    fn unlock(&self);
+}
+
+/// Per-cluster lock that tells its owner whether anybody is waiting, so the
+/// global lock can be passed along inside the cluster.
+pub trait LocalLock: Default + Send + Sync {
+    /// Per-thread queue node, unused by locks without a queue
+    type Node: Default + Send + Sync;
+
+    /// Returns whether the previous owner passed the global lock along.
+    fn lock(&self, node: &Self::Node) -> bool;
+
+    /// Whether another thread of the cluster waits for the lock.
+    fn has_waiters(&self, node: &Self::Node) -> bool;
+
+    fn unlock(&self, node: &Self::Node, pass_global: bool);
+}
+
+/// Test-and-test-and-set lock with exponential backoff.
+#[derive(Debug, Default)]
+pub struct BackoffLock {
+    locked: AtomicBool,
+}
+
+impl GlobalLock for BackoffLock {
+    fn lock(&self) {
+        let backoff = Backoff::new();
+
+        loop {
+            if !self.locked.load(Relaxed)
+                && self
+                    .locked
+                    .compare_exchange_weak(false, true, Acquire, Relaxed)
+                    .is_ok()
+            {
+                return;
+            }
+
+            backoff.snooze();
+        }
+    }
+
+    fn unlock(&self) {
+        self.locked.store(false, Release);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TicketLock {
+    next: AtomicU64,
+    serving: AtomicU64,
+    /// Set by an owner that passes the global lock to the next ticket
+    passed: AtomicBool,
+}
+
+impl TicketLock {
+    fn acquire(&self) {
+        let ticket = self.next.fetch_add(1, Relaxed);
+        let backoff = Backoff::new();
+
+        while self.serving.load(Acquire) != ticket {
+            backoff.snooze();
+        }
+    }
+
+    fn release(&self) {
+        self.serving.store(self.serving.load(Relaxed) + 1, Release);
+    }
+}
+
+impl GlobalLock for TicketLock {
+    fn lock(&self) {
+        self.acquire();
+    }
+
+    fn unlock(&self) {
+        self.release();
+    }
+}
+
+impl LocalLock for TicketLock {
+    type Node = ();
+
+    fn lock(&self, _node: &()) -> bool {
+        self.acquire();
+        self.passed.swap(false, Relaxed)
+    }
+
+    fn has_waiters(&self, _node: &()) -> bool {
+        self.next.load(Relaxed) != self.serving.load(Relaxed) + 1
+    }
+
+    fn unlock(&self, _node: &(), pass_global: bool) {
+        self.passed.store(pass_global, Relaxed);
+        self.release();
+    }
+}
+
+const WAITING: u8 = 0;
+const LOCAL: u8 = 1;
+const GLOBAL: u8 = 2;
+
+#[derive(Debug)]
+pub struct McsNode {
+    next: AtomicPtr<McsNode>,
+    /// `WAITING` until the owner hands over, then whether the global lock
+    /// came along (`LOCAL`) or still has to be taken (`GLOBAL`)
+    state: AtomicU8,
+}
+
+impl Default for McsNode {
+    fn default() -> Self {
+        Self {
+            next: AtomicPtr::new(ptr::null_mut()),
+            state: AtomicU8::new(WAITING),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct McsLock {
+    tail: AtomicPtr<McsNode>,
+}
+
+impl Default for McsLock {
+    fn default() -> Self {
+        Self {
+            tail: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
+impl LocalLock for McsLock {
+    type Node = McsNode;
+
+    fn lock(&self, node: &McsNode) -> bool {
+        let node_ptr = node as *const McsNode as *mut McsNode;
+
+        node.next.store(ptr::null_mut(), Relaxed);
+        node.state.store(WAITING, Relaxed);
+
+        let prev = self.tail.swap(node_ptr, AcqRel);
+
+        if prev.is_null() {
+            return false;
+        }
+
+        unsafe { (*prev).next.store(node_ptr, Release) };
+
+        let backoff = Backoff::new();
+        loop {
+            match node.state.load(Acquire) {
+                WAITING => backoff.snooze(),
+                state => return state == LOCAL,
+            }
+        }
+    }
+
+    fn has_waiters(&self, node: &McsNode) -> bool {
+        !node.next.load(Acquire).is_null()
+            || self.tail.load(Acquire) != node as *const McsNode as *mut McsNode
+    }
+
+    fn unlock(&self, node: &McsNode, pass_global: bool) {
+        let node_ptr = node as *const McsNode as *mut McsNode;
+        let mut next = node.next.load(Acquire);
+
+        if next.is_null() {
+            if self
+                .tail
+                .compare_exchange(node_ptr, ptr::null_mut(), Release, Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+
+            // a successor swapped the tail but has not linked itself yet
+            loop {
+                next = node.next.load(Acquire);
+                if !next.is_null() {
+                    break;
+                }
+                spin_loop();
+            }
+        }
+
+        unsafe {
+            (*next)
+                .state
+                .store(if pass_global { LOCAL } else { GLOBAL }, Release);
+        }
+    }
+}
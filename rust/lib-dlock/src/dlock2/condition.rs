@@ -0,0 +1,34 @@
+//! Requests that stay parked in the lock until a predicate on the data holds.
+//!
+//! The predicate lives on the stack of the waiting thread, the node of its
+//! request only carries a pointer to it. The combiner passes over a parked
+//! request as long as the predicate does not hold and serves it in the
+//! first combining pass that finds it true. The waiting thread never
+//! submits the request again.
+
+use std::ptr::NonNull;
+
+pub type Predicate<'a, T> = &'a (dyn Fn(&T) -> bool + Sync);
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Condition(NonNull<()>);
+
+// the predicate is `Sync` and outlives the request it belongs to
+unsafe impl Send for Condition {}
+unsafe impl Sync for Condition {}
+
+impl Condition {
+    pub fn new<T>(predicate: &Predicate<'_, T>) -> Self {
+        Self(NonNull::from(predicate).cast())
+    }
+
+    /// Whether the request may be served on `data`.
+    ///
+    /// # Safety
+    ///
+    /// The condition must have been created from a predicate on `T` whose
+    /// request is still waiting.
+    pub unsafe fn holds<T>(self, data: &T) -> bool {
+        (self.0.cast::<Predicate<'_, T>>().as_ref())(data)
+    }
+}
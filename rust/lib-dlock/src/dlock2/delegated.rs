@@ -0,0 +1,69 @@
+//! Data structures whose methods run as requests of a lock.
+//!
+//! `#[delegated]` on `impl Foo { fn push(&mut self, x: u64); fn pop(&mut
+//! self) -> Option<u64>; }` generates the request enum `FooOp`, the response
+//! enum `FooOutput` and `Delegatable for Foo`, whose `delegate` is the
+//! delegate of the lock. `Delegated<Foo, L>` wraps the lock and gets `push`
+//! and `pop` taking `&self` from the generated trait `DelegatedFoo`:
+//!
+//! ```ignore
+//! let lock = target.to_locktype(Foo::default(), Foo::delegate, &config)?;
+//! let foo = Delegated::<Foo, _>::new(lock);
+//! foo.push(1);
+//! ```
+
+use std::{fmt::Display, marker::PhantomData};
+
+pub use libdlock_macro::delegated;
+
+use super::DLock2;
+
+/// A data structure `#[delegated]` turned into requests and responses.
+pub trait Delegatable {
+    /// A method call, with its arguments
+    type Op;
+    /// The return value of a method call, tagged with the method
+    type Output;
+
+    /// Run `op` on `self`, the delegate of the lock guarding it.
+    fn delegate(&mut self, op: Self::Op) -> Self::Output;
+}
+
+/// A lock delegating the methods of `T`, which it exposes through the trait
+/// `#[delegated]` generated next to `T`.
+pub struct Delegated<T, L> {
+    lock: L,
+    phantom: PhantomData<fn(T) -> T>,
+}
+
+impl<T, L> Delegated<T, L>
+where
+    T: Delegatable,
+    L: DLock2<T::Op, T::Output>,
+{
+    pub fn new(lock: L) -> Self {
+        Self {
+            lock,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Run one method call under the lock.
+    pub fn call(&self, op: T::Op) -> T::Output {
+        self.lock.lock(op)
+    }
+
+    pub fn inner(&self) -> &L {
+        &self.lock
+    }
+
+    pub fn into_inner(self) -> L {
+        self.lock
+    }
+}
+
+impl<T, L: Display> Display for Delegated<T, L> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.lock.fmt(f)
+    }
+}
@@ -1,4 +1,4 @@
 mod lock;
 mod node;
 
-pub type DSMSynch<T, I, F> = lock::DSMSynch<T, I, F>;
+pub type DSMSynch<T, I, O, F> = lock::DSMSynch<T, I, O, F>;
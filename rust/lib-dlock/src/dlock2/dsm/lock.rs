@@ -3,7 +3,6 @@ use std::{
     arch::x86_64::__rdtscp,
     cell::{SyncUnsafeCell, UnsafeCell},
     hint::spin_loop,
-    mem::MaybeUninit,
     ptr::{self, null_mut},
     sync::atomic::{AtomicPtr, AtomicU8, Ordering::*},
 };
@@ -12,11 +11,16 @@ use debug_unwraps::DebugUnwrapExt;
 use thread_local::ThreadLocal;
 
 use super::node::Node;
-use crate::dlock2::DLock2Delegate;
+use crate::dlock2::{
+    batch::{BatchDelegate, Batcher},
+    nesting,
+    slot::Slot,
+    DLock2Delegate,
+};
 
 #[derive(Debug)]
-struct ThreadData<T> {
-    nodes: UnsafeCell<[Node<T>; 2]>,
+struct ThreadData<I, O> {
+    nodes: UnsafeCell<[Node<I, O>; 2]>,
     toggle: AtomicU8,
 
     #[cfg(feature = "combiner_stat")]
@@ -24,21 +28,24 @@ struct ThreadData<T> {
 }
 
 #[derive(Debug)]
-pub struct DSMSynch<T, I, F, const H: u32 = 64>
+pub struct DSMSynch<T, I, O, F, const H: u32 = 64>
 where
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
     I: Send,
+    O: Send,
 {
     delegate: F,
     data: SyncUnsafeCell<T>,
-    tail: AtomicPtr<Node<I>>,
-    local_node: ThreadLocal<ThreadData<I>>,
+    tail: AtomicPtr<Node<I, O>>,
+    local_node: ThreadLocal<ThreadData<I, O>>,
+    batch: Batcher<T, I, O, Node<I, O>>,
 }
 
-impl<T, I, F> DSMSynch<T, I, F>
+impl<T, I, O, F> DSMSynch<T, I, O, F>
 where
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
     I: Send,
+    O: Send,
 {
     pub fn new(data: T, delegate: F) -> Self {
         Self {
@@ -46,27 +53,53 @@ where
             data: SyncUnsafeCell::new(data),
             tail: AtomicPtr::default(),
             local_node: ThreadLocal::new(),
+            batch: Batcher::new(),
         }
     }
+
+    /// Hand all requests of a combining pass to `batch` at once instead of
+    /// running the delegate on every request.
+    pub fn with_batch_delegate(mut self, batch: impl BatchDelegate<T, I, O> + 'static) -> Self {
+        self.batch.set(batch);
+        self
+    }
+
+    pub fn is_batched(&self) -> bool {
+        self.batch.is_enabled()
+    }
 }
 
 trait AsMutPtr {
     fn as_mut_ptr(&self) -> *mut Self;
 }
 
-impl<T> AsMutPtr for Node<T> {
-    fn as_mut_ptr(&self) -> *mut Node<T> {
+impl<I, O> AsMutPtr for Node<I, O> {
+    fn as_mut_ptr(&self) -> *mut Node<I, O> {
         self as *const _ as *mut _
     }
 }
 
-unsafe impl<T, I, F, const H: u32> DLock2<I> for DSMSynch<T, I, F, H>
+impl<T, I, O, F, const H: u32> Drop for DSMSynch<T, I, O, F, H>
+where
+    F: DLock2Delegate<T, I, O>,
+    I: Send,
+    O: Send,
+{
+    fn drop(&mut self) {
+        nesting::forget(self);
+    }
+}
+
+unsafe impl<T, I, O, F, const H: u32> DLock2<I, O> for DSMSynch<T, I, O, F, H>
 where
     T: Send + Sync,
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
     I: Send,
+    O: Send,
 {
-    fn lock(&self, data: I) -> I {
+    fn lock(&self, data: I) -> O {
+        nesting::enter(self);
+
         let thread_data = self.local_node.get_or(|| ThreadData {
             nodes: Default::default(),
             toggle: AtomicU8::new(0),
@@ -85,7 +118,7 @@ where
             my_node.next.store_release(null_mut());
 
             // announce the request
-            my_node.data.get().write(MaybeUninit::new(data));
+            my_node.data.get().write(Slot::input(data));
 
             // insert the node into the queue
             let my_pred_node = self.tail.swap(my_node.as_mut_ptr(), AcqRel);
@@ -101,11 +134,12 @@ where
                 }
 
                 if my_node.completed.load_acquire() {
-                    return my_node.data.get().read().assume_init();
+                    return (*my_node.data.get()).take_output();
                 }
             }
 
             // combiner
+            let _combining = nesting::combining_guard(self);
 
             #[cfg(feature = "combiner_stat")]
             let begin = __rdtscp(&mut aux);
@@ -117,13 +151,18 @@ where
             loop {
                 counter += 1;
 
-                tmp_node.data.get().write(MaybeUninit::new((self.delegate)(
-                    self.data.get().as_mut().debug_unwrap_unchecked(),
-                    tmp_node.data.get().read().assume_init(),
-                )));
-
-                tmp_node.completed.store_release(true);
-                tmp_node.wait.store_release(false);
+                if self.batch.is_enabled() {
+                    self.batch
+                        .push(tmp_node, (*tmp_node.data.get()).take_input());
+                } else {
+                    tmp_node.data.get().write(Slot::output((self.delegate)(
+                        self.data.get().as_mut().debug_unwrap_unchecked(),
+                        (*tmp_node.data.get()).take_input(),
+                    )));
+
+                    tmp_node.completed.store_release(true);
+                    tmp_node.wait.store_release(false);
+                }
 
                 if tmp_node.next.load_acquire().is_null()
                     || (*tmp_node.next.load_acquire())
@@ -142,6 +181,17 @@ where
                     .debug_unwrap_unchecked();
             }
 
+            // the batch has to be done before the next combiner takes over
+            self.batch.run(
+                self.data.get().as_mut().debug_unwrap_unchecked(),
+                &self.delegate,
+                |node, output| {
+                    node.data.get().write(Slot::output(output));
+                    node.completed.store_release(true);
+                    node.wait.store_release(false);
+                },
+            );
+
             if tmp_node.next.load_acquire().is_null() {
                 // This ordering might be wrong?
                 if self
@@ -153,7 +203,7 @@ where
                     // It is not sure whether the acquire ordering is required because the current thread
                     // should be the combiner which means it should handle its own node.
                     if my_node.completed.load_acquire() {
-                        return my_node.data.get().read().assume_init();
+                        return (*my_node.data.get()).take_output();
                     }
 
                     unreachable!("This should not happen");
@@ -182,7 +232,7 @@ where
                 *thread_data.combiner_time_stat.get() += end - begin;
             }
 
-            return my_node.data.get().read().assume_init();
+            return (*my_node.data.get()).take_output();
         }
     }
 
@@ -1,25 +1,26 @@
 use std::{
     cell::SyncUnsafeCell,
-    mem::MaybeUninit,
     sync::atomic::{AtomicBool, AtomicPtr},
 };
 
+use crate::dlock2::slot::Slot;
+
 #[derive(Debug)]
-pub struct Node<T> {
+pub struct Node<I, O> {
     pub age: SyncUnsafeCell<u32>,
-    pub data: SyncUnsafeCell<MaybeUninit<T>>,
+    pub data: SyncUnsafeCell<Slot<I, O>>,
     pub completed: AtomicBool,
     pub wait: AtomicBool,
-    pub next: AtomicPtr<Node<T>>,
+    pub next: AtomicPtr<Node<I, O>>,
     #[cfg(feature = "combiner_stat")]
     pub combiner_time_stat: u64,
 }
 
-impl<T> Default for Node<T> {
+impl<I, O> Default for Node<I, O> {
     fn default() -> Self {
         Node {
             age: SyncUnsafeCell::new(0),
-            data: MaybeUninit::uninit().into(),
+            data: Slot::empty().into(),
             completed: AtomicBool::new(false),
             wait: AtomicBool::new(false),
             next: AtomicPtr::new(std::ptr::null_mut()),
@@ -5,4 +5,4 @@ use crate::spin_lock::RawSpinLock;
 mod lock;
 mod node;
 
-pub type FC<T, I, F, L = RawSpinLock> = lock::FC<T, I, F, L>;
+pub type FC<T, I, O, F, L = RawSpinLock> = lock::FC<T, I, O, F, L>;
@@ -1,42 +1,70 @@
 use std::{
     arch::x86_64::__rdtscp,
     cell::SyncUnsafeCell,
-    mem::MaybeUninit,
     ptr::{self, null_mut, NonNull},
     sync::atomic::{AtomicPtr, AtomicU32, Ordering::*},
+    time::{Duration, Instant},
 };
 
 use crossbeam::utils::{Backoff, CachePadded};
 use lock_api::RawMutex;
 use thread_local::ThreadLocal;
 
-use crate::dlock2::{DLock2, DLock2Delegate};
+use crate::dlock2::{
+    batch::{BatchDelegate, Batcher},
+    condition::{Condition, Predicate},
+    nesting,
+    seqlock::SeqLock,
+    slot::Slot,
+    ticket::{Ticket, TicketState, MAX_DETACHED},
+    DLock2, DLock2Delegate,
+};
 
 use super::node::Node;
 
 const CLEAN_UP_AGE: u32 = 500;
 
+/// Nodes a thread uses for the requests it keeps in flight, next to the node
+/// of `lock`
+#[derive(Debug)]
+struct Slots<I, O> {
+    /// Nodes not holding a request
+    free: SyncUnsafeCell<Vec<*mut Node<I, O>>>,
+    /// Nodes of detached requests, oldest first
+    detached: SyncUnsafeCell<Vec<*mut Node<I, O>>>,
+    /// Nodes of withdrawn requests a running combining pass may still serve
+    withdrawn: SyncUnsafeCell<Vec<*mut Node<I, O>>>,
+}
+
+// the nodes are shared with the combiner through the list anyway
+unsafe impl<I, O> Send for Slots<I, O> {}
+
 #[derive(Debug)]
-pub struct FC<T, I, F, L>
+pub struct FC<T, I, O, F, L>
 where
     T: Send + Sync,
     I: Send,
-    F: Fn(&mut T, I) -> I,
+    O: Send,
+    F: Fn(&mut T, I) -> O,
     L: RawMutex,
 {
     pass: AtomicU32,
     combiner_lock: CachePadded<L>,
     delegate: F,
     data: SyncUnsafeCell<T>,
-    head: AtomicPtr<Node<I>>,
-    local_node: ThreadLocal<SyncUnsafeCell<Node<I>>>,
+    head: AtomicPtr<Node<I, O>>,
+    local_node: ThreadLocal<SyncUnsafeCell<Node<I, O>>>,
+    slots: ThreadLocal<Slots<I, O>>,
+    batch: Batcher<T, I, O, Node<I, O>>,
+    seqlock: SeqLock,
 }
 
-impl<T, I, F, L> FC<T, I, F, L>
+impl<T, I, O, F, L> FC<T, I, O, F, L>
 where
     T: Send + Sync,
     I: Send,
-    F: DLock2Delegate<T, I>,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
     L: RawMutex,
 {
     pub fn new(data: T, delegate: F) -> Self {
@@ -47,18 +75,47 @@ where
             data: SyncUnsafeCell::new(data),
             head: AtomicPtr::new(std::ptr::null_mut()),
             local_node: ThreadLocal::new(),
+            slots: ThreadLocal::new(),
+            batch: Batcher::new(),
+            seqlock: SeqLock::new(),
         }
     }
 
-    fn push_node(&self, node: &mut Node<I>) {
+    /// Hand all requests of a combining pass to `batch` at once instead of
+    /// running the delegate on every request.
+    pub fn with_batch_delegate(mut self, batch: impl BatchDelegate<T, I, O> + 'static) -> Self {
+        self.batch.set(batch);
+        self
+    }
+
+    pub fn is_batched(&self) -> bool {
+        self.batch.is_enabled()
+    }
+
+    /// Run `reader` on the data on the calling thread, concurrently with
+    /// other readers and without waiting for the combiner. Returns `None`
+    /// if every attempt overlapped with a combining pass, the read then has
+    /// to be delegated.
+    ///
+    /// # Safety
+    ///
+    /// `reader` may see the data in the middle of a combining pass, it must
+    /// neither crash nor hang on such a state.
+    pub unsafe fn try_read<R>(&self, reader: impl Fn(&T) -> R) -> Option<R> {
+        self.seqlock.read(|| reader(&*self.data.get()))
+    }
+
+    fn push_node(&self, node: &Node<I, O>) {
         let mut head = self.head.load(Acquire);
         node.active.store(true, Release);
         loop {
             node.next.store(head, Relaxed);
-            match self
-                .head
-                .compare_exchange_weak(head, node, Release, Acquire)
-            {
+            match self.head.compare_exchange_weak(
+                head,
+                node as *const Node<I, O> as *mut Node<I, O>,
+                Release,
+                Acquire,
+            ) {
                 Ok(_) => {
                     break;
                 }
@@ -67,13 +124,28 @@ where
         }
     }
 
-    fn push_if_unactive(&self, node: &mut Node<I>) {
-        if node.active.load(Acquire) {
+    fn push_if_unactive(&self, node: &Node<I, O>) {
+        // the clean up may push the node back as well
+        if node.active.load(Acquire)
+            || node
+                .active
+                .compare_exchange(false, true, SeqCst, Relaxed)
+                .is_err()
+        {
             return;
         }
         self.push_node(node);
     }
 
+    unsafe fn serve(&self, node: &Node<I, O>, data: &mut T) {
+        node.data.get().write(Slot::output((self.delegate)(
+            data,
+            (*node.data.get()).take_input(),
+        )));
+
+        node.complete.store(true, Release);
+    }
+
     fn combine(&self) {
         let mut current_ptr = NonNull::new(self.head.load(Acquire));
 
@@ -95,18 +167,38 @@ where
             if current.active.load(Acquire) && !current.complete.load(Acquire) {
                 unsafe {
                     (*current.age.get()) = pass;
-                    current.data.get().write(MaybeUninit::new((self.delegate)(
-                        self.data.get().as_mut().unwrap_unchecked(),
-                        current.data.get().read().assume_init(),
-                    )));
-                }
 
-                current.complete.store(true, Release);
+                    let data = self.data.get().as_mut().unwrap_unchecked();
+
+                    match *current.condition.get() {
+                        // withdrawn by its owner
+                        _ if !current.claim.serve() => {}
+                        // parked until a later pass
+                        Some(condition) if !condition.holds(&*data) => {}
+                        // the predicate only holds on the data as it is now
+                        Some(_) => self.serve(current, data),
+                        None if self.batch.is_enabled() => {
+                            self.batch.push(current, (*current.data.get()).take_input())
+                        }
+                        None => self.serve(current, data),
+                    }
+                }
             }
 
             current_ptr = NonNull::new(current.next.load(Acquire));
         }
 
+        unsafe {
+            self.batch.run(
+                self.data.get().as_mut().unwrap_unchecked(),
+                &self.delegate,
+                |node, output| {
+                    node.data.get().write(Slot::output(output));
+                    node.complete.store(true, Release);
+                },
+            );
+        }
+
         #[cfg(feature = "combiner_stat")]
         unsafe {
             let end = __rdtscp(&mut aux);
@@ -115,7 +207,7 @@ where
         }
     }
 
-    unsafe fn clean_unactive_node(&self, head: &AtomicPtr<Node<I>>, pass: u32) {
+    unsafe fn clean_unactive_node(&self, head: &AtomicPtr<Node<I, O>>, pass: u32) {
         let previous_ptr = NonNull::new(head.load(Acquire)).unwrap();
 
         let mut previous_nonnull = previous_ptr;
@@ -131,7 +223,14 @@ where
             if pass - (*current.age.get()) > CLEAN_UP_AGE {
                 (*previous.next.as_ptr()) = *current.next.as_ptr();
                 (*current.next.as_ptr()) = null_mut();
-                current.active.store(false, Release);
+                current.active.store(false, SeqCst);
+
+                // a detached request submitted meanwhile has nobody to push
+                // its node back
+                if !current.complete.load(SeqCst) {
+                    self.push_if_unactive(current);
+                }
+
                 current_ptr = NonNull::new(previous.next.load(Acquire));
                 continue;
             }
@@ -140,58 +239,247 @@ where
             current_ptr = NonNull::new(current.next.load(Acquire));
         }
     }
-}
 
-unsafe impl<'a, T, I, F, L> DLock2<I> for FC<T, I, F, L>
-where
-    T: Send + Sync,
-    I: Send,
-    F: DLock2Delegate<T, I>,
-    L: RawMutex + Send + Sync,
-{
-    fn lock(&self, data: I) -> I {
+    /// Run `data` once `predicate` holds on the data. The request stays in
+    /// the list until a combining pass finds the predicate true, the thread
+    /// keeps combining meanwhile.
+    pub fn lock_when(&self, data: I, predicate: impl Fn(&T) -> bool + Sync) -> O {
+        let predicate: Predicate<'_, T> = &predicate;
+
+        self.lock_node(data, Some(Condition::new(&predicate)))
+    }
+
+    fn lock_node(&self, data: I, condition: Option<Condition>) -> O {
+        nesting::enter(self);
+
         let node = self.local_node.get_or(|| SyncUnsafeCell::new(Node::new()));
 
         let node = unsafe { &mut *node.get() };
 
-        node.data = SyncUnsafeCell::new(MaybeUninit::new(data));
+        node.data = SyncUnsafeCell::new(Slot::input(data));
+        node.condition = SyncUnsafeCell::new(condition);
+        node.claim.reset(false);
         node.complete.store(false, Release);
 
-        'outer: loop {
-            self.push_if_unactive(node);
+        self.wait_node(node, None);
 
-            if self.combiner_lock.try_lock() {
-                self.combine();
-                unsafe {
-                    let pass = self.pass.load(Relaxed);
+        unsafe { (*node.data.get()).take_output() }
+    }
 
-                    if pass % CLEAN_UP_AGE == 0 {
-                        self.clean_unactive_node(&self.head, pass);
-                    }
+    /// Take the combiner lock if it is free and run a combining pass.
+    fn try_combine(&self) -> bool {
+        if !self.combiner_lock.try_lock() {
+            return false;
+        }
 
-                    self.combiner_lock.unlock();
-                }
+        nesting::combining(self, || self.seqlock.write(|| self.combine()));
+        unsafe {
+            let pass = self.pass.load(Relaxed);
+
+            if pass % CLEAN_UP_AGE == 0 {
+                self.clean_unactive_node(&self.head, pass);
+            }
+
+            self.combiner_lock.unlock();
+        }
+
+        true
+    }
 
+    /// Combine or wait until the request in `node` is served, or until
+    /// `deadline` passes. Returns whether the request was served.
+    fn wait_node(&self, node: &Node<I, O>, deadline: Option<Instant>) -> bool {
+        loop {
+            self.push_if_unactive(node);
+
+            if self.try_combine() {
                 if node.complete.load(Acquire) {
-                    break 'outer;
+                    return true;
                 }
             } else {
                 let backoff = Backoff::new();
-                let mut count: u32 = 8;
-                loop {
+                for _ in 0..8 {
                     if node.complete.load(Acquire) {
-                        break 'outer;
+                        return true;
                     }
                     backoff.spin();
-                    count = count.wrapping_sub(1);
-                    if count == 0 {
-                        continue 'outer;
-                    }
                 }
             }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return false;
+            }
+        }
+    }
+
+    fn slots(&self) -> &Slots<I, O> {
+        // combiner statistics go to the node of `lock`
+        self.local_node.get_or(|| SyncUnsafeCell::new(Node::new()));
+
+        self.slots.get_or(|| Slots {
+            free: Vec::new().into(),
+            detached: Vec::new().into(),
+            withdrawn: Vec::new().into(),
+        })
+    }
+
+    /// Keep the request of a dropped ticket in flight as a detached one.
+    unsafe fn detach(lock: *const (), node: NonNull<u8>) {
+        let lock = &*lock.cast::<Self>();
+
+        (*lock.slots().detached.get()).push(node.cast().as_ptr());
+    }
+
+    /// Give back the nodes of detached requests that were served, waiting
+    /// for the oldest one if too many are in flight, and the nodes of
+    /// withdrawn requests no combining pass can see anymore.
+    unsafe fn reclaim(&self, slots: &Slots<I, O>) {
+        let detached = &mut *slots.detached.get();
+        let withdrawn = &mut *slots.withdrawn.get();
+        let free = &mut *slots.free.get();
+
+        // a pass that read a withdrawn request as pending would serve it
+        // again if its node took a new request, the passes running at the
+        // withdrawal are over once the combiner lock is free
+        if !withdrawn.is_empty() && self.combiner_lock.try_lock() {
+            self.combiner_lock.unlock();
+            free.append(withdrawn);
+        }
+
+        if detached.len() >= MAX_DETACHED {
+            let node = detached.remove(0);
+            self.wait_node(&*node, None);
+            drop((*(*node).data.get()).take_output());
+            free.push(node);
+        }
+
+        detached.retain(|&node| {
+            if !(*node).complete.load(Acquire) {
+                return true;
+            }
+
+            drop((*(*node).data.get()).take_output());
+            free.push(node);
+            false
+        });
+    }
+}
+
+impl<T, I, O, F, L> Drop for FC<T, I, O, F, L>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: Fn(&mut T, I) -> O,
+    L: RawMutex,
+{
+    fn drop(&mut self) {
+        nesting::forget(self);
+    }
+}
+
+unsafe impl<'a, T, I, O, F, L> DLock2<I, O> for FC<T, I, O, F, L>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
+    L: RawMutex + Send + Sync,
+{
+    fn lock(&self, data: I) -> O {
+        self.lock_node(data, None)
+    }
+
+    /// The request goes into a spare node of the thread, which is served by
+    /// the next combining pass. The thread runs one right away if nobody
+    /// else is combining.
+    fn submit(&self, data: I) -> Ticket<'_, O> {
+        nesting::enter(self);
+
+        let slots = self.slots();
+
+        unsafe {
+            self.reclaim(slots);
+
+            let node = &mut *(*slots.free.get())
+                .pop()
+                .unwrap_or_else(|| Box::leak(Box::new(Node::new())));
+
+            node.data = SyncUnsafeCell::new(Slot::input(data));
+            node.condition = SyncUnsafeCell::new(None);
+            node.claim.reset(false);
+            node.complete.store(false, SeqCst);
+
+            self.push_if_unactive(node);
+            self.try_combine();
+
+            Ticket::pending(self, node, Self::detach)
+        }
+    }
+
+    fn wait(&self, ticket: Ticket<'_, O>) -> O {
+        let node = match ticket.redeem(self) {
+            TicketState::Ready(output) => return output,
+            TicketState::Pending(node) => node.cast::<Node<I, O>>(),
+        };
+
+        unsafe {
+            self.wait_node(node.as_ref(), None);
+            let output = (*node.as_ref().data.get()).take_output();
+
+            (*self.slots().free.get()).push(node.as_ptr());
+
+            output
         }
+    }
+
+    fn lock_detached(&self, data: I) {
+        drop(self.submit(data));
+    }
+
+    fn supports_timeout(&self) -> bool {
+        true
+    }
+
+    /// The request waits in a spare node of the thread. Once the deadline
+    /// passes it is withdrawn unless a combiner took it up already, a
+    /// withdrawn request stays in the list as a completed one and its node
+    /// is only reused once no combining pass can see it.
+    fn lock_timeout(&self, data: I, timeout: Duration) -> Result<O, I> {
+        nesting::enter(self);
+
+        let deadline = Instant::now() + timeout;
+        let slots = self.slots();
 
-        unsafe { node.data.get().read().assume_init() }
+        unsafe {
+            self.reclaim(slots);
+
+            let node = &mut *(*slots.free.get())
+                .pop()
+                .unwrap_or_else(|| Box::leak(Box::new(Node::new())));
+
+            node.data = SyncUnsafeCell::new(Slot::input(data));
+            node.condition = SyncUnsafeCell::new(None);
+            node.claim.reset(true);
+            node.complete.store(false, SeqCst);
+
+            if !self.wait_node(node, Some(deadline)) {
+                if node.claim.withdraw() {
+                    let data = (*node.data.get()).take_input();
+                    node.complete.store(true, Release);
+                    (*slots.withdrawn.get()).push(node);
+                    return Err(data);
+                }
+
+                // a combiner took the request up before the deadline
+                self.wait_node(node, None);
+            }
+
+            let output = (*node.data.get()).take_output();
+            (*slots.free.get()).push(node);
+
+            Ok(output)
+        }
     }
 
     #[cfg(feature = "combiner_stat")]
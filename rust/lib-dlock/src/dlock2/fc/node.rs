@@ -1,32 +1,38 @@
 use std::{
     cell::{SyncUnsafeCell, UnsafeCell},
-    mem::MaybeUninit,
     sync::atomic::{AtomicBool, AtomicPtr},
 };
 
 use crossbeam::utils::CachePadded;
 
-pub struct Node<T> {
+use crate::dlock2::{claim::Claim, condition::Condition, slot::Slot};
+
+pub struct Node<I, O> {
     pub age: UnsafeCell<u32>,
     pub active: CachePadded<AtomicBool>,
-    pub data: SyncUnsafeCell<MaybeUninit<T>>,
+    pub data: SyncUnsafeCell<Slot<I, O>>,
     pub complete: AtomicBool,
-    pub next: AtomicPtr<Node<T>>,
+    pub next: AtomicPtr<Node<I, O>>,
+    pub condition: SyncUnsafeCell<Option<Condition>>,
+    pub claim: Claim,
     #[cfg(feature = "combiner_stat")]
     pub combiner_time_stat: u64,
 }
 
-impl<T> Node<T> {
-    pub(crate) fn new() -> Node<T>
+impl<I, O> Node<I, O> {
+    pub(crate) fn new() -> Node<I, O>
     where
-        T: Send,
+        I: Send,
+        O: Send,
     {
         Node {
             age: 0.into(),
             active: AtomicBool::new(false).into(),
             complete: AtomicBool::new(false),
-            data: SyncUnsafeCell::new(MaybeUninit::uninit()),
+            data: SyncUnsafeCell::new(Slot::empty()),
             next: AtomicPtr::default(),
+            condition: SyncUnsafeCell::new(None),
+            claim: Claim::new(),
             #[cfg(feature = "combiner_stat")]
             combiner_time_stat: 0,
         }
@@ -1,6 +1,6 @@
-use crate::spin_lock::RawSpinLock;
+use crate::{dlock2::ban_policy::FixedBan, spin_lock::RawSpinLock};
 
 mod lock;
 mod node;
 
-pub type FCBan<T, I, F, L = RawSpinLock> = lock::FCBan<T, I, F, L>;
+pub type FCBan<T, I, O, F, L = RawSpinLock, P = FixedBan> = lock::FCBan<T, I, O, F, L, P>;
@@ -1,10 +1,10 @@
 use std::{
     arch::x86_64::__rdtscp,
     cell::SyncUnsafeCell,
-    mem::MaybeUninit,
     ops::AddAssign,
     ptr::{self, null_mut, NonNull},
     sync::atomic::{AtomicI64, AtomicPtr, AtomicU32, Ordering::*},
+    time::{Duration, Instant},
 };
 
 use crossbeam::utils::{Backoff, CachePadded};
@@ -12,7 +12,13 @@ use lock_api::RawMutex;
 use thread_local::ThreadLocal;
 
 use crate::{
-    dlock2::{DLock2, DLock2Delegate},
+    dlock2::{
+        ban_policy::{credit_ban, BanPolicy, FixedBan},
+        group::Groups,
+        nesting,
+        slot::Slot,
+        DLock2, DLock2Delegate,
+    },
     spin_lock::RawSpinLock,
 };
 
@@ -21,34 +27,57 @@ use super::node::Node;
 const CLEAN_UP_AGE: u32 = 500;
 
 #[derive(Debug)]
-pub struct FCBan<T, I, F, L>
+pub struct FCBan<T, I, O, F, L, P = FixedBan>
 where
     T: Send + Sync,
     I: Send,
-    F: Fn(&mut T, I) -> I,
+    O: Send,
+    F: Fn(&mut T, I) -> O,
     L: RawMutex,
+    P: BanPolicy,
 {
     pass: AtomicU32,
     combiner_lock: CachePadded<L>,
     delegate: F,
     num_waiting_threads: AtomicI64,
+    ban_policy: P,
+    combiner_credit: f64,
+    groups: Groups,
     data: SyncUnsafeCell<T>,
-    head: AtomicPtr<Node<I>>,
-    local_node: ThreadLocal<SyncUnsafeCell<Node<I>>>,
+    head: AtomicPtr<Node<I, O>>,
+    local_node: ThreadLocal<SyncUnsafeCell<Node<I, O>>>,
 }
 
-impl<T, I, F, L> FCBan<T, I, F, L>
+impl<T, I, O, F, L> FCBan<T, I, O, F, L, FixedBan>
 where
     T: Send + Sync,
     I: Send,
-    F: DLock2Delegate<T, I>,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
     L: RawMutex,
 {
     pub fn new(data: T, delegate: F) -> Self {
+        Self::with_ban_policy(data, delegate, FixedBan)
+    }
+}
+
+impl<T, I, O, F, L, P> FCBan<T, I, O, F, L, P>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
+    L: RawMutex,
+    P: BanPolicy,
+{
+    pub fn with_ban_policy(data: T, delegate: F, ban_policy: P) -> Self {
         Self {
             pass: AtomicU32::new(0),
             combiner_lock: CachePadded::new(L::INIT),
             num_waiting_threads: AtomicI64::new(0),
+            ban_policy,
+            combiner_credit: 0.0,
+            groups: Groups::new(),
             delegate,
             data: SyncUnsafeCell::new(data),
             head: AtomicPtr::new(std::ptr::null_mut()),
@@ -56,7 +85,47 @@ where
         }
     }
 
-    fn push_node(&self, node: &mut Node<I>) {
+    pub fn ban_policy(&self) -> &P {
+        &self.ban_policy
+    }
+
+    /// Shorten the ban of a combiner by the given fraction of the time it
+    /// spent serving other threads.
+    pub fn with_combiner_credit(mut self, fraction: f64) -> Self {
+        self.combiner_credit = fraction;
+        self
+    }
+
+    fn local_node(&self) -> &SyncUnsafeCell<Node<I, O>> {
+        self.local_node.get_or(|| {
+            let mut node = Node::new(self.ban_policy.register(), self.groups.register());
+            let mut aux = 0;
+            unsafe {
+                node.banned_until = __rdtscp(&mut aux).into();
+            }
+            SyncUnsafeCell::new(node)
+        })
+    }
+
+    /// Penalty for a request of `node` that held the lock for `cs` cycles.
+    /// With groups the penalty is scaled so that every group gets its
+    /// weighted share.
+    fn penalty(&self, node: &Node<I, O>, cs: u64) -> u64 {
+        let penalty =
+            self.ban_policy
+                .penalty(node.id, cs, self.num_waiting_threads.load(Relaxed) as u64);
+
+        if !self.groups.in_use() {
+            return penalty;
+        }
+
+        let group = node.group.load(Relaxed);
+        self.groups.charge(group, cs);
+
+        self.groups.scale_penalty(group, penalty)
+    }
+
+    fn push_node(&self, node: &mut Node<I, O>) {
         self.num_waiting_threads.fetch_add(1, Relaxed);
         let mut head = self.head.load(Acquire);
         node.active.store(true, Release);
@@ -74,7 +143,7 @@ where
         }
     }
 
-    fn push_if_unactive(&self, node: &mut Node<I>) {
+    fn push_if_unactive(&self, node: &mut Node<I, O>) {
         if node.active.load(Acquire) {
             return;
         }
@@ -88,7 +157,6 @@ where
 
         #[cfg(feature = "combiner_stat")]
         let mut aux: u32 = 0;
-        #[cfg(feature = "combiner_stat")]
         let begin: u64;
 
         let mut work_begin: u64;
@@ -96,13 +164,12 @@ where
         unsafe {
             let mut aux: u32 = 0;
             work_begin = __rdtscp(&mut aux);
-
-            #[cfg(feature = "combiner_stat")]
-            {
-                begin = work_begin;
-            }
+            begin = work_begin;
         }
 
+        let own_node = self.local_node.get().unwrap().get() as *const Node<I, O>;
+        let mut own_cs = 0;
+
         while let Some(current_nonnull) = current_ptr {
             let current = unsafe { current_nonnull.as_ref() };
 
@@ -113,24 +180,26 @@ where
 
                     // if banned, skip
 
-                    if work_begin >= current.banned_until.get().read() {
-                        current.data.get().write(MaybeUninit::new(
-                            (self.delegate)(
-                                self.data.get().as_mut().unwrap_unchecked(),
-                                current.data.get().read().assume_init(),
-                            ),
+                    if work_begin >= current.banned_until.get().read() && current.claim.serve() {
+                        *current.data.get() = Slot::output((self.delegate)(
+                            self.data.get().as_mut().unwrap_unchecked(),
+                            (*current.data.get()).take_input(),
                         ));
                         current.complete.store(true, Release);
 
                         let work_end = __rdtscp(&mut aux);
-                        let cs = (work_end - work_begin) as i64;
+                        let cs = work_end - work_begin;
 
                         current
                             .banned_until
                             .get()
                             .as_mut()
                             .unwrap_unchecked()
-                            .add_assign((cs * (self.num_waiting_threads.load(Relaxed))) as u64);
+                            .add_assign(self.penalty(current, cs));
+
+                        if ptr::eq(current, own_node) {
+                            own_cs += cs;
+                        }
 
                         work_begin = work_end;
                     }
@@ -140,15 +209,26 @@ where
             current_ptr = NonNull::new(current.next.load(Acquire));
         }
 
+        let end = unsafe { __rdtscp(&mut 0) };
+
+        if self.combiner_credit > 0.0 {
+            let others = (end - begin).saturating_sub(own_cs);
+            unsafe {
+                credit_ban(
+                    &mut *(*own_node).banned_until.get(),
+                    (others as f64 * self.combiner_credit) as u64,
+                    end,
+                );
+            }
+        }
+
         #[cfg(feature = "combiner_stat")]
         unsafe {
-            let end = __rdtscp(&mut aux);
-
             (*self.local_node.get().unwrap().get()).combiner_time_stat += end - begin;
         }
     }
 
-    unsafe fn clean_unactive_node(&self, head: &AtomicPtr<Node<I>>, pass: u32) {
+    unsafe fn clean_unactive_node(&self, head: &AtomicPtr<Node<I, O>>, pass: u32) {
         let previous_ptr = NonNull::new(head.load(Acquire)).unwrap();
 
         let mut previous_nonnull = previous_ptr;
@@ -174,34 +254,36 @@ where
             current_ptr = NonNull::new(current.next.load(Acquire));
         }
     }
-}
 
-unsafe impl<T, I, F> DLock2<I> for FCBan<T, I, F, RawSpinLock>
-where
-    T: Send + Sync,
-    I: Send,
-    F: DLock2Delegate<T, I>,
-{
-    fn lock(&self, data: I) -> I {
-        let node = self.local_node.get_or(|| {
-            let mut node = Node::new();
-            let mut aux = 0;
-            unsafe {
-                node.banned_until = __rdtscp(&mut aux).into();
-            }
-            SyncUnsafeCell::new(node)
-        });
+    /// Wait until no combining pass that saw the request last withdrawn from
+    /// `node` as pending is running, or until `deadline` passes. Such a pass
+    /// would serve the next request of the node a second time, passes
+    /// starting later see the withdrawn request complete.
+    fn settle(&self, node: &Node<I, O>, deadline: Option<Instant>) -> bool {
+        if !node.claim.is_withdrawn() {
+            return true;
+        }
 
-        let node = unsafe { &mut *node.get() };
+        let backoff = Backoff::new();
+        while !self.combiner_lock.try_lock() {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return false;
+            }
+            backoff.snooze();
+        }
 
-        node.data = SyncUnsafeCell::new(MaybeUninit::new(data));
-        node.complete.store(false, Release);
+        unsafe { self.combiner_lock.unlock() };
+        true
+    }
 
-        'outer: loop {
+    /// Combine or wait until the request in `node` is served, or until
+    /// `deadline` passes. Returns whether the request was served.
+    fn wait_node(&self, node: &mut Node<I, O>, deadline: Option<Instant>) -> bool {
+        loop {
             self.push_if_unactive(node);
 
             if self.combiner_lock.try_lock() {
-                self.combine();
+                nesting::combining(self, || self.combine());
                 unsafe {
                     if self.pass.load(Relaxed) % CLEAN_UP_AGE == 0 {
                         self.clean_unactive_node(&self.head, self.pass.load(Relaxed));
@@ -210,27 +292,113 @@ where
                 }
 
                 if node.complete.load(Acquire) {
-                    break 'outer;
+                    return true;
                 }
             } else {
                 let backoff = Backoff::new();
                 loop {
                     if node.complete.load(Acquire) {
-                        break 'outer;
+                        return true;
                     }
                     backoff.snooze();
                     if backoff.is_completed() {
-                        continue 'outer;
+                        break;
                     }
                 }
             }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return false;
+            }
+        }
+    }
+}
+
+impl<T, I, O, F, L, P> Drop for FCBan<T, I, O, F, L, P>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: Fn(&mut T, I) -> O,
+    L: RawMutex,
+    P: BanPolicy,
+{
+    fn drop(&mut self) {
+        nesting::forget(self);
+    }
+}
+
+unsafe impl<T, I, O, F, P> DLock2<I, O> for FCBan<T, I, O, F, RawSpinLock, P>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
+    P: BanPolicy,
+{
+    fn lock(&self, data: I) -> O {
+        nesting::enter(self);
+
+        let node = unsafe { &mut *self.local_node().get() };
+        self.settle(node, None);
+
+        node.data = SyncUnsafeCell::new(Slot::input(data));
+        node.claim.reset(false);
+        node.complete.store(false, Release);
+
+        self.wait_node(node, None);
+
+        unsafe { (*node.data.get()).take_output() }
+    }
+
+    fn supports_timeout(&self) -> bool {
+        true
+    }
+
+    /// The combiner passes over the request while the thread is banned, a
+    /// deadline within the ban withdraws it.
+    fn lock_timeout(&self, data: I, timeout: Duration) -> Result<O, I> {
+        nesting::enter(self);
+
+        let deadline = Instant::now() + timeout;
+        let node = unsafe { &mut *self.local_node().get() };
+
+        // a pass that saw the last withdrawn request still runs
+        if !self.settle(node, Some(deadline)) {
+            return Err(data);
+        }
+
+        node.data = SyncUnsafeCell::new(Slot::input(data));
+        node.claim.reset(true);
+        node.complete.store(false, Release);
+
+        if !self.wait_node(node, Some(deadline)) {
+            if node.claim.withdraw() {
+                let data = unsafe { (*node.data.get()).take_input() };
+                node.complete.store(true, Release);
+                return Err(data);
+            }
+
+            // a combiner took the request up before the deadline
+            self.wait_node(node, None);
         }
 
-        unsafe { node.data.get().read().assume_init() }
+        Ok(unsafe { (*node.data.get()).take_output() })
     }
 
     #[cfg(feature = "combiner_stat")]
     fn get_combine_time(&self) -> Option<u64> {
         unsafe { self.local_node.get().map(|x| (*x.get()).combiner_time_stat) }
     }
+
+    fn join_group(&self, name: &str, weight: u64) -> Option<usize> {
+        let node = unsafe { &mut *self.local_node().get() };
+        let group = self.groups.join(name, weight, node.group.load(Relaxed));
+        node.group.store(group, Relaxed);
+        Some(group)
+    }
+
+    fn groups(&self) -> Option<&Groups> {
+        Some(&self.groups)
+    }
 }
@@ -1,32 +1,40 @@
 use std::{
     cell::{SyncUnsafeCell, UnsafeCell},
-    mem::MaybeUninit,
-    sync::atomic::{AtomicBool, AtomicPtr},
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize},
 };
 
-pub struct Node<T> {
+use crate::dlock2::{claim::Claim, slot::Slot};
+
+pub struct Node<I, O> {
+    pub id: usize,
+    pub group: AtomicUsize,
     pub age: UnsafeCell<u32>,
     pub active: AtomicBool,
-    pub data: SyncUnsafeCell<MaybeUninit<T>>,
+    pub data: SyncUnsafeCell<Slot<I, O>>,
     pub complete: AtomicBool,
-    pub next: AtomicPtr<Node<T>>,
+    pub next: AtomicPtr<Node<I, O>>,
     pub banned_until: SyncUnsafeCell<u64>,
+    pub claim: Claim,
     #[cfg(feature = "combiner_stat")]
     pub combiner_time_stat: u64,
 }
 
-impl<T> Node<T> {
-    pub(crate) fn new() -> Node<T>
+impl<I, O> Node<I, O> {
+    pub(crate) fn new(id: usize, group: usize) -> Node<I, O>
     where
-        T: Send,
+        I: Send,
+        O: Send,
     {
         Node {
+            id,
+            group: AtomicUsize::new(group),
             age: 0.into(),
             active: AtomicBool::new(false),
             complete: AtomicBool::new(false),
-            data: SyncUnsafeCell::new(MaybeUninit::uninit()),
+            data: SyncUnsafeCell::new(Slot::empty()),
             next: AtomicPtr::default(),
             banned_until: 0.into(),
+            claim: Claim::new(),
             #[cfg(feature = "combiner_stat")]
             combiner_time_stat: 0,
         }
@@ -0,0 +1,8 @@
+use crate::spin_lock::RawSpinLock;
+
+mod lock;
+mod node;
+
+pub use self::lock::NO_DEADLINE;
+
+pub type FCEDF<T, I, O, F, L = RawSpinLock> = lock::FCEDF<T, I, O, F, L>;
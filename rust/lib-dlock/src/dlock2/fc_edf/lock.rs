@@ -0,0 +1,286 @@
+use std::{
+    arch::x86_64::__rdtscp,
+    cell::SyncUnsafeCell,
+    ptr::{null_mut, NonNull},
+    sync::atomic::{AtomicPtr, AtomicU32, Ordering::*},
+};
+
+use arrayvec::ArrayVec;
+use crossbeam::utils::{Backoff, CachePadded};
+use lock_api::RawMutex;
+use thread_local::ThreadLocal;
+
+use crate::dlock2::{nesting, slot::Slot, usage_decay::UsageDecay, DLock2, DLock2Delegate};
+
+use super::node::Node;
+
+const CLEAN_UP_AGE: u32 = 500;
+
+/// Requests collected before they are ordered and served. With more pending
+/// requests than this the publication list is served in batches, each in
+/// EDF order.
+const BATCH: usize = 64;
+
+/// Deadline of requests submitted through `lock`
+pub const NO_DEADLINE: u64 = u64::MAX;
+
+/// Flat combining that serves the pending requests of a pass
+/// earliest-deadline-first. Requests without a deadline are served after
+/// those with one, least usage first.
+#[derive(Debug)]
+pub struct FCEDF<T, I, O, F, L>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: Fn(&mut T, I) -> O,
+    L: RawMutex,
+{
+    pass: AtomicU32,
+    combiner_lock: CachePadded<L>,
+    delegate: F,
+    usage_decay: UsageDecay,
+    data: SyncUnsafeCell<T>,
+    head: AtomicPtr<Node<I, O>>,
+    local_node: ThreadLocal<SyncUnsafeCell<Node<I, O>>>,
+}
+
+impl<T, I, O, F, L> FCEDF<T, I, O, F, L>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
+    L: RawMutex,
+{
+    pub fn new(data: T, delegate: F) -> Self {
+        Self {
+            pass: AtomicU32::new(0),
+            combiner_lock: CachePadded::new(L::INIT),
+            delegate,
+            usage_decay: UsageDecay::None,
+            data: SyncUnsafeCell::new(data),
+            head: AtomicPtr::new(std::ptr::null_mut()),
+            local_node: ThreadLocal::new(),
+        }
+    }
+
+    pub fn with_usage_decay(mut self, usage_decay: UsageDecay) -> Self {
+        self.usage_decay = usage_decay;
+        self
+    }
+
+    /// Execute `data` no later than `deadline_tsc` if possible. A request
+    /// that completes after its deadline counts as a miss of the calling
+    /// thread.
+    pub fn lock_with_deadline(&self, data: I, deadline_tsc: u64) -> O {
+        nesting::enter(self);
+
+        let node = self.local_node.get_or(|| SyncUnsafeCell::new(Node::new()));
+
+        let node = unsafe { &mut *node.get() };
+
+        node.data = SyncUnsafeCell::new(Slot::input(data));
+        *node.deadline.get_mut() = deadline_tsc;
+        node.complete.store(false, Release);
+
+        'outer: loop {
+            self.push_if_unactive(node);
+
+            if self.combiner_lock.try_lock() {
+                nesting::combining(self, || self.combine());
+                unsafe {
+                    let pass = self.pass.load(Relaxed);
+
+                    if pass % CLEAN_UP_AGE == 0 {
+                        self.clean_unactive_node(&self.head, pass);
+                    }
+
+                    self.combiner_lock.unlock();
+                }
+
+                if node.complete.load(Acquire) {
+                    break 'outer;
+                }
+            } else {
+                let backoff = Backoff::new();
+                let mut count: u32 = 8;
+                loop {
+                    if node.complete.load(Acquire) {
+                        break 'outer;
+                    }
+                    backoff.spin();
+                    count = count.wrapping_sub(1);
+                    if count == 0 {
+                        continue 'outer;
+                    }
+                }
+            }
+        }
+
+        unsafe { (*node.data.get()).take_output() }
+    }
+
+    fn push_node(&self, node: &mut Node<I, O>) {
+        let mut head = self.head.load(Acquire);
+        node.active.store(true, Release);
+        loop {
+            node.next.store(head, Relaxed);
+            match self
+                .head
+                .compare_exchange_weak(head, node, Release, Acquire)
+            {
+                Ok(_) => {
+                    break;
+                }
+                Err(x) => head = x,
+            }
+        }
+    }
+
+    fn push_if_unactive(&self, node: &mut Node<I, O>) {
+        if node.active.load(Acquire) {
+            return;
+        }
+        self.push_node(node);
+    }
+
+    unsafe fn serve(&self, node: &Node<I, O>) {
+        let mut aux: u32 = 0;
+        let begin = __rdtscp(&mut aux);
+
+        node.data.get().write(Slot::output((self.delegate)(
+            self.data.get().as_mut().unwrap_unchecked(),
+            (*node.data.get()).take_input(),
+        )));
+
+        let end = __rdtscp(&mut aux);
+
+        *node.usage.get() += end - begin;
+
+        let deadline = *node.deadline.get();
+        if deadline != NO_DEADLINE && end > deadline {
+            node.deadline_misses.fetch_add(1, Relaxed);
+        }
+
+        node.complete.store(true, Release);
+    }
+
+    unsafe fn serve_batch(&self, batch: &mut ArrayVec<&Node<I, O>, BATCH>, now: u64) {
+        for node in batch.iter() {
+            *node.usage.get() =
+                self.usage_decay
+                    .update(*node.usage.get(), &mut *node.usage_stamp.get(), now);
+        }
+
+        // `NO_DEADLINE` sorts after every deadline, usage breaks the ties
+        batch.sort_unstable_by_key(|node| unsafe { (*node.deadline.get(), *node.usage.get()) });
+
+        for node in batch.drain(..) {
+            self.serve(node);
+        }
+    }
+
+    fn combine(&self) {
+        let mut current_ptr = NonNull::new(self.head.load(Acquire));
+
+        let pass = self.pass.fetch_add(1, Relaxed);
+
+        let mut aux: u32 = 0;
+        let begin = unsafe { __rdtscp(&mut aux) };
+
+        let mut batch = ArrayVec::<&Node<I, O>, BATCH>::new();
+
+        while let Some(current_nonnull) = current_ptr {
+            let current = unsafe { current_nonnull.as_ref() };
+
+            if current.active.load(Acquire) && !current.complete.load(Acquire) {
+                unsafe {
+                    (*current.age.get()) = pass;
+
+                    if batch.is_full() {
+                        self.serve_batch(&mut batch, begin);
+                    }
+                }
+
+                batch.push(current);
+            }
+
+            current_ptr = NonNull::new(current.next.load(Acquire));
+        }
+
+        unsafe {
+            self.serve_batch(&mut batch, begin);
+        }
+
+        #[cfg(feature = "combiner_stat")]
+        unsafe {
+            let end = __rdtscp(&mut aux);
+
+            (*self.local_node.get().unwrap().get()).combiner_time_stat += end - begin;
+        }
+    }
+
+    unsafe fn clean_unactive_node(&self, head: &AtomicPtr<Node<I, O>>, pass: u32) {
+        let previous_ptr = NonNull::new(head.load(Acquire)).unwrap();
+
+        let mut previous_nonnull = previous_ptr;
+
+        let mut current_ptr = NonNull::new(*previous_nonnull.as_ref().next.as_ptr());
+
+        while let Some(current_nonnull) = current_ptr {
+            let current = current_nonnull.as_ref();
+            let previous = previous_nonnull.as_ref();
+
+            if pass - (*current.age.get()) > CLEAN_UP_AGE {
+                (*previous.next.as_ptr()) = *current.next.as_ptr();
+                (*current.next.as_ptr()) = null_mut();
+                current.active.store(false, Release);
+                current_ptr = NonNull::new(previous.next.load(Acquire));
+                continue;
+            }
+
+            previous_nonnull = current_nonnull;
+            current_ptr = NonNull::new(current.next.load(Acquire));
+        }
+    }
+}
+
+impl<T, I, O, F, L> Drop for FCEDF<T, I, O, F, L>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: Fn(&mut T, I) -> O,
+    L: RawMutex,
+{
+    fn drop(&mut self) {
+        nesting::forget(self);
+    }
+}
+
+unsafe impl<T, I, O, F, L> DLock2<I, O> for FCEDF<T, I, O, F, L>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
+    L: RawMutex + Send + Sync,
+{
+    fn lock(&self, data: I) -> O {
+        self.lock_with_deadline(data, NO_DEADLINE)
+    }
+
+    #[cfg(feature = "combiner_stat")]
+    fn get_combine_time(&self) -> Option<u64> {
+        unsafe { self.local_node.get().map(|x| (*x.get()).combiner_time_stat) }
+    }
+
+    fn get_deadline_miss_count(&self) -> Option<u64> {
+        unsafe {
+            self.local_node
+                .get()
+                .map(|x| (*x.get()).deadline_misses.load(Relaxed))
+        }
+    }
+}
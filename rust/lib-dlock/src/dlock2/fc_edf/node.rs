@@ -0,0 +1,46 @@
+use std::{
+    cell::{SyncUnsafeCell, UnsafeCell},
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64},
+};
+
+use crate::dlock2::slot::Slot;
+
+use crossbeam::utils::CachePadded;
+
+pub struct Node<I, O> {
+    pub age: UnsafeCell<u32>,
+    pub active: CachePadded<AtomicBool>,
+    pub data: SyncUnsafeCell<Slot<I, O>>,
+    /// Deadline (tsc) of the pending request, `NO_DEADLINE` if it has none
+    pub deadline: SyncUnsafeCell<u64>,
+    pub complete: AtomicBool,
+    pub next: AtomicPtr<Node<I, O>>,
+    /// Usage and the tsc at which it was last decayed (combiner only)
+    pub usage: SyncUnsafeCell<u64>,
+    pub usage_stamp: SyncUnsafeCell<u64>,
+    pub deadline_misses: AtomicU64,
+    #[cfg(feature = "combiner_stat")]
+    pub combiner_time_stat: u64,
+}
+
+impl<I, O> Node<I, O> {
+    pub(crate) fn new() -> Node<I, O>
+    where
+        I: Send,
+        O: Send,
+    {
+        Node {
+            age: 0.into(),
+            active: AtomicBool::new(false).into(),
+            complete: AtomicBool::new(false),
+            data: SyncUnsafeCell::new(Slot::empty()),
+            deadline: SyncUnsafeCell::new(u64::MAX),
+            next: AtomicPtr::default(),
+            usage: SyncUnsafeCell::new(0),
+            usage_stamp: SyncUnsafeCell::new(0),
+            deadline_misses: AtomicU64::new(0),
+            #[cfg(feature = "combiner_stat")]
+            combiner_time_stat: 0,
+        }
+    }
+}
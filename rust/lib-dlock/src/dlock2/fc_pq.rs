@@ -1,7 +1,13 @@
 use crate::spin_lock::RawSpinLock;
 
+use self::{admission::WorkConserving, policy::LeastUsage};
+
+pub mod admission;
+pub mod locality;
 mod lock;
 mod node;
+pub mod policy;
 
-pub type FCPQ<T, I, PQ, F, L = RawSpinLock> = lock::FCPQ<T, I, PQ, F, L>;
-pub type UsageNode<'a, I> = lock::UsageNode<'a, I>;
+pub type FCPQ<T, I, O, PQ, F, L = RawSpinLock, A = WorkConserving, P = LeastUsage> =
+    lock::FCPQ<T, I, O, PQ, F, L, A, P>;
+pub type UsageNode<'a, I, O> = lock::UsageNode<'a, I, O>;
@@ -0,0 +1,54 @@
+//! Admission control for `FCPQ`.
+//!
+//! Before serving a pending request the combiner asks the admission policy
+//! whether the owner should be held out of the job queue for a few combining
+//! passes. Held requests are still served in a pass that finds nothing else
+//! pending, so no policy can idle the lock.
+
+use std::fmt::Debug;
+
+pub trait Admission: Send + Sync + Debug {
+    /// Number of combining passes a pending request should be held out for,
+    /// given the usage of its owner and the average usage of all threads in
+    /// the job queue. Zero admits the request.
+    fn hold_passes(&self, usage: u64, average_usage: u64) -> u64;
+}
+
+/// Serve whoever tops the job queue (the original `FCPQ` behaviour).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WorkConserving;
+
+impl Admission for WorkConserving {
+    #[inline(always)]
+    fn hold_passes(&self, _: u64, _: u64) -> u64 {
+        0
+    }
+}
+
+/// Hold out threads whose usage exceeds `threshold` times the average for
+/// `passes` combining passes. Once a hold expires the thread is admitted at
+/// least once before it can be held again.
+#[derive(Debug, Clone, Copy)]
+pub struct SoftBan {
+    pub threshold: f64,
+    pub passes: u64,
+}
+
+impl Default for SoftBan {
+    fn default() -> Self {
+        Self {
+            threshold: 2.0,
+            passes: 1,
+        }
+    }
+}
+
+impl Admission for SoftBan {
+    fn hold_passes(&self, usage: u64, average_usage: u64) -> u64 {
+        if usage as f64 > self.threshold * average_usage as f64 {
+            self.passes
+        } else {
+            0
+        }
+    }
+}
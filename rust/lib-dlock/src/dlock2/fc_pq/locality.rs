@@ -0,0 +1,20 @@
+//! Locality-aware tie-breaking for `FCPQ`.
+//!
+//! Every node records the CPU and cluster its thread registered from. When
+//! the request on top of the job queue belongs to another cluster than the
+//! combiner, the combiner looks a few nodes further for a pending request of
+//! its own cluster whose key is within `epsilon` of the top, and serves that
+//! one first.
+
+use crate::topology::Topology;
+
+/// Nodes the combiner looks at beyond the top of the job queue
+pub const LOCALITY_WINDOW: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Locality {
+    pub topology: Topology,
+    /// Largest key difference to the top of the job queue that still counts
+    /// as a tie
+    pub epsilon: u64,
+}
@@ -1,14 +1,16 @@
+use arrayvec::ArrayVec;
 use derivative::Derivative;
 use lock_api::RawMutex;
 use ringbuffer::{ConstGenericRingBuffer, RingBuffer};
 use std::fmt::Debug;
-use std::mem::MaybeUninit;
+use std::mem;
 use std::thread::current;
 use std::{
     arch::x86_64::__rdtscp,
     cell::SyncUnsafeCell,
     ptr,
     sync::atomic::{AtomicPtr, Ordering::*},
+    time::{Duration, Instant},
 };
 
 use crossbeam::utils::{Backoff, CachePadded};
@@ -17,87 +19,181 @@ use thread_local::ThreadLocal;
 
 use crate::{
     atomic_extension::AtomicExtension,
-    dlock2::{DLock2, DLock2Delegate},
+    dlock2::{
+        batch::{BatchDelegate, Batcher},
+        group::Groups,
+        nesting,
+        slot::Slot,
+        usage_decay::UsageDecay,
+        DLock2, DLock2Delegate,
+    },
     sequential_priority_queue::SequentialPriorityQueue,
     spin_lock::RawSpinLock,
+    topology::current_cpu,
 };
 
 mod buffer;
 
 use self::buffer::ConcurrentRingBuffer;
 
-use super::node::Node;
+use super::{
+    admission::{Admission, WorkConserving},
+    locality::{Locality, LOCALITY_WINDOW},
+    node::{Node, UNBOUND},
+    policy::{CombiningPolicy, LeastUsage},
+};
 
 const CLEAN_UP_AGE: u32 = 500;
 
 #[derive(Derivative, Debug)]
 #[derivative(PartialEq, Eq, PartialOrd, Ord)]
-pub struct UsageNode<'a, I> {
-    usage: u64,
+pub struct UsageNode<'a, I, O> {
+    key: u64,
     tie_breaker: u64,
     #[derivative(PartialEq = "ignore", PartialOrd = "ignore", Ord = "ignore")]
-    node: &'a Node<I>,
+    usage: u64,
+    #[derivative(PartialEq = "ignore", PartialOrd = "ignore", Ord = "ignore")]
+    group: usize,
+    #[derivative(PartialEq = "ignore", PartialOrd = "ignore", Ord = "ignore")]
+    node: &'a Node<I, O>,
 }
 
-impl<T> Clone for UsageNode<'_, T> {
+impl<I, O> Clone for UsageNode<'_, I, O> {
     fn clone(&self) -> Self {
         UsageNode {
-            usage: self.usage,
+            key: self.key,
             tie_breaker: self.tie_breaker,
+            usage: self.usage,
+            group: self.group,
             node: self.node,
         }
     }
 }
 
-impl<T> Copy for UsageNode<'_, T> {}
+impl<I, O> Copy for UsageNode<'_, I, O> {}
+
+unsafe impl<'a, I: Send, O: Send> Sync for UsageNode<'a, I, O> {}
 
-unsafe impl<'a, I: Send> Sync for UsageNode<'a, I> {}
+/// Combiner-owned bookkeeping of the nodes in the job queue
+#[derive(Debug, Default)]
+struct Accounting {
+    pass: u64,
+    total_usage: u64,
+    num_nodes: u64,
+}
 
 #[derive(Debug)]
-pub struct FCPQ<T, I, PQ, F, L>
+pub struct FCPQ<T, I, O, PQ, F, L, A = WorkConserving, P = LeastUsage>
 where
     T: Send + Sync,
     I: Send + 'static,
-    PQ: SequentialPriorityQueue<UsageNode<'static, I>> + Debug,
-    F: Fn(&mut T, I) -> I,
+    O: Send + 'static,
+    PQ: SequentialPriorityQueue<UsageNode<'static, I, O>> + Debug,
+    F: Fn(&mut T, I) -> O,
     L: RawMutex,
+    A: Admission,
+    P: CombiningPolicy,
 {
     combiner_lock: CachePadded<L>,
     delegate: F,
-    job_queue: SyncUnsafeCell<PQ>,
-    waiting_nodes: ConcurrentRingBuffer<(AtomicPtr<Node<I>>, u64), 64>,
+    admission: A,
+    policy: SyncUnsafeCell<P>,
+    usage_decay: UsageDecay,
+    combiner_credit: f64,
+    locality: Option<Locality>,
+    accounting: SyncUnsafeCell<Accounting>,
+    groups: Groups,
+    /// One job queue per group, indexed by group id
+    job_queues: SyncUnsafeCell<Vec<PQ>>,
+    waiting_nodes: ConcurrentRingBuffer<(AtomicPtr<Node<I, O>>, u64), 64>,
     data: SyncUnsafeCell<T>,
-    local_node: ThreadLocal<SyncUnsafeCell<Node<I>>>,
+    local_node: ThreadLocal<SyncUnsafeCell<Node<I, O>>>,
+    batch: Batcher<T, I, O, Node<I, O>>,
 }
 
-impl<T, I, PQ, F, L> FCPQ<T, I, PQ, F, L>
+impl<T, I, O, PQ, F, L> FCPQ<T, I, O, PQ, F, L, WorkConserving, LeastUsage>
 where
     T: Send + Sync,
     I: Send,
-    PQ: SequentialPriorityQueue<UsageNode<'static, I>> + Debug,
-    F: DLock2Delegate<T, I>,
+    O: Send,
+    PQ: SequentialPriorityQueue<UsageNode<'static, I, O>> + Debug,
+    F: DLock2Delegate<T, I, O>,
     L: RawMutex,
 {
     pub fn new(data: T, delegate: F) -> Self {
+        Self::with_admission_and_policy(data, delegate, WorkConserving, LeastUsage)
+    }
+}
+
+impl<T, I, O, PQ, F, L, A> FCPQ<T, I, O, PQ, F, L, A, LeastUsage>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    PQ: SequentialPriorityQueue<UsageNode<'static, I, O>> + Debug,
+    F: DLock2Delegate<T, I, O>,
+    L: RawMutex,
+    A: Admission,
+{
+    pub fn with_admission(data: T, delegate: F, admission: A) -> Self {
+        Self::with_admission_and_policy(data, delegate, admission, LeastUsage)
+    }
+}
+
+impl<T, I, O, PQ, F, L, P> FCPQ<T, I, O, PQ, F, L, WorkConserving, P>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    PQ: SequentialPriorityQueue<UsageNode<'static, I, O>> + Debug,
+    F: DLock2Delegate<T, I, O>,
+    L: RawMutex,
+    P: CombiningPolicy,
+{
+    pub fn with_policy(data: T, delegate: F, policy: P) -> Self {
+        Self::with_admission_and_policy(data, delegate, WorkConserving, policy)
+    }
+}
+
+impl<T, I, O, PQ, F, L, A, P> FCPQ<T, I, O, PQ, F, L, A, P>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    PQ: SequentialPriorityQueue<UsageNode<'static, I, O>> + Debug,
+    F: DLock2Delegate<T, I, O>,
+    L: RawMutex,
+    A: Admission,
+    P: CombiningPolicy,
+{
+    pub fn with_admission_and_policy(data: T, delegate: F, admission: A, policy: P) -> Self {
         Self {
             combiner_lock: CachePadded::new(L::INIT),
             delegate,
-            job_queue: PQ::new().into(),
+            admission,
+            policy: policy.into(),
+            usage_decay: UsageDecay::None,
+            combiner_credit: 0.0,
+            locality: None,
+            accounting: Accounting::default().into(),
+            groups: Groups::new(),
+            job_queues: vec![PQ::new()].into(),
             waiting_nodes: ConcurrentRingBuffer::new(),
             data: SyncUnsafeCell::new(data),
             local_node: ThreadLocal::new(),
+            batch: Batcher::new(),
         }
     }
 
-    fn push_node(&self, node: &Node<I>) {
+    fn push_node(&self, node: &Node<I, O>) {
         node.active.store(true, Release);
         self.waiting_nodes.push((
-            AtomicPtr::new(node as *const _ as *mut Node<I>),
+            AtomicPtr::new(node as *const _ as *mut Node<I, O>),
             current().id().as_u64().into(),
         ));
     }
 
-    fn push_if_unactive(&self, node: &mut Node<I>) {
+    fn push_if_unactive(&self, node: &mut Node<I, O>) {
         if node.active.load(Acquire) {
             return;
         }
@@ -105,18 +201,280 @@ where
         self.push_node(node);
     }
 
-    fn combine(&self) {
+    pub fn with_usage_decay(mut self, usage_decay: UsageDecay) -> Self {
+        self.usage_decay = usage_decay;
+        self
+    }
+
+    /// Credit the given fraction of the time a combiner spends serving other
+    /// threads back to its usage.
+    pub fn with_combiner_credit(mut self, fraction: f64) -> Self {
+        self.combiner_credit = fraction;
+        self
+    }
+
+    /// Prefer pending requests of the combiner's cluster among those whose
+    /// key is within `locality.epsilon` of the top of the job queue. `None`
+    /// always serves the top.
+    pub fn with_locality(mut self, locality: Option<Locality>) -> Self {
+        self.locality = locality;
+        self
+    }
+
+    /// Hand all requests of a combining pass to `batch` at once instead of
+    /// running the delegate on every request. The length of a batch is
+    /// charged to its requests in equal shares.
+    pub fn with_batch_delegate(mut self, batch: impl BatchDelegate<T, I, O> + 'static) -> Self {
+        self.batch.set(batch);
+        self
+    }
+
+    pub fn is_batched(&self) -> bool {
+        self.batch.is_enabled()
+    }
+
+    /// Put the calling thread into `cluster` instead of the cluster of the
+    /// CPU it registered from.
+    pub fn bind_cluster(&self, cluster: usize) {
+        unsafe { (*self.local_node().get()).cluster.store(cluster, Relaxed) };
+    }
+
+    fn local_node(&self) -> &SyncUnsafeCell<Node<I, O>> {
+        self.local_node
+            .get_or(|| SyncUnsafeCell::new(Node::new(self.groups.register(), current_cpu())))
+    }
+
+    unsafe fn enqueue(&self, current: UsageNode<'static, I, O>) {
+        let job_queues = &mut *self.job_queues.get();
+
+        if job_queues.len() <= current.group {
+            job_queues.resize_with(current.group + 1, PQ::new);
+        }
+
+        job_queues[current.group].push(current);
+    }
+
+    /// Pop from the job queue of the group with the least usage relative to
+    /// its weight among those with queued nodes. With locality enabled a
+    /// nearby request of the cluster of `home`, the combiner's node, may be
+    /// taken instead of the top.
+    unsafe fn dequeue(&self, home: &Node<I, O>) -> Option<UsageNode<'static, I, O>> {
+        let job_queues = &mut *self.job_queues.get();
+
+        let queue = if job_queues.len() == 1 {
+            &mut job_queues[0]
+        } else {
+            job_queues
+                .iter_mut()
+                .enumerate()
+                .filter(|(_, queue)| queue.len() > 0)
+                .min_by_key(|(group, _)| self.groups.virtual_time(*group))
+                .map(|(_, queue)| queue)?
+        };
+
+        let first = queue.pop()?;
+
+        match self.locality.as_ref() {
+            Some(locality) => Some(self.nearby(queue, first, home, locality)),
+            None => Some(first),
+        }
+    }
+
+    fn cluster_of(node: &Node<I, O>, locality: &Locality) -> usize {
+        match node.cluster.load(Relaxed) {
+            UNBOUND => locality.topology.cluster_of(node.cpu),
+            cluster => cluster,
+        }
+    }
+
+    /// The first pending node of cluster `home` among those within `epsilon`
+    /// of `first`, or `first` if there is none. Skipped nodes are put back.
+    unsafe fn nearby(
+        &self,
+        queue: &mut PQ,
+        first: UsageNode<'static, I, O>,
+        home: &Node<I, O>,
+        locality: &Locality,
+    ) -> UsageNode<'static, I, O> {
+        let home = Self::cluster_of(home, locality);
+        let is_local = |current: &UsageNode<I, O>| {
+            Self::cluster_of(current.node, locality) == home && !current.node.complete.load(Acquire)
+        };
+
+        if first.node.complete.load(Acquire) || is_local(&first) {
+            return first;
+        }
+
+        let limit = first.key.saturating_add(locality.epsilon);
+        let mut skipped = ArrayVec::<UsageNode<I, O>, LOCALITY_WINDOW>::new();
+        let mut chosen = None;
+
+        while !skipped.is_full() && queue.peek().is_some_and(|next| next.key <= limit) {
+            let next = queue.pop().unwrap_unchecked();
+
+            if is_local(&next) {
+                chosen = Some(next);
+                break;
+            }
+
+            skipped.push(next);
+        }
+
+        for current in skipped {
+            queue.push(current);
+        }
+
+        match chosen {
+            Some(chosen) => {
+                queue.push(first);
+                chosen
+            }
+            None => first,
+        }
+    }
+
+    /// Whether a pending request should be held out of this combining pass.
+    unsafe fn hold(&self, current: &UsageNode<I, O>, accounting: &Accounting) -> bool {
+        let held_until = &mut *current.node.held_until.get();
+
+        if *held_until != 0 {
+            if accounting.pass < *held_until {
+                return true;
+            }
+
+            // the hold expired, admit the request once
+            *held_until = 0;
+            return false;
+        }
+
+        let average = accounting.total_usage / accounting.num_nodes.max(1);
+        let passes = self.admission.hold_passes(current.usage, average);
+
+        if passes == 0 {
+            return false;
+        }
+
+        *held_until = accounting.pass + passes;
+        current.node.soft_bans.fetch_add(1, Relaxed);
+        true
+    }
+
+    /// Bring the usage of `current` up to date before its request is served.
+    unsafe fn refresh_usage(
+        &self,
+        current: &mut UsageNode<I, O>,
+        accounting: &mut Accounting,
+        now: u64,
+    ) {
+        let node = current.node;
+
+        let usage = current.usage;
+        current.usage = self
+            .usage_decay
+            .update(usage, &mut *node.usage_stamp.get(), now)
+            .saturating_sub(mem::take(&mut *node.credit.get()));
+        accounting.total_usage -= usage - current.usage;
+    }
+
+    /// Charge `cs` cycles of critical section to `current`.
+    unsafe fn charge(&self, current: &mut UsageNode<I, O>, accounting: &mut Accounting, cs: u64) {
+        current.usage += cs;
+        self.groups.charge(current.group, cs);
+        current.key = (*self.policy.get()).on_serve(current.key, current.usage, cs);
+        accounting.total_usage += cs;
+    }
+
+    /// Serve the pending request of `current`, returns its critical-section
+    /// length.
+    unsafe fn serve(&self, current: &mut UsageNode<I, O>, accounting: &mut Accounting) -> u64 {
         let mut aux: u32 = 0;
-        let mut begin: u64;
+        let node = current.node;
 
-        unsafe {
-            begin = __rdtscp(&mut aux);
+        // alternatively we can potentially save one __rdtscp by using `end` here
+        // which would result in a slightly inaccurate usage
+        let begin = __rdtscp(&mut aux);
+
+        self.refresh_usage(current, accounting, begin);
+
+        node.data.get().write(Slot::output((self.delegate)(
+            self.data.get().as_mut().unwrap_unchecked(),
+            (*node.data.get()).take_input(),
+        )));
+
+        let end = __rdtscp(&mut aux);
+
+        self.charge(current, accounting, end - begin);
+
+        node.complete.store(true, Release);
+
+        end - begin
+    }
+
+    /// Move the pending request of `current` into the batch, the node stays
+    /// out of the job queue until the batch is served.
+    unsafe fn defer(&self, current: &mut UsageNode<I, O>, accounting: &mut Accounting) {
+        self.refresh_usage(current, accounting, __rdtscp(&mut 0));
+        self.batch
+            .push(current.node, (*current.node.data.get()).take_input());
+    }
+
+    /// Serve the batch of `batched`, returns the critical-section length
+    /// charged to each of them.
+    unsafe fn serve_batch(
+        &self,
+        batched: &mut [UsageNode<I, O>],
+        accounting: &mut Accounting,
+    ) -> u64 {
+        let mut aux: u32 = 0;
+
+        let begin = __rdtscp(&mut aux);
+
+        let len = self.batch.run(
+            self.data.get().as_mut().unwrap_unchecked(),
+            &self.delegate,
+            |node, output| {
+                node.data.get().write(Slot::output(output));
+            },
+        );
+
+        let end = __rdtscp(&mut aux);
+        let share = (end - begin) / len.max(1) as u64;
+
+        for current in batched.iter_mut() {
+            self.charge(current, accounting, share);
+            current.node.complete.store(true, Release);
+        }
+
+        share
+    }
+
+    /// Take a node out of the job queue if its request has been consumed,
+    /// otherwise put it back.
+    unsafe fn retire_or_requeue(
+        &self,
+        current: UsageNode<'static, I, O>,
+        accounting: &mut Accounting,
+    ) {
+        if current.node.complete.load(Acquire) {
+            accounting.total_usage -= current.usage;
+            accounting.num_nodes -= 1;
+            current.node.usage.store_release(current.usage);
+            *current.node.key.get() = current.key;
+            current.node.active.store_release(false);
+        } else {
+            self.enqueue(current);
         }
+    }
+
+    fn combine(&self) {
+        let begin = unsafe { __rdtscp(&mut 0) };
 
         const H: usize = 64;
 
         // only one thread would combine so this is safe
-        let job_queue: &mut PQ = unsafe { &mut *self.job_queue.get() };
+        let accounting: &mut Accounting = unsafe { &mut *self.accounting.get() };
+
+        accounting.pass += 1;
 
         if !self.waiting_nodes.empty() {
             let iterator = unsafe { self.waiting_nodes.iter() };
@@ -129,9 +487,23 @@ where
                 count += 1;
                 unsafe {
                     let node = &*node.load_acquire();
-                    job_queue.push(UsageNode {
-                        usage: node.usage.load_acquire(),
+                    let usage = self
+                        .usage_decay
+                        .update(
+                            node.usage.load_acquire(),
+                            &mut *node.usage_stamp.get(),
+                            begin,
+                        )
+                        .saturating_sub(mem::take(&mut *node.credit.get()));
+
+                    accounting.total_usage += usage;
+                    accounting.num_nodes += 1;
+
+                    self.enqueue(UsageNode {
+                        key: (*self.policy.get()).on_insert(*node.key.get(), usage),
                         tie_breaker: id,
+                        usage,
+                        group: node.group.load(Relaxed),
                         node,
                     });
                 }
@@ -140,49 +512,54 @@ where
             assert!(count == size.0);
         }
 
-        let mut buffer = ConstGenericRingBuffer::<UsageNode<I>, 4>::new();
+        let mut buffer = ConstGenericRingBuffer::<UsageNode<I, O>, 4>::new();
+        let mut held = ArrayVec::<UsageNode<I, O>, H>::new();
+        let mut batched = ArrayVec::<UsageNode<I, O>, H>::new();
+        let mut served = 0;
+        let mut exhausted = false;
+
+        let own_node = self.local_node.get().unwrap().get() as *const Node<I, O>;
+        let mut own_cs = 0;
 
         unsafe {
             for _ in 0..H {
-                let current = job_queue.pop();
+                let current = self.dequeue(&*own_node);
 
                 if current.is_none() {
+                    exhausted = true;
                     break;
                 }
 
                 let mut current = current.unwrap_unchecked();
 
-                let node = current.node;
-
-                if !node.complete.load(Acquire) {
-                    // alternatively we can potentially save one __rdtscp by using `end` here
-                    // which would result in a slightly inaccurate usage
-                    begin = __rdtscp(&mut aux);
-
-                    node.data.get().write(MaybeUninit::new(
-                        (self.delegate)(
-                            self.data.get().as_mut().unwrap_unchecked(),
-                            node.data.get().read().assume_init(),
-                        ),
-                    ));
+                // a withdrawn request is retired once its owner marks it
+                // complete
+                let pending =
+                    !current.node.complete.load(Acquire) && !current.node.claim.is_withdrawn();
 
-                    let end = __rdtscp(&mut aux);
-
-                    current.usage += end - begin;
+                if pending && self.hold(&current, accounting) {
+                    held.push(current);
+                    continue;
+                }
 
-                    node.complete.store(true, Release);
+                if pending && current.node.claim.serve() {
+                    if self.batch.is_enabled() {
+                        self.defer(&mut current, accounting);
+                        batched.push(current);
+                    } else {
+                        let cs = self.serve(&mut current, accounting);
+                        if ptr::eq(current.node, own_node) {
+                            own_cs += cs;
+                        }
 
-                    job_queue.push(current);
+                        self.enqueue(current);
+                    }
+                    served += 1;
                 } else {
                     // if the buffer is full then push the nodes back to the job queue
                     if buffer.is_full() {
                         for node in buffer.drain() {
-                            if node.node.complete.load(Acquire) {
-                                node.node.usage.store_release(node.usage);
-                                node.node.active.store_release(false);
-                            } else {
-                                job_queue.push(node);
-                            }
+                            self.retire_or_requeue(node, accounting);
                         }
                     }
 
@@ -192,68 +569,189 @@ where
             }
 
             for node in buffer.drain() {
-                if node.node.complete.load(Acquire) {
-                    node.node.usage.store_release(node.usage);
-                    node.node.active.store_release(false);
-                } else {
-                    job_queue.push(node);
+                self.retire_or_requeue(node, accounting);
+            }
+
+            // nothing else is pending, held requests are served anyway
+            let serve_held = served == 0 && exhausted;
+
+            for mut current in held {
+                if serve_held && current.node.claim.serve() {
+                    if self.batch.is_enabled() {
+                        self.defer(&mut current, accounting);
+                        batched.push(current);
+                        continue;
+                    }
+
+                    let cs = self.serve(&mut current, accounting);
+                    if ptr::eq(current.node, own_node) {
+                        own_cs += cs;
+                    }
+                }
+                self.enqueue(current);
+            }
+
+            if !batched.is_empty() {
+                let share = self.serve_batch(&mut batched, accounting);
+
+                for current in batched {
+                    if ptr::eq(current.node, own_node) {
+                        own_cs += share;
+                    }
+                    self.enqueue(current);
                 }
             }
         }
 
+        let end = unsafe { __rdtscp(&mut 0) };
+
+        // credited at the combiner's next serve, its usage may be in the job
+        // queue right now
+        if self.combiner_credit > 0.0 {
+            let others = (end - begin).saturating_sub(own_cs);
+            unsafe {
+                *(*own_node).credit.get() += (others as f64 * self.combiner_credit) as u64;
+            }
+        }
+
         #[cfg(feature = "combiner_stat")]
         unsafe {
-            let end = __rdtscp(&mut aux);
-
             (*self.local_node.get().unwrap().get()).combiner_time_stat += end - begin;
         }
     }
-}
 
-unsafe impl<T, PQ, I, F, L> DLock2<I> for FCPQ<T, I, PQ, F, L>
-where
-    T: Send + Sync,
-    PQ: SequentialPriorityQueue<UsageNode<'static, I>> + Debug + Send + Sync,
-    I: Send,
-    F: DLock2Delegate<T, I>,
-    L: RawMutex + Send + Sync,
-{
-    fn lock(&self, data: I) -> I {
-        let node = self.local_node.get_or(|| SyncUnsafeCell::new(Node::new()));
+    /// Wait until no combining pass that saw the request last withdrawn from
+    /// `node` as pending is running, or until `deadline` passes. Such a pass
+    /// would serve the next request of the node a second time, passes
+    /// starting later see the withdrawn request complete.
+    fn settle(&self, node: &Node<I, O>, deadline: Option<Instant>) -> bool {
+        if !node.claim.is_withdrawn() {
+            return true;
+        }
 
-        let node = unsafe { &mut *node.get() };
+        let backoff = Backoff::new();
+        while !self.combiner_lock.try_lock() {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return false;
+            }
+            backoff.snooze();
+        }
 
-        node.data = SyncUnsafeCell::new(MaybeUninit::new(data));
-        node.complete.store(false, Release);
+        unsafe { self.combiner_lock.unlock() };
+        true
+    }
 
-        'outer: loop {
+    /// Combine or wait until the request in `node` is served, or until
+    /// `deadline` passes. Returns whether the request was served.
+    fn wait_node(&self, node: &mut Node<I, O>, deadline: Option<Instant>) -> bool {
+        loop {
             self.push_if_unactive(node);
 
             if self.combiner_lock.try_lock() {
-                self.combine();
+                nesting::combining(self, || self.combine());
 
                 unsafe {
                     self.combiner_lock.unlock();
                 }
 
                 if node.complete.load(Acquire) {
-                    break 'outer;
+                    return true;
                 }
             } else {
                 let backoff = Backoff::new();
                 loop {
                     if node.complete.load(Acquire) {
-                        break 'outer;
+                        return true;
                     }
                     backoff.snooze();
                     if backoff.is_completed() {
-                        continue 'outer;
+                        break;
                     }
                 }
             }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return false;
+            }
+        }
+    }
+}
+
+impl<T, I, O, PQ, F, L, A, P> Drop for FCPQ<T, I, O, PQ, F, L, A, P>
+where
+    T: Send + Sync,
+    I: Send + 'static,
+    O: Send + 'static,
+    PQ: SequentialPriorityQueue<UsageNode<'static, I, O>> + Debug,
+    F: Fn(&mut T, I) -> O,
+    L: RawMutex,
+    A: Admission,
+    P: CombiningPolicy,
+{
+    fn drop(&mut self) {
+        nesting::forget(self);
+    }
+}
+
+unsafe impl<T, PQ, I, O, F, L, A, P> DLock2<I, O> for FCPQ<T, I, O, PQ, F, L, A, P>
+where
+    T: Send + Sync,
+    PQ: SequentialPriorityQueue<UsageNode<'static, I, O>> + Debug + Send + Sync,
+    I: Send,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
+    L: RawMutex + Send + Sync,
+    A: Admission,
+    P: CombiningPolicy,
+{
+    fn lock(&self, data: I) -> O {
+        nesting::enter(self);
+
+        let node = unsafe { &mut *self.local_node().get() };
+        self.settle(node, None);
+
+        node.data = SyncUnsafeCell::new(Slot::input(data));
+        node.claim.reset(false);
+        node.complete.store(false, Release);
+
+        self.wait_node(node, None);
+
+        unsafe { (*node.data.get()).take_output() }
+    }
+
+    fn supports_timeout(&self) -> bool {
+        true
+    }
+
+    /// A request held out by admission counts as waiting, a deadline within
+    /// the hold withdraws it.
+    fn lock_timeout(&self, data: I, timeout: Duration) -> Result<O, I> {
+        nesting::enter(self);
+
+        let deadline = Instant::now() + timeout;
+        let node = unsafe { &mut *self.local_node().get() };
+
+        // a pass that saw the last withdrawn request still runs
+        if !self.settle(node, Some(deadline)) {
+            return Err(data);
+        }
+
+        node.data = SyncUnsafeCell::new(Slot::input(data));
+        node.claim.reset(true);
+        node.complete.store(false, Release);
+
+        if !self.wait_node(node, Some(deadline)) {
+            if node.claim.withdraw() {
+                let data = unsafe { (*node.data.get()).take_input() };
+                node.complete.store(true, Release);
+                return Err(data);
+            }
+
+            // a combiner took the request up before the deadline
+            self.wait_node(node, None);
         }
 
-        unsafe { node.data.get().read().assume_init() }
+        Ok(unsafe { (*node.data.get()).take_output() })
     }
 
     #[cfg(feature = "combiner_stat")]
@@ -264,4 +762,25 @@ where
                 .map(|x| (*x.get()).combiner_time_stat)
         }
     }
+
+    fn get_soft_ban_count(&self) -> Option<u64> {
+        unsafe {
+            self.local_node
+                .get()
+                .map(|x| (*x.get()).soft_bans.load(Relaxed))
+        }
+    }
+
+    /// The new group takes effect the next time the thread's node enters
+    /// the job queue.
+    fn join_group(&self, name: &str, weight: u64) -> Option<usize> {
+        let node = unsafe { &mut *self.local_node().get() };
+        let group = self.groups.join(name, weight, node.group.load(Relaxed));
+        node.group.store(group, Relaxed);
+        Some(group)
+    }
+
+    fn groups(&self) -> Option<&Groups> {
+        Some(&self.groups)
+    }
 }
@@ -1,8 +1,13 @@
-use std::{cell::SyncUnsafeCell, mem::MaybeUninit, sync::atomic::{AtomicBool, AtomicU64}};
+use std::{
+    cell::SyncUnsafeCell,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize},
+};
 
 use atomic_enum::atomic_enum;
 use crossbeam::utils::CachePadded;
 
+use crate::dlock2::{claim::Claim, slot::Slot};
+
 #[atomic_enum]
 #[derive(PartialEq)]
 pub enum ActiveState {
@@ -11,26 +16,54 @@ pub enum ActiveState {
     Active,
 }
 
+pub const UNBOUND: usize = usize::MAX;
+
 #[derive(Debug)]
-pub struct Node<T> {
+pub struct Node<I, O> {
     pub usage: AtomicU64,
+    pub group: AtomicUsize,
+    /// CPU the thread registered from
+    pub cpu: usize,
+    /// Cluster the thread was bound to, `UNBOUND` to use the cluster of `cpu`
+    pub cluster: AtomicUsize,
+    /// tsc at which `usage` was last decayed (combiner only)
+    pub usage_stamp: SyncUnsafeCell<u64>,
+    /// Job queue key while the node is inactive (combiner only)
+    pub key: SyncUnsafeCell<u64>,
     pub active: CachePadded<AtomicBool>,
-    pub data: SyncUnsafeCell<MaybeUninit<T>>,
+    pub data: SyncUnsafeCell<Slot<I, O>>,
     pub complete: AtomicBool,
+    pub claim: Claim,
+    /// Combining pass until which the node is held out (combiner only)
+    pub held_until: SyncUnsafeCell<u64>,
+    pub soft_bans: AtomicU64,
+    /// Combining time credited to the thread, taken off its usage at the
+    /// next serve (combiner only)
+    pub credit: SyncUnsafeCell<u64>,
     #[cfg(feature = "combiner_stat")]
     pub combiner_time_stat: u64,
 }
 
-impl<T> Node<T> {
-    pub(crate) fn new() -> Node<T>
+impl<I, O> Node<I, O> {
+    pub(crate) fn new(group: usize, cpu: usize) -> Node<I, O>
     where
-        T: Send,
+        I: Send,
+        O: Send,
     {
         Node {
             usage: AtomicU64::new(0),
+            group: AtomicUsize::new(group),
+            cpu,
+            cluster: AtomicUsize::new(UNBOUND),
+            usage_stamp: SyncUnsafeCell::new(0),
+            key: SyncUnsafeCell::new(0),
             active: AtomicBool::new(false).into(),
             complete: AtomicBool::new(false),
-            data: SyncUnsafeCell::new(MaybeUninit::uninit()),
+            claim: Claim::new(),
+            held_until: SyncUnsafeCell::new(0),
+            soft_bans: AtomicU64::new(0),
+            credit: SyncUnsafeCell::new(0),
+            data: SyncUnsafeCell::new(Slot::empty()),
             #[cfg(feature = "combiner_stat")]
             combiner_time_stat: 0,
         }
@@ -0,0 +1,112 @@
+//! Scheduling disciplines for `FCPQ`.
+//!
+//! The job queue serves the request with the smallest key first. A
+//! `CombiningPolicy` decides the key of a node when it enters the job queue
+//! and how the key changes after each of its requests is served. Keys
+//! persist in the node while its thread is inactive. Only the combiner calls
+//! into the policy.
+
+use std::fmt::Debug;
+
+pub trait CombiningPolicy: Send + Sync + Debug {
+    /// Key of a node entering the job queue, given the key it left with and
+    /// its (decayed) usage.
+    fn on_insert(&mut self, key: u64, usage: u64) -> u64;
+
+    /// Key after a request was served for `cs` cycles, `usage` already
+    /// includes `cs`.
+    fn on_serve(&mut self, key: u64, usage: u64, cs: u64) -> u64;
+}
+
+/// Serve the thread with the least usage (the original `FCPQ` ordering).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LeastUsage;
+
+impl CombiningPolicy for LeastUsage {
+    #[inline(always)]
+    fn on_insert(&mut self, _: u64, usage: u64) -> u64 {
+        usage
+    }
+
+    #[inline(always)]
+    fn on_serve(&mut self, _: u64, usage: u64, _: u64) -> u64 {
+        usage
+    }
+}
+
+/// Serve requests in the order they arrived at the job queue; a served
+/// thread goes to the back of the line.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Fifo {
+    arrival: u64,
+}
+
+impl CombiningPolicy for Fifo {
+    fn on_insert(&mut self, _: u64, _: u64) -> u64 {
+        self.arrival += 1;
+        self.arrival
+    }
+
+    fn on_serve(&mut self, _: u64, _: u64, _: u64) -> u64 {
+        self.arrival += 1;
+        self.arrival
+    }
+}
+
+/// Stride scheduling with equal tickets. Every thread advances its pass by
+/// the cycles it was served for, and a thread (re)joining the job queue
+/// starts no earlier than the global pass, so time spent inactive cannot be
+/// banked the way it is under `LeastUsage`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stride {
+    global_pass: u64,
+}
+
+impl CombiningPolicy for Stride {
+    fn on_insert(&mut self, key: u64, _: u64) -> u64 {
+        key.max(self.global_pass)
+    }
+
+    fn on_serve(&mut self, key: u64, _: u64, cs: u64) -> u64 {
+        self.global_pass = self.global_pass.max(key);
+        key + cs
+    }
+}
+
+/// Lottery scheduling with equal tickets: every key is a fresh draw, so each
+/// pending thread is equally likely to be served next.
+#[derive(Debug, Clone, Copy)]
+pub struct Lottery {
+    state: u64,
+}
+
+impl Lottery {
+    pub fn with_seed(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    // splitmix64, the combiner draws far too often for a shared generator
+    fn draw(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl Default for Lottery {
+    fn default() -> Self {
+        Self::with_seed(fastrand::u64(..))
+    }
+}
+
+impl CombiningPolicy for Lottery {
+    fn on_insert(&mut self, _: u64, _: u64) -> u64 {
+        self.draw()
+    }
+
+    fn on_serve(&mut self, _: u64, _: u64, _: u64) -> u64 {
+        self.draw()
+    }
+}
@@ -1,7 +1,6 @@
 use std::{
     arch::x86_64::__rdtscp,
     cell::SyncUnsafeCell,
-    mem::MaybeUninit,
     ptr::{self},
     sync::atomic::{AtomicPtr, Ordering::*},
 };
@@ -13,7 +12,7 @@ use lock_api::RawMutex;
 use thread_local::ThreadLocal;
 
 use crate::{
-    dlock2::{DLock2, DLock2Delegate},
+    dlock2::{nesting, slot::Slot, usage_decay::UsageDecay, DLock2, DLock2Delegate},
     spin_lock::RawSpinLock,
 };
 
@@ -23,45 +22,54 @@ const CLEAN_UP_AGE: u32 = 500;
 
 #[derive(Derivative)]
 #[derivative(Debug, PartialEq, Eq, PartialOrd, Ord)]
-struct UsageNode<I> {
+struct UsageNode<I, O> {
     usage: u64,
     #[derivative(PartialEq = "ignore", PartialOrd = "ignore", Ord = "ignore")]
-    node: AtomicPtr<Node<I>>,
+    node: AtomicPtr<Node<I, O>>,
 }
 
 #[derive(Debug)]
-pub struct FCSL<T, I, F, L>
+pub struct FCSL<T, I, O, F, L>
 where
     T: Send + Sync,
     I: Send + 'static,
-    F: Fn(&mut T, I) -> I,
+    O: Send + 'static,
+    F: Fn(&mut T, I) -> O,
     L: RawMutex,
 {
     combiner_lock: CachePadded<L>,
     delegate: F,
+    usage_decay: UsageDecay,
     data: SyncUnsafeCell<T>,
-    jobs: SkipSet<UsageNode<I>>,
-    local_node: ThreadLocal<SyncUnsafeCell<Node<I>>>,
+    jobs: SkipSet<UsageNode<I, O>>,
+    local_node: ThreadLocal<SyncUnsafeCell<Node<I, O>>>,
 }
 
-impl<T, I, F, L> FCSL<T, I, F, L>
+impl<T, I, O, F, L> FCSL<T, I, O, F, L>
 where
     T: Send + Sync,
     I: Send,
-    F: DLock2Delegate<T, I>,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
     L: RawMutex,
 {
     pub fn new(data: T, delegate: F) -> Self {
         Self {
             combiner_lock: CachePadded::new(L::INIT),
             delegate,
+            usage_decay: UsageDecay::None,
             data: SyncUnsafeCell::new(data),
             jobs: SkipSet::new(),
             local_node: ThreadLocal::new(),
         }
     }
 
-    fn push_node(&self, node: &mut Node<I>) {
+    pub fn with_usage_decay(mut self, usage_decay: UsageDecay) -> Self {
+        self.usage_decay = usage_decay;
+        self
+    }
+
+    fn push_node(&self, node: &mut Node<I, O>) {
         let usage = node.usage;
 
         let usage_node = UsageNode {
@@ -72,7 +80,7 @@ where
         self.jobs.insert(usage_node);
     }
 
-    fn push_if_unactive(&self, node: &mut Node<I>) {
+    fn push_if_unactive(&self, node: &mut Node<I, O>) {
         if node.active.load(Acquire) {
             return;
         }
@@ -101,16 +109,18 @@ where
                 let node = &mut *current.node.load(Acquire);
 
                 if !node.complete.load(Acquire) {
-                    node.data.get().write(MaybeUninit::new(
-                        (self.delegate)(
-                            self.data.get().as_mut().unwrap_unchecked(),
-                            node.data.get().read().assume_init(),
-                        ),
+                    *node.data.get() = Slot::output((self.delegate)(
+                        self.data.get().as_mut().unwrap_unchecked(),
+                        (*node.data.get()).take_input(),
                     ));
 
                     let end = __rdtscp(&mut aux);
 
-                    node.usage += end - begin;
+                    // decay before the node is inserted again by its owner
+                    node.usage = self
+                        .usage_decay
+                        .update(node.usage, &mut node.usage_stamp, begin)
+                        + (end - begin);
 
                     begin = end;
 
@@ -128,28 +138,42 @@ where
     }
 }
 
-unsafe impl<'a, T, I, F> DLock2<I> for FCSL<T, I, F, RawSpinLock>
+impl<T, I, O, F, L> Drop for FCSL<T, I, O, F, L>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: Fn(&mut T, I) -> O,
+    L: RawMutex,
+{
+    fn drop(&mut self) {
+        nesting::forget(self);
+    }
+}
+
+unsafe impl<'a, T, I, O, F> DLock2<I, O> for FCSL<T, I, O, F, RawSpinLock>
 where
     T: Send + Sync,
     I: Send,
-    F: DLock2Delegate<T, I>,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
 {
-    fn lock(&self, data: I) -> I {
+    fn lock(&self, data: I) -> O {
+        nesting::enter(self);
+
         let node = self.local_node.get_or(|| SyncUnsafeCell::new(Node::new()));
 
         let node = unsafe { &mut *node.get() };
 
-        node.data = SyncUnsafeCell::new(MaybeUninit::new(data));
+        node.data = SyncUnsafeCell::new(Slot::input(data));
         node.complete.store(false, Release);
 
         'outer: loop {
             self.push_if_unactive(node);
 
             if self.combiner_lock.try_lock() {
-                unsafe {
-                    self.combine();
-                    self.combiner_lock.unlock();
-                }
+                nesting::combining(self, || self.combine());
+                unsafe { self.combiner_lock.unlock() };
                 if node.complete.load(Acquire) {
                     break 'outer;
                 }
@@ -167,7 +191,7 @@ where
             }
         }
 
-        unsafe { node.data.get().read().assume_init() }
+        unsafe { (*node.data.get()).take_output() }
     }
 
     #[cfg(feature = "combiner_stat")]
@@ -1,30 +1,32 @@
-use std::{
-    cell::SyncUnsafeCell,
-    mem::MaybeUninit,
-    sync::atomic::AtomicBool,
-};
+use std::{cell::SyncUnsafeCell, sync::atomic::AtomicBool};
 
 use crossbeam::utils::CachePadded;
 
-pub struct Node<T> {
+use crate::dlock2::slot::Slot;
+
+pub struct Node<I, O> {
     pub usage: u64,
+    /// tsc at which `usage` was last decayed (combiner only)
+    pub usage_stamp: u64,
     pub active: CachePadded<AtomicBool>,
-    pub data: SyncUnsafeCell<MaybeUninit<T>>,
+    pub data: SyncUnsafeCell<Slot<I, O>>,
     pub complete: AtomicBool,
     #[cfg(feature = "combiner_stat")]
     pub combiner_time_stat: u64,
 }
 
-impl<T> Node<T> {
-    pub(crate) fn new() -> Node<T>
+impl<I, O> Node<I, O> {
+    pub(crate) fn new() -> Node<I, O>
     where
-        T: Send,
+        I: Send,
+        O: Send,
     {
         Node {
             usage: 0,
+            usage_stamp: 0,
             active: AtomicBool::new(false).into(),
             complete: AtomicBool::new(false),
-            data: SyncUnsafeCell::new(MaybeUninit::uninit()),
+            data: SyncUnsafeCell::new(Slot::empty()),
             #[cfg(feature = "combiner_stat")]
             combiner_time_stat: 0,
         }
@@ -0,0 +1,15 @@
+//! ffwd: fast, fly-weight delegation (Roghanchi et al., SOSP 2017).
+//!
+//! A dedicated server thread, pinned to its own core, owns the data. Every
+//! client has a request line of its own and clients of the same cluster
+//! share a response line. The server polls the request lines group by
+//! group, runs the delegate for every new request and publishes the
+//! responses of a group with a single store of the group's toggle bits.
+//! A thread gives its request line back when it exits, so only the threads
+//! using the lock at once count against `MAX_GROUPS * GROUP_SIZE`.
+
+mod lock;
+
+pub use self::lock::{GROUP_SIZE, MAX_GROUPS};
+
+pub type Ffwd<T, I, O, F> = lock::Ffwd<T, I, O, F>;
@@ -0,0 +1,372 @@
+use std::{
+    array,
+    cell::{RefCell, SyncUnsafeCell},
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering::*},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crossbeam::utils::{Backoff, CachePadded};
+
+use crate::{
+    dlock2::{nesting, DLock2, DLock2Delegate},
+    spin_lock::SpinLock,
+    topology::Topology,
+};
+
+/// Clients sharing a response line
+pub const GROUP_SIZE: usize = 15;
+/// Groups of a lock, which caps the threads using it at once to
+/// `MAX_GROUPS * GROUP_SIZE`
+pub const MAX_GROUPS: usize = 64;
+
+#[derive(Debug)]
+struct Request<I> {
+    /// Flipped by the client for every new request
+    toggle: AtomicBool,
+    data: SyncUnsafeCell<MaybeUninit<I>>,
+}
+
+#[derive(Debug)]
+struct Response<O> {
+    /// Bit `slot` equals the request toggle of client `slot` once its
+    /// response is ready, only written by the server
+    toggles: AtomicU64,
+    data: [SyncUnsafeCell<MaybeUninit<O>>; GROUP_SIZE],
+}
+
+#[derive(Debug)]
+struct Group<I, O> {
+    requests: [CachePadded<Request<I>>; GROUP_SIZE],
+    response: CachePadded<Response<O>>,
+}
+
+// a request is only read by the server and a response only by its client,
+// each after the toggle handed it over
+unsafe impl<I: Send, O: Send> Sync for Group<I, O> {}
+
+impl<I, O> Group<I, O> {
+    fn new() -> Self {
+        Self {
+            requests: array::from_fn(|_| {
+                CachePadded::new(Request {
+                    toggle: AtomicBool::new(false),
+                    data: SyncUnsafeCell::new(MaybeUninit::uninit()),
+                })
+            }),
+            response: CachePadded::new(Response {
+                toggles: AtomicU64::new(0),
+                data: array::from_fn(|_| SyncUnsafeCell::new(MaybeUninit::uninit())),
+            }),
+        }
+    }
+}
+
+/// Slots taken in each group, shared with the threads holding them so that
+/// a thread gives its slots back when it exits.
+#[derive(Debug)]
+struct Registry {
+    num_groups: AtomicUsize,
+    /// Slots of each group the server polls, freed slots included
+    members: [AtomicUsize; MAX_GROUPS],
+    /// Cluster and freed slots of each group
+    groups: SpinLock<Vec<(usize, u64)>>,
+}
+
+impl Registry {
+    fn new() -> Self {
+        Self {
+            num_groups: AtomicUsize::new(0),
+            members: [const { AtomicUsize::new(0) }; MAX_GROUPS],
+            groups: SpinLock::new(Vec::new()),
+        }
+    }
+
+    /// Take a slot in a group of `cluster`, reusing a freed one first.
+    fn register(&self, cluster: usize) -> (usize, usize) {
+        let mut groups = self.groups.lock();
+
+        let free = groups
+            .iter()
+            .position(|&(group_cluster, free)| group_cluster == cluster && free != 0);
+        if let Some(group) = free {
+            let slot = groups[group].1.trailing_zeros() as usize;
+            groups[group].1 &= !(1 << slot);
+            return (group, slot);
+        }
+
+        let members = &self.members;
+        let open = (0..groups.len())
+            .find(|&group| groups[group].0 == cluster && members[group].load(Relaxed) < GROUP_SIZE);
+
+        let group = open.unwrap_or_else(|| {
+            assert!(
+                groups.len() < MAX_GROUPS,
+                "more than {} threads use the ffwd lock at once",
+                MAX_GROUPS * GROUP_SIZE
+            );
+            groups.push((cluster, 0));
+            self.num_groups.store(groups.len(), Release);
+            groups.len() - 1
+        });
+
+        let slot = members[group].load(Relaxed);
+        members[group].store(slot + 1, Release);
+        (group, slot)
+    }
+
+    /// Give back a slot whose last request has been answered. The server
+    /// keeps polling it and finds nothing new until it is taken again.
+    fn release(&self, group: usize, slot: usize) {
+        self.groups.lock()[group].1 |= 1 << slot;
+    }
+}
+
+/// A slot of the thread in a lock.
+#[derive(Debug)]
+struct Client {
+    registry: Arc<Registry>,
+    group: usize,
+    slot: usize,
+    toggle: bool,
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.registry.release(self.group, self.slot);
+    }
+}
+
+thread_local! {
+    /// Slots the thread holds, given back when the thread exits
+    static CLIENTS: RefCell<Vec<Client>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug)]
+struct Server<T, I, O, F> {
+    delegate: F,
+    data: SyncUnsafeCell<T>,
+    groups: Box<[Group<I, O>]>,
+    registry: Arc<Registry>,
+    stop: AtomicBool,
+}
+
+impl<T, I, O, F> Server<T, I, O, F>
+where
+    F: DLock2Delegate<T, I, O>,
+{
+    fn run(&self) {
+        // the server thread runs a delegate of the lock at all times
+        let _combining = nesting::combining_guard(self);
+        let backoff = Backoff::new();
+
+        while !self.stop.load(Acquire) {
+            let mut served = false;
+
+            let num_groups = self.registry.num_groups.load(Acquire);
+            for (id, group) in self.groups[..num_groups].iter().enumerate() {
+                served |= unsafe { self.serve(id, group) };
+            }
+
+            if served {
+                backoff.reset();
+            } else {
+                backoff.snooze();
+            }
+        }
+    }
+
+    /// Run the new requests of `group` and publish their responses at once.
+    unsafe fn serve(&self, id: usize, group: &Group<I, O>) -> bool {
+        let response = &group.response;
+        let old = response.toggles.load(Relaxed);
+        let mut toggles = old;
+
+        for (slot, request) in group.requests[..self.registry.members[id].load(Acquire)]
+            .iter()
+            .enumerate()
+        {
+            let bit = 1 << slot;
+
+            if request.toggle.load(Acquire) == (toggles & bit != 0) {
+                continue;
+            }
+
+            let output = (self.delegate)(
+                &mut *self.data.get(),
+                request.data.get().read().assume_init(),
+            );
+            response.data[slot].get().write(MaybeUninit::new(output));
+            toggles ^= bit;
+        }
+
+        if toggles == old {
+            return false;
+        }
+
+        response.toggles.store(toggles, Release);
+        true
+    }
+}
+
+#[derive(Debug)]
+pub struct Ffwd<T, I, O, F>
+where
+    F: DLock2Delegate<T, I, O>,
+{
+    server: Arc<Server<T, I, O, F>>,
+    handle: Option<JoinHandle<()>>,
+    server_cpu: Option<usize>,
+    topology: Topology,
+}
+
+impl<T, I, O, F> Ffwd<T, I, O, F>
+where
+    T: Send + Sync + 'static,
+    I: Send + 'static,
+    O: Send + 'static,
+    F: DLock2Delegate<T, I, O> + 'static,
+{
+    /// Start the server thread without pinning it.
+    pub fn new(data: T, delegate: F) -> Self {
+        Self::with_server(data, delegate, None, Topology::single())
+    }
+
+    /// Start the server thread pinned to `server_cpu`, clients are grouped
+    /// by their cluster in `topology`.
+    pub fn with_server(
+        data: T,
+        delegate: F,
+        server_cpu: Option<usize>,
+        topology: Topology,
+    ) -> Self {
+        let server = Arc::new(Server {
+            delegate,
+            data: SyncUnsafeCell::new(data),
+            groups: (0..MAX_GROUPS).map(|_| Group::new()).collect(),
+            registry: Arc::new(Registry::new()),
+            stop: AtomicBool::new(false),
+        });
+
+        let handle = thread::Builder::new()
+            .name("ffwd server".to_string())
+            .spawn({
+                let server = server.clone();
+                move || {
+                    if let Some(id) = server_cpu {
+                        core_affinity::set_for_current(core_affinity::CoreId { id });
+                    }
+                    server.run();
+                }
+            })
+            .expect("failed to spawn the ffwd server");
+
+        Self {
+            server,
+            handle: Some(handle),
+            server_cpu,
+            topology,
+        }
+    }
+}
+
+impl<T, I, O, F> Ffwd<T, I, O, F>
+where
+    F: DLock2Delegate<T, I, O>,
+{
+    /// Groups clients have been put in so far.
+    pub fn num_groups(&self) -> usize {
+        self.server.registry.num_groups.load(Acquire)
+    }
+
+    /// Flip the toggle of the calling thread's slot, taking a slot on the
+    /// first request. Returns the group, the slot and the new toggle.
+    fn next_request(&self) -> (usize, usize, bool) {
+        let registry = &self.server.registry;
+
+        CLIENTS.with_borrow_mut(|clients| {
+            let client = match clients
+                .iter()
+                .position(|client| Arc::ptr_eq(&client.registry, registry))
+            {
+                Some(client) => &mut clients[client],
+                None => {
+                    // slots of dropped locks are no longer needed
+                    clients.retain(|client| Arc::strong_count(&client.registry) > 1);
+
+                    let (group, slot) = registry.register(self.topology.current_cluster());
+                    let request = &self.server.groups[group].requests[slot];
+
+                    clients.push(Client {
+                        registry: registry.clone(),
+                        group,
+                        slot,
+                        toggle: request.toggle.load(Relaxed),
+                    });
+                    clients.last_mut().unwrap()
+                }
+            };
+
+            client.toggle = !client.toggle;
+            (client.group, client.slot, client.toggle)
+        })
+    }
+}
+
+impl<T, I, O, F> Drop for Ffwd<T, I, O, F>
+where
+    F: DLock2Delegate<T, I, O>,
+{
+    fn drop(&mut self) {
+        self.server.stop.store(true, Release);
+
+        if let Some(handle) = self.handle.take() {
+            handle.join().expect("ffwd server panicked");
+        }
+
+        nesting::forget(&*self.server);
+    }
+}
+
+unsafe impl<T, I, O, F> DLock2<I, O> for Ffwd<T, I, O, F>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
+{
+    fn lock(&self, data: I) -> O {
+        nesting::enter(&*self.server);
+
+        let (group, slot, toggle) = self.next_request();
+        let group = &self.server.groups[group];
+        let request = &group.requests[slot];
+        let bit = 1 << slot;
+
+        unsafe { request.data.get().write(MaybeUninit::new(data)) };
+        request.toggle.store(toggle, Release);
+
+        let backoff = Backoff::new();
+        while (group.response.toggles.load(Acquire) & bit != 0) != toggle {
+            // a panicking delegate ends the server, the response never comes
+            if self.handle.as_ref().is_some_and(JoinHandle::is_finished) {
+                panic!("ffwd server panicked");
+            }
+            backoff.snooze();
+        }
+
+        unsafe { group.response.data[slot].get().read().assume_init() }
+    }
+
+    #[cfg(feature = "combiner_stat")]
+    fn get_combine_time(&self) -> Option<u64> {
+        None
+    }
+
+    fn server_cpu(&self) -> Option<usize> {
+        self.server_cpu
+    }
+}
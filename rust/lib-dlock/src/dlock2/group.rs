@@ -0,0 +1,170 @@
+//! Thread groups for hierarchical fairness in `FCPQ` and `FCBan`.
+//!
+//! Threads join a named group with a weight through `DLock2::join_group`.
+//! The combiner first shares the lock among groups in proportion to their
+//! weights and then among the threads of a group the way the lock already
+//! does. Threads that never join a group belong to `DEFAULT_GROUP`, so a
+//! lock that nobody joins a group of behaves exactly as before.
+//!
+//! Groups are compared by virtual time, their usage divided by their weight.
+//! A group that stops requesting would fall behind and, once it comes back,
+//! take the lock until it caught up with the others. To avoid that, the
+//! virtual time of a group never lags the start of the latest service, the
+//! way start-time fair queueing treats a flow that becomes backlogged again.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::*};
+
+use crate::spin_lock::SpinLock;
+
+pub const MAX_GROUPS: usize = 64;
+pub const DEFAULT_GROUP: usize = 0;
+
+/// Snapshot of one group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupUsage {
+    pub name: String,
+    pub weight: u64,
+    pub members: u64,
+    /// Critical-section cycles served for the group
+    pub usage: u64,
+}
+
+#[derive(Debug)]
+pub struct Groups {
+    names: SpinLock<Vec<String>>,
+    num_groups: AtomicUsize,
+    weights: [AtomicU64; MAX_GROUPS],
+    members: [AtomicU64; MAX_GROUPS],
+    usage: [AtomicU64; MAX_GROUPS],
+    /// Usage charged against the weight, bumped up to `clock` when a group
+    /// that lagged behind is served again
+    virtual_usage: [AtomicU64; MAX_GROUPS],
+    /// Virtual time of the group at the start of the latest service
+    clock: AtomicU64,
+}
+
+impl Groups {
+    pub fn new() -> Self {
+        let groups = Self {
+            names: SpinLock::new(vec!["default".to_string()]),
+            num_groups: AtomicUsize::new(1),
+            weights: [const { AtomicU64::new(0) }; MAX_GROUPS],
+            members: [const { AtomicU64::new(0) }; MAX_GROUPS],
+            usage: [const { AtomicU64::new(0) }; MAX_GROUPS],
+            virtual_usage: [const { AtomicU64::new(0) }; MAX_GROUPS],
+            clock: AtomicU64::new(0),
+        };
+
+        groups.weights[DEFAULT_GROUP].store(1, Relaxed);
+        groups
+    }
+
+    /// Add a new thread to the default group.
+    pub fn register(&self) -> usize {
+        self.members[DEFAULT_GROUP].fetch_add(1, Relaxed);
+        DEFAULT_GROUP
+    }
+
+    /// Move a thread from group `from` to the group called `name`, creating
+    /// it if needed. The weight given by the latest thread to join wins.
+    pub fn join(&self, name: &str, weight: u64, from: usize) -> usize {
+        let mut names = self.names.lock();
+
+        let id = match names.iter().position(|n| n == name) {
+            Some(id) => id,
+            None => {
+                assert!(names.len() < MAX_GROUPS, "too many groups");
+                names.push(name.to_string());
+                self.num_groups.store(names.len(), Release);
+                names.len() - 1
+            }
+        };
+
+        self.weights[id].store(weight.max(1), Relaxed);
+
+        if id != from {
+            self.members[from].fetch_sub(1, Relaxed);
+            self.members[id].fetch_add(1, Relaxed);
+        }
+
+        id
+    }
+
+    /// Whether any thread has joined a group other than the default one.
+    #[inline]
+    pub fn in_use(&self) -> bool {
+        self.num_groups.load(Acquire) > 1
+    }
+
+    #[inline]
+    pub fn num_groups(&self) -> usize {
+        self.num_groups.load(Acquire)
+    }
+
+    #[inline]
+    pub fn weight(&self, group: usize) -> u64 {
+        self.weights[group].load(Relaxed)
+    }
+
+    #[inline]
+    pub fn members(&self, group: usize) -> u64 {
+        self.members[group].load(Relaxed)
+    }
+
+    /// Sum of the weights of the groups that have members.
+    pub fn total_weight(&self) -> u64 {
+        (0..self.num_groups())
+            .filter(|&group| self.members(group) > 0)
+            .map(|group| self.weight(group))
+            .sum()
+    }
+
+    /// Usage of a group relative to its weight, no earlier than the start of
+    /// the latest service so that an idle group banks no credit.
+    #[inline]
+    pub fn virtual_time(&self, group: usize) -> u64 {
+        (self.virtual_usage[group].load(Relaxed) / self.weight(group)).max(self.clock.load(Relaxed))
+    }
+
+    /// Account `cs` cycles served for a thread of `group`. Only the combiner
+    /// charges.
+    #[inline]
+    pub fn charge(&self, group: usize, cs: u64) {
+        let weight = self.weight(group);
+        let clock = self.clock.load(Relaxed);
+        let virtual_usage = self.virtual_usage[group]
+            .load(Relaxed)
+            .max(clock.saturating_mul(weight));
+
+        self.clock.store(clock.max(virtual_usage / weight), Relaxed);
+        self.virtual_usage[group].store(virtual_usage + cs, Relaxed);
+        self.usage[group].fetch_add(cs, Relaxed);
+    }
+
+    /// Scale a per-thread penalty so that `group` gets its weighted share of
+    /// the lock.
+    pub fn scale_penalty(&self, group: usize, penalty: u64) -> u64 {
+        (penalty as u128 * self.total_weight() as u128 / self.weight(group) as u128) as u64
+    }
+
+    pub fn usage(&self) -> Vec<GroupUsage> {
+        let names = self.names.lock();
+
+        names
+            .iter()
+            .enumerate()
+            .map(|(group, name)| GroupUsage {
+                name: name.clone(),
+                weight: self.weight(group),
+                members: self.members(group),
+                usage: self.usage[group].load(Relaxed),
+            })
+            .collect()
+    }
+}
+
+impl Default for Groups {
+    fn default() -> Self {
+        Self::new()
+    }
+}
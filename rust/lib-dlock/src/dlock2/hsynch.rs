@@ -0,0 +1,6 @@
+use crate::spin_lock::RawSpinLock;
+
+mod lock;
+mod node;
+
+pub type HSynch<T, I, O, F, L = RawSpinLock> = lock::HSynch<T, I, O, F, L>;
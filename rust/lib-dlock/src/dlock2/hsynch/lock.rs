@@ -0,0 +1,252 @@
+use std::{
+    arch::x86_64::__rdtscp,
+    cell::SyncUnsafeCell,
+    hint::spin_loop,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering::*},
+};
+
+use crossbeam::utils::{Backoff, CachePadded};
+use lock_api::RawMutex;
+use thread_local::ThreadLocal;
+
+use crate::{
+    dlock2::{nesting, slot::Slot, DLock2, DLock2Delegate},
+    topology::Topology,
+};
+
+use super::node::Node;
+
+const H: u32 = 64;
+
+#[derive(Debug)]
+struct ThreadData<I, O> {
+    cluster: AtomicUsize,
+    node: AtomicPtr<Node<I, O>>,
+    combiner_time_stat: SyncUnsafeCell<u64>,
+}
+
+#[derive(Debug)]
+struct Cluster<I, O> {
+    tail: AtomicPtr<Node<I, O>>,
+    /// The cluster's combiner is waiting for the top-level lock
+    waiting: AtomicBool,
+    /// Cycles the cluster held the top-level lock for
+    usage: AtomicU64,
+}
+
+/// H-Synch: one CC-Synch queue per cluster. The combiner of a cluster
+/// serves a batch of its cluster's requests while holding the top-level
+/// lock. When several cluster combiners wait for the top-level lock, the
+/// cluster with the least usage goes first.
+#[derive(Debug)]
+pub struct HSynch<T, I, O, F, L>
+where
+    F: DLock2Delegate<T, I, O>,
+    L: RawMutex,
+{
+    delegate: F,
+    data: SyncUnsafeCell<T>,
+    topology: Topology,
+    top_lock: CachePadded<L>,
+    clusters: Box<[CachePadded<Cluster<I, O>>]>,
+    local_node: ThreadLocal<ThreadData<I, O>>,
+}
+
+impl<T, I, O, F, L> HSynch<T, I, O, F, L>
+where
+    F: DLock2Delegate<T, I, O>,
+    L: RawMutex,
+{
+    pub fn new(data: T, delegate: F) -> Self {
+        Self::with_topology(data, delegate, Topology::single())
+    }
+
+    pub fn with_topology(data: T, delegate: F, topology: Topology) -> Self {
+        let clusters = (0..topology.num_clusters())
+            .map(|_| {
+                CachePadded::new(Cluster {
+                    tail: AtomicPtr::new(Box::leak(Box::new(Node::default()))),
+                    waiting: AtomicBool::new(false),
+                    usage: AtomicU64::new(0),
+                })
+            })
+            .collect();
+
+        Self {
+            delegate,
+            data: SyncUnsafeCell::new(data),
+            topology,
+            top_lock: CachePadded::new(L::INIT),
+            clusters,
+            local_node: ThreadLocal::new(),
+        }
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    /// Cycles every cluster held the top-level lock for.
+    pub fn cluster_usage(&self) -> Vec<u64> {
+        self.clusters
+            .iter()
+            .map(|cluster| cluster.usage.load(Relaxed))
+            .collect()
+    }
+
+    /// Put the calling thread into `cluster` instead of the cluster of the
+    /// CPU it first locked from.
+    pub fn bind_cluster(&self, cluster: usize) {
+        assert!(cluster < self.clusters.len(), "no such cluster");
+        self.thread_data().cluster.store(cluster, Relaxed);
+    }
+
+    fn thread_data(&self) -> &ThreadData<I, O> {
+        self.local_node.get_or(|| ThreadData {
+            cluster: self.topology.current_cluster().into(),
+            node: AtomicPtr::new(Box::leak(Box::new(Node::default()))),
+            combiner_time_stat: 0.into(),
+        })
+    }
+
+    /// Whether a waiting cluster with less usage should go first. Ties are
+    /// broken by cluster id so exactly one waiting cluster never yields.
+    fn yields(&self, cluster: usize) -> bool {
+        let usage = self.clusters[cluster].usage.load(Relaxed);
+
+        self.clusters.iter().enumerate().any(|(other, c)| {
+            other != cluster
+                && c.waiting.load(Acquire)
+                && (c.usage.load(Relaxed), other) < (usage, cluster)
+        })
+    }
+
+    fn acquire_top(&self, cluster: usize) {
+        self.clusters[cluster].waiting.store(true, Release);
+
+        let backoff = Backoff::new();
+        while self.yields(cluster) || !self.top_lock.try_lock() {
+            backoff.snooze();
+        }
+
+        self.clusters[cluster].waiting.store(false, Release);
+    }
+}
+
+impl<T, I, O, F, L> Drop for HSynch<T, I, O, F, L>
+where
+    F: DLock2Delegate<T, I, O>,
+    L: RawMutex,
+{
+    fn drop(&mut self) {
+        nesting::forget(self);
+    }
+}
+
+unsafe impl<T, I, O, F, L> DLock2<I, O> for HSynch<T, I, O, F, L>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
+    L: RawMutex + Send + Sync,
+{
+    fn lock(&self, data: I) -> O {
+        nesting::enter(self);
+
+        let thread_data = self.thread_data();
+        let cluster_id = thread_data.cluster.load(Relaxed);
+        let cluster = &self.clusters[cluster_id];
+        let mut aux = 0;
+
+        // use thread local node as next node
+        let next_node = unsafe { &mut *thread_data.node.load(Acquire) };
+
+        next_node.next.store(std::ptr::null_mut(), Release);
+        next_node.wait.store(true, Release);
+        next_node.completed.store(false, Release);
+
+        let current_ptr = cluster.tail.swap(next_node, AcqRel);
+        let current_node = unsafe { current_ptr.as_ref().unwrap_unchecked() };
+
+        unsafe {
+            current_node.data.get().write(Slot::input(data));
+            current_node.next.store(next_node, Release);
+            thread_data.node.store(current_ptr, Relaxed);
+        }
+
+        // wait for the current node to be waked
+        while current_node.wait.load(Acquire) {
+            spin_loop()
+        }
+
+        // check whether the current node is completed
+        if current_node.completed.load(Acquire) {
+            return unsafe { (*current_node.data.get()).take_output() };
+        }
+
+        // combiner of the cluster
+        let _combining = nesting::combining_guard(self);
+
+        #[cfg(feature = "combiner_stat")]
+        let begin = unsafe { __rdtscp(&mut aux) };
+
+        self.acquire_top(cluster_id);
+
+        let work_begin = unsafe { __rdtscp(&mut aux) };
+
+        let mut tmp_node = current_node;
+
+        let mut counter: u32 = 0;
+
+        let mut next_ptr = NonNull::new(tmp_node.next.load(Acquire));
+
+        while let Some(next_nonnull) = next_ptr {
+            if counter >= H {
+                break;
+            }
+
+            counter += 1;
+            let next_node = unsafe { next_nonnull.as_ref() };
+
+            unsafe {
+                tmp_node.data.get().write(Slot::output((self.delegate)(
+                    self.data.get().as_mut().unwrap_unchecked(),
+                    (*tmp_node.data.get()).take_input(),
+                )));
+
+                tmp_node.completed.store(true, Release);
+                tmp_node.wait.store(false, Release);
+            }
+
+            tmp_node = next_node;
+            next_ptr = NonNull::new(tmp_node.next.load(Acquire));
+        }
+
+        let work_end = unsafe { __rdtscp(&mut aux) };
+        cluster.usage.fetch_add(work_end - work_begin, Relaxed);
+
+        unsafe {
+            self.top_lock.unlock();
+        }
+
+        tmp_node.wait.store(false, Release);
+
+        #[cfg(feature = "combiner_stat")]
+        unsafe {
+            *thread_data.combiner_time_stat.get() += work_end - begin;
+        }
+
+        return unsafe { (*current_node.data.get()).take_output() };
+    }
+
+    #[cfg(feature = "combiner_stat")]
+    fn get_combine_time(&self) -> Option<u64> {
+        unsafe {
+            self.local_node
+                .get()
+                .map(|local_node| *local_node.combiner_time_stat.get())
+        }
+    }
+}
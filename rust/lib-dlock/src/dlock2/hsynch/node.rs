@@ -0,0 +1,24 @@
+use std::{
+    cell::SyncUnsafeCell,
+    sync::atomic::{AtomicBool, AtomicPtr},
+};
+
+use crate::dlock2::slot::Slot;
+
+pub struct Node<I, O> {
+    pub data: SyncUnsafeCell<Slot<I, O>>,
+    pub completed: AtomicBool,
+    pub wait: AtomicBool,
+    pub next: AtomicPtr<Node<I, O>>,
+}
+
+impl<I, O> Default for Node<I, O> {
+    fn default() -> Self {
+        Node {
+            data: SyncUnsafeCell::new(Slot::empty()),
+            completed: AtomicBool::new(false),
+            wait: AtomicBool::new(false),
+            next: AtomicPtr::new(std::ptr::null_mut()),
+        }
+    }
+}
@@ -1,22 +1,30 @@
-use std::{ops::DerefMut, sync::Mutex};
+use std::{
+    ops::DerefMut,
+    sync::{Mutex, TryLockError},
+    time::{Duration, Instant},
+};
+
+use crossbeam::utils::Backoff;
 
 use super::{DLock2, DLock2Delegate};
 
 #[derive(Debug)]
-pub struct DLock2Mutex<T, I, F>
+pub struct DLock2Mutex<T, I, O, F>
 where
     I: Send,
-    F: DLock2Delegate<T, I>,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
 {
     delegate: F,
     data: Mutex<T>,
-    phantom: std::marker::PhantomData<fn() -> I>,
+    phantom: std::marker::PhantomData<fn(I) -> O>,
 }
 
-impl<T, I, F> DLock2Mutex<T, I, F>
+impl<T, I, O, F> DLock2Mutex<T, I, O, F>
 where
     I: Send,
-    F: DLock2Delegate<T, I>,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
 {
     pub fn new(data: T, delegate: F) -> Self {
         Self {
@@ -27,17 +35,36 @@ where
     }
 }
 
-unsafe impl<T, I, F> DLock2<I> for DLock2Mutex<T, I, F>
+unsafe impl<T, I, O, F> DLock2<I, O> for DLock2Mutex<T, I, O, F>
 where
     T: Send + Sync,
     I: Send,
-    F: DLock2Delegate<T, I>,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
 {
-    fn lock(&self, data: I) -> I {
+    fn lock(&self, data: I) -> O {
         let mut lock_data = self.data.lock().unwrap();
         (self.delegate)(lock_data.deref_mut(), data)
     }
 
+    fn supports_timeout(&self) -> bool {
+        true
+    }
+
+    fn lock_timeout(&self, data: I, timeout: Duration) -> Result<O, I> {
+        let deadline = Instant::now() + timeout;
+        let backoff = Backoff::new();
+
+        loop {
+            match self.data.try_lock() {
+                Ok(mut lock_data) => return Ok((self.delegate)(lock_data.deref_mut(), data)),
+                Err(TryLockError::Poisoned(err)) => panic!("{}", err),
+                Err(TryLockError::WouldBlock) if Instant::now() >= deadline => return Err(data),
+                Err(TryLockError::WouldBlock) => backoff.snooze(),
+            }
+        }
+    }
+
     #[cfg(feature = "combiner_stat")]
     fn get_combine_time(&self) -> Option<u64> {
         None
@@ -0,0 +1,183 @@
+//! Delegation from inside a delegate.
+//!
+//! A delegate may call `lock` on another lock, the combiner then waits for
+//! that lock like any other thread. A delegate calling `lock` on its own lock
+//! would wait for the very combining pass it runs in, which panics instead of
+//! hanging. FC, CC, FCBan, CCBan, FCPQ, FCSL, DSM-Synch, FC-EDF, H-Synch and
+//! ffwd check for this.
+//!
+//! Debug builds also keep a wait-for graph with an edge from every lock whose
+//! delegate delegated to another lock. A cycle in it means two combiners can
+//! end up waiting for each other, it is recorded the first time it closes and
+//! returned by `take_lock_order_cycles`.
+
+use std::{any::type_name, cell::RefCell};
+
+#[cfg(debug_assertions)]
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+};
+
+thread_local! {
+    /// Locks whose delegates the thread is running, innermost last
+    static COMBINING: RefCell<Vec<Lock>> = const { RefCell::new(Vec::new()) };
+}
+
+#[cfg(debug_assertions)]
+static WAIT_FOR: Mutex<WaitFor> = Mutex::new(WaitFor {
+    edges: BTreeMap::new(),
+    cycles: Vec::new(),
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Lock {
+    address: usize,
+    name: &'static str,
+}
+
+impl Lock {
+    fn of<L: ?Sized>(lock: &L) -> Self {
+        let name = type_name::<L>();
+        let name = name.split('<').next().unwrap_or(name);
+
+        Self {
+            address: (lock as *const L).cast::<()>() as usize,
+            name: name.rsplit("::").next().unwrap_or(name),
+        }
+    }
+}
+
+impl std::fmt::Display for Lock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{:#x}", self.name, self.address)
+    }
+}
+
+#[cfg(debug_assertions)]
+#[derive(Debug)]
+struct WaitFor {
+    edges: BTreeMap<Lock, BTreeSet<Lock>>,
+    cycles: Vec<String>,
+}
+
+#[cfg(debug_assertions)]
+impl WaitFor {
+    fn add(&mut self, from: Lock, to: Lock) {
+        if !self.edges.entry(from).or_default().insert(to) {
+            return;
+        }
+
+        if let Some(path) = self.path(to, from, &mut BTreeSet::new()) {
+            let cycle = std::iter::once(from)
+                .chain(path)
+                .map(|lock| lock.to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+
+            self.cycles.push(cycle);
+        }
+    }
+
+    /// Locks from `from` to `to` along the edges, both included.
+    fn path(&self, from: Lock, to: Lock, visited: &mut BTreeSet<Lock>) -> Option<Vec<Lock>> {
+        if from == to {
+            return Some(vec![to]);
+        }
+
+        if !visited.insert(from) {
+            return None;
+        }
+
+        self.edges.get(&from)?.iter().find_map(|&next| {
+            let mut path = self.path(next, to, visited)?;
+            path.insert(0, from);
+            Some(path)
+        })
+    }
+
+    fn forget(&mut self, lock: Lock) {
+        self.edges.remove(&lock);
+        self.edges.values_mut().for_each(|targets| {
+            targets.remove(&lock);
+        });
+    }
+}
+
+/// Check a request the calling thread is about to hand to `lock`.
+///
+/// # Panics
+///
+/// Panics if the thread runs a delegate of `lock` itself.
+pub(crate) fn enter<L: ?Sized>(lock: &L) {
+    COMBINING.with(|combining| {
+        let combining = combining.borrow();
+
+        if combining.is_empty() {
+            return;
+        }
+
+        let lock = Lock::of(lock);
+
+        if combining.iter().any(|held| held.address == lock.address) {
+            panic!(
+                "re-entrant delegation: a delegate of {} called it again, which would wait for \
+                 its own combining pass",
+                lock
+            );
+        }
+
+        #[cfg(debug_assertions)]
+        {
+            let mut wait_for = WAIT_FOR.lock().unwrap_or_else(|e| e.into_inner());
+            for &held in combining.iter() {
+                wait_for.add(held, lock);
+            }
+        }
+    });
+}
+
+/// Run a combining pass of `lock`.
+pub(crate) fn combining<L: ?Sized, R>(lock: &L, pass: impl FnOnce() -> R) -> R {
+    let _combining = combining_guard(lock);
+
+    pass()
+}
+
+/// The calling thread runs a combining pass of a lock until the guard is
+/// dropped
+pub(crate) struct Combining(());
+
+impl Drop for Combining {
+    fn drop(&mut self) {
+        COMBINING.with(|combining| combining.borrow_mut().pop());
+    }
+}
+
+/// Start a combining pass of `lock` that lasts as long as the guard, for
+/// locks combining inline in `lock`.
+pub(crate) fn combining_guard<L: ?Sized>(lock: &L) -> Combining {
+    COMBINING.with(|combining| combining.borrow_mut().push(Lock::of(lock)));
+
+    Combining(())
+}
+
+/// Drop the edges of a lock that goes away, so that a lock created at the
+/// same address starts out without any.
+pub(crate) fn forget<L: ?Sized>(_lock: &L) {
+    #[cfg(debug_assertions)]
+    WAIT_FOR
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .forget(Lock::of(_lock));
+}
+
+/// The lock-order cycles reported since the last call, empty in release
+/// builds.
+pub fn take_lock_order_cycles() -> Vec<String> {
+    #[cfg(debug_assertions)]
+    return std::mem::take(&mut WAIT_FOR.lock().unwrap_or_else(|e| e.into_inner()).cycles);
+
+    #[cfg(not(debug_assertions))]
+    Vec::new()
+}
@@ -0,0 +1,84 @@
+//! Optimistic reads that bypass the combiner.
+//!
+//! The combiner makes the version odd for the time of a combining pass. A
+//! reader runs its closure on the protected data right on the calling
+//! thread and keeps the result if the version was even and did not change
+//! meanwhile. Readers never block the combiner, a reader that keeps running
+//! into combining passes, or waits too long for one to end, gives up so that
+//! the caller can delegate the read as an ordinary request instead.
+
+use std::sync::atomic::{fence, AtomicU64, Ordering::*};
+
+use crossbeam::utils::Backoff;
+
+/// Failed validations of an optimistic read before it gives up
+pub const READ_RETRIES: usize = 16;
+
+#[derive(Debug, Default)]
+pub(crate) struct SeqLock {
+    version: AtomicU64,
+}
+
+impl SeqLock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run a combining pass. Only the combiner may call this.
+    pub fn write<R>(&self, pass: impl FnOnce() -> R) -> R {
+        let version = self.version.load(Relaxed);
+
+        self.version.store(version + 1, Relaxed);
+        fence(Release);
+
+        let result = pass();
+
+        self.version.store(version + 2, Release);
+
+        result
+    }
+
+    /// Run `reader` until it overlaps with no combining pass, at most
+    /// `READ_RETRIES` times. Waiting for a pass to end before a run counts
+    /// against none of them.
+    ///
+    /// # Safety
+    ///
+    /// `reader` may see the data in the middle of a combining pass, it must
+    /// neither crash nor hang on such a state. Its result is dropped then.
+    pub unsafe fn read<R>(&self, reader: impl Fn() -> R) -> Option<R> {
+        for _ in 0..READ_RETRIES {
+            let version = self.even_version()?;
+
+            let result = reader();
+
+            fence(Acquire);
+
+            if self.version.load(Relaxed) == version {
+                return Some(result);
+            }
+        }
+
+        None
+    }
+
+    /// The version once no combining pass runs, `None` if a pass outlasts
+    /// the backoff.
+    fn even_version(&self) -> Option<u64> {
+        let backoff = Backoff::new();
+
+        loop {
+            let version = self.version.load(Acquire);
+
+            if version % 2 == 1 {
+                if backoff.is_completed() {
+                    return None;
+                }
+                backoff.snooze();
+                continue;
+            }
+
+            return Some(version);
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//! ShflLock-style queue lock (Kashyap et al., SOSP 2019).
+//!
+//! Waiters queue up MCS-style behind a test-and-set lock. The waiter at the
+//! head of the queue, the shuffler, reorders the waiters behind it while
+//! the lock is held by somebody else, so the reordering stays off the
+//! critical path. A `ShufflePolicy` decides the order and gets callbacks to
+//! keep per-thread accounting.
+
+mod lock;
+mod node;
+pub mod policy;
+
+use self::policy::NoShuffle;
+pub use self::{lock::MAX_SKIPS, node::Node};
+
+pub type ShflLock<T, I, O, F, P = NoShuffle> = lock::ShflLock<T, I, O, F, P>;
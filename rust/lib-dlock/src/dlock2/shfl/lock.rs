@@ -0,0 +1,246 @@
+use std::{
+    arch::x86_64::__rdtscp,
+    cell::SyncUnsafeCell,
+    hint::spin_loop,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering::*},
+};
+
+use crossbeam::utils::{Backoff, CachePadded};
+use thread_local::ThreadLocal;
+
+use crate::{
+    dlock2::{DLock2, DLock2Delegate},
+    topology::Topology,
+};
+
+use super::{node::Node, policy::ShufflePolicy};
+
+/// Waiters one shuffling pass looks at
+const MAX_SHUFFLE: usize = 64;
+/// Waiters that may be moved before a waiter while it is queued
+pub const MAX_SKIPS: u32 = 16;
+
+#[derive(Debug)]
+pub struct ShflLock<T, I, O, F, P>
+where
+    F: DLock2Delegate<T, I, O>,
+    P: ShufflePolicy,
+{
+    delegate: F,
+    data: SyncUnsafeCell<T>,
+    topology: Topology,
+    policy: P,
+    locked: CachePadded<AtomicBool>,
+    tail: CachePadded<AtomicPtr<Node<P::Stat>>>,
+    local_node: ThreadLocal<Node<P::Stat>>,
+    phantom: std::marker::PhantomData<fn(I) -> O>,
+}
+
+impl<T, I, O, F, P> ShflLock<T, I, O, F, P>
+where
+    F: DLock2Delegate<T, I, O>,
+    P: ShufflePolicy + Default,
+{
+    pub fn new(data: T, delegate: F) -> Self {
+        Self::with_policy(data, delegate, P::default())
+    }
+
+    pub fn with_topology(data: T, delegate: F, topology: Topology) -> Self {
+        Self {
+            topology,
+            ..Self::new(data, delegate)
+        }
+    }
+}
+
+impl<T, I, O, F, P> ShflLock<T, I, O, F, P>
+where
+    F: DLock2Delegate<T, I, O>,
+    P: ShufflePolicy,
+{
+    pub fn with_policy(data: T, delegate: F, policy: P) -> Self {
+        Self {
+            delegate,
+            data: SyncUnsafeCell::new(data),
+            topology: Topology::single(),
+            policy,
+            locked: CachePadded::new(AtomicBool::new(false)),
+            tail: CachePadded::new(AtomicPtr::new(ptr::null_mut())),
+            local_node: ThreadLocal::new(),
+            phantom: std::marker::PhantomData,
+        }
+    }
+
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    /// Put the calling thread into `cluster` instead of the cluster of the
+    /// CPU it first locked from.
+    pub fn bind_cluster(&self, cluster: usize) {
+        self.local_node().cluster.store(cluster, Relaxed);
+    }
+
+    fn local_node(&self) -> &Node<P::Stat> {
+        self.local_node
+            .get_or(|| Node::new(self.topology.current_cluster()))
+    }
+
+    fn try_lock(&self) -> bool {
+        !self.locked.load(Relaxed)
+            && self
+                .locked
+                .compare_exchange_weak(false, true, Acquire, Relaxed)
+                .is_ok()
+    }
+
+    fn acquire(&self, node: &Node<P::Stat>) {
+        if self.tail.load(Relaxed).is_null() && self.try_lock() {
+            return;
+        }
+
+        let node_ptr = node as *const Node<P::Stat> as *mut Node<P::Stat>;
+
+        node.next.store(ptr::null_mut(), Relaxed);
+        node.is_head.store(false, Relaxed);
+        node.skipped.store(0, Relaxed);
+
+        let prev = self.tail.swap(node_ptr, AcqRel);
+
+        if !prev.is_null() {
+            unsafe { (*prev).next.store(node_ptr, Release) };
+
+            let backoff = Backoff::new();
+            while !node.is_head.load(Acquire) {
+                backoff.snooze();
+            }
+        }
+
+        // head of the queue, shuffle while the lock is still held
+        let mut shuffled = false;
+        let backoff = Backoff::new();
+        while !self.try_lock() {
+            if !shuffled {
+                unsafe { self.shuffle(node) };
+                shuffled = true;
+            }
+            backoff.snooze();
+        }
+
+        // hand the head of the queue to the next waiter
+        let mut next = node.next.load(Acquire);
+
+        if next.is_null() {
+            if self
+                .tail
+                .compare_exchange(node_ptr, ptr::null_mut(), Release, Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+
+            // a successor swapped the tail but has not linked itself yet
+            loop {
+                next = node.next.load(Acquire);
+                if !next.is_null() {
+                    break;
+                }
+                spin_loop();
+            }
+        }
+
+        unsafe { (*next).is_head.store(true, Release) };
+    }
+
+    /// Reorder the waiters behind `shuffler`, the head of the queue. Only the
+    /// head relinks nodes and it never moves the tail, which is the only
+    /// node whose `next` an arriving thread writes. A waiter that was skipped
+    /// `MAX_SKIPS` times is not passed again.
+    unsafe fn shuffle(&self, shuffler: &Node<P::Stat>) {
+        // last node of the prefix of waiters that go next
+        let mut last = shuffler as *const Node<P::Stat> as *mut Node<P::Stat>;
+        let mut prev = last;
+        let mut current = shuffler.next.load(Acquire);
+
+        for _ in 0..MAX_SHUFFLE {
+            if current.is_null() {
+                break;
+            }
+
+            let next = (*current).next.load(Acquire);
+
+            if next.is_null() {
+                break;
+            }
+
+            if prev == last {
+                if !Self::may_skip(&*current)
+                    || !self.policy.should_move_before(&*next, &*current, shuffler)
+                {
+                    last = current;
+                }
+                prev = current;
+            } else if self.policy.should_move_before(
+                &*current,
+                &*(*last).next.load(Relaxed),
+                shuffler,
+            ) {
+                let skipped = (*last).next.load(Relaxed);
+                if !Self::may_skip(&*skipped) {
+                    break;
+                }
+                (*skipped).skipped.fetch_add(1, Relaxed);
+
+                (*prev).next.store(next, Relaxed);
+                (*current).next.store(skipped, Relaxed);
+                (*last).next.store(current, Release);
+                last = current;
+            } else {
+                prev = current;
+            }
+
+            current = next;
+        }
+    }
+
+    /// Whether another waiter may still be moved before `node`.
+    fn may_skip(node: &Node<P::Stat>) -> bool {
+        node.skipped.load(Relaxed) < MAX_SKIPS
+    }
+}
+
+unsafe impl<T, I, O, F, P> DLock2<I, O> for ShflLock<T, I, O, F, P>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
+    P: ShufflePolicy,
+{
+    fn lock(&self, data: I) -> O {
+        let node = self.local_node();
+        let mut aux = 0;
+
+        self.acquire(node);
+        self.policy.on_acquire(node);
+
+        let begin = unsafe { __rdtscp(&mut aux) };
+        let output = (self.delegate)(unsafe { &mut *self.data.get() }, data);
+        let end = unsafe { __rdtscp(&mut aux) };
+
+        self.policy.on_release(node, end - begin);
+        self.locked.store(false, Release);
+
+        output
+    }
+
+    #[cfg(feature = "combiner_stat")]
+    fn get_combine_time(&self) -> Option<u64> {
+        None
+    }
+}
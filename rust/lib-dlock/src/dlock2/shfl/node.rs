@@ -0,0 +1,38 @@
+use std::{
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicUsize, Ordering::*},
+};
+
+/// Queue node of a thread, `S` is the per-thread state of the policy.
+#[derive(Debug)]
+pub struct Node<S> {
+    pub(super) next: AtomicPtr<Node<S>>,
+    /// Set by the predecessor once this node is at the head of the queue
+    pub(super) is_head: AtomicBool,
+    pub(super) cluster: AtomicUsize,
+    /// Times a waiter was moved before this node since it queued up
+    pub(super) skipped: AtomicU32,
+    stat: S,
+}
+
+impl<S: Default> Node<S> {
+    pub(super) fn new(cluster: usize) -> Self {
+        Self {
+            next: AtomicPtr::new(ptr::null_mut()),
+            is_head: AtomicBool::new(false),
+            cluster: AtomicUsize::new(cluster),
+            skipped: AtomicU32::new(0),
+            stat: S::default(),
+        }
+    }
+}
+
+impl<S> Node<S> {
+    pub fn cluster(&self) -> usize {
+        self.cluster.load(Relaxed)
+    }
+
+    pub fn stat(&self) -> &S {
+        &self.stat
+    }
+}
@@ -0,0 +1,85 @@
+//! Queue orders for `ShflLock`.
+//!
+//! The shuffler walks the waiters behind it and builds a prefix of waiters
+//! that go next. A waiter right behind the prefix joins it unless the one
+//! after it should go first. Any later waiter that should go before the
+//! first waiter behind the prefix is moved to the end of the prefix.
+//!
+//! A waiter lets at most `MAX_SKIPS` others move before it, after that it
+//! keeps its place whatever the policy says, so a waiter the policy never
+//! favours, like one of a remote cluster, is not starved.
+
+use std::{
+    fmt::Debug,
+    sync::atomic::{AtomicU64, Ordering::*},
+};
+
+use super::Node;
+
+pub trait ShufflePolicy: Send + Sync + Debug {
+    /// Per-thread state kept in the thread's queue node
+    type Stat: Default + Send + Sync + Debug;
+
+    /// Whether waiter `a`, queued behind `b`, should get the lock before
+    /// `b`. Only called by `shuffler`, the waiter at the head of the queue.
+    fn should_move_before(
+        &self,
+        a: &Node<Self::Stat>,
+        b: &Node<Self::Stat>,
+        shuffler: &Node<Self::Stat>,
+    ) -> bool;
+
+    /// Called by a thread right after it acquired the lock.
+    fn on_acquire(&self, _node: &Node<Self::Stat>) {}
+
+    /// Called by a thread right before it releases the lock, after a
+    /// critical section of `cs` cycles.
+    fn on_release(&self, _node: &Node<Self::Stat>, _cs: u64) {}
+}
+
+/// Never reorder, the lock behaves like an MCS lock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoShuffle;
+
+impl ShufflePolicy for NoShuffle {
+    type Stat = ();
+
+    fn should_move_before(&self, _: &Node<()>, _: &Node<()>, _: &Node<()>) -> bool {
+        false
+    }
+}
+
+/// Group the waiters of the shuffler's cluster behind it (ShflLock's NUMA
+/// policy), so the lock stays inside a cluster for a while.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NumaGrouping;
+
+impl ShufflePolicy for NumaGrouping {
+    type Stat = ();
+
+    fn should_move_before(&self, a: &Node<()>, b: &Node<()>, shuffler: &Node<()>) -> bool {
+        a.cluster() == shuffler.cluster() && b.cluster() != shuffler.cluster()
+    }
+}
+
+/// Let waiters that used the lock less go before heavier users.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UsageFair;
+
+impl ShufflePolicy for UsageFair {
+    /// Critical-section cycles of the thread
+    type Stat = AtomicU64;
+
+    fn should_move_before(
+        &self,
+        a: &Node<AtomicU64>,
+        b: &Node<AtomicU64>,
+        _: &Node<AtomicU64>,
+    ) -> bool {
+        a.stat().load(Relaxed) < b.stat().load(Relaxed)
+    }
+
+    fn on_release(&self, node: &Node<AtomicU64>, cs: u64) {
+        node.stat().fetch_add(cs, Relaxed);
+    }
+}
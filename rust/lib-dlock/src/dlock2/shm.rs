@@ -0,0 +1,29 @@
+//! CC-Synch in a shared memory segment, so several processes can delegate
+//! operations on the same data.
+//!
+//! Nothing in the segment holds a pointer: nodes link to each other by their
+//! offset from the start of the segment, which is the same in every process
+//! whatever address the segment is mapped at. Waiters block on futexes in
+//! the segment, which the kernel matches across processes. A process
+//! registers when it attaches to the segment and the last process to leave
+//! drops the data.
+//!
+//! A segment has `MAX_NODES` queue nodes. Every thread that delegates takes
+//! one, and its process hands them back to a free list in the segment when
+//! it drops the lock, so the limit applies to the threads of the processes
+//! attached at the same time. A process that dies without dropping the lock
+//! keeps its nodes. Worse, if it dies while its request is queued or while
+//! it combines, the queue never moves again and the other processes wait
+//! forever. The lock does not detect dead processes.
+//!
+//! The combiner runs its own copy of the delegate, so every process has to
+//! pass the same delegate, and the data and requests must not hold pointers
+//! into the memory of one process.
+
+mod lock;
+mod node;
+pub mod segment;
+
+pub use self::lock::MAX_NODES;
+
+pub type ShmCC<T, I, O, F> = lock::ShmCC<T, I, O, F>;
@@ -0,0 +1,391 @@
+use std::{
+    cell::{Cell, SyncUnsafeCell},
+    io,
+    mem::{size_of, size_of_val},
+    sync::atomic::{AtomicU32, AtomicU64, Ordering::*},
+    time::{Duration, Instant},
+};
+
+use crossbeam::utils::{Backoff, CachePadded};
+use thread_local::ThreadLocal;
+
+use crate::dlock2::{slot::Slot, DLock2, DLock2Delegate};
+
+use super::{
+    node::{Node, DONE, NULL, PARKED, WAITING},
+    segment::ShmSegment,
+};
+
+const H: u32 = 64;
+
+/// Nodes in a segment, one per thread of every process attached at the
+/// same time plus the initial tail. A process hands the nodes of its
+/// threads back when it drops the lock.
+pub const MAX_NODES: usize = 1024;
+
+/// Index of no node in the free list
+const NO_NODE: u32 = u32::MAX;
+
+const MAGIC: u64 = u64::from_le_bytes(*b"dlock2cc");
+
+/// How long a process attaching waits for the creator to set the segment up
+const ATTACH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[repr(C)]
+struct Header<T, I, O> {
+    /// `MAGIC` once the creator has set up the segment
+    magic: AtomicU64,
+    /// Size of the header, checked by every process attaching
+    size: u64,
+    processes: AtomicU32,
+    next_node: AtomicU32,
+    /// Index of the first node handed back in the low half, bumped in the
+    /// high half on every push so that a stale pop fails. A free node links
+    /// to the index of the next one through `next`.
+    free: AtomicU64,
+    tail: CachePadded<AtomicU64>,
+    data: CachePadded<SyncUnsafeCell<T>>,
+    nodes: [CachePadded<Node<I, O>>; MAX_NODES],
+}
+
+#[derive(Debug)]
+pub struct ShmCC<T, I, O, F>
+where
+    F: DLock2Delegate<T, I, O>,
+{
+    segment: ShmSegment,
+    delegate: F,
+    /// Offset of the node the thread brings along on its next request
+    local_node: ThreadLocal<Cell<u64>>,
+    phantom: std::marker::PhantomData<fn() -> (T, I, O)>,
+}
+
+impl<T, I, O, F> ShmCC<T, I, O, F>
+where
+    F: DLock2Delegate<T, I, O>,
+{
+    /// Bytes a segment needs to hold the lock.
+    pub const fn segment_size() -> usize {
+        size_of::<Header<T, I, O>>()
+    }
+
+    /// A lock in an anonymous segment used by this process only.
+    pub fn new(data: T, delegate: F) -> Self {
+        let segment = ShmSegment::memfd("dlock2-shm-cc", Self::segment_size())
+            .expect("failed to create the shared memory segment");
+
+        unsafe { Self::create(segment, data, delegate) }.unwrap()
+    }
+
+    /// Set up the lock in `segment` and register this process.
+    ///
+    /// # Safety
+    ///
+    /// Nobody else may use `segment` until this returns. `T` and `I` must be
+    /// valid in every process mapping the segment, i.e. hold no pointers.
+    pub unsafe fn create(segment: ShmSegment, data: T, delegate: F) -> io::Result<Self> {
+        if segment.len() < Self::segment_size() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "shared memory segment too small",
+            ));
+        }
+
+        let header = segment.as_ptr().cast::<Header<T, I, O>>();
+
+        for node in (*header).nodes.iter() {
+            node.next.store(NULL, Relaxed);
+            node.completed.store(false, Relaxed);
+            node.wait.value.store(DONE, Relaxed);
+        }
+
+        (&raw mut (*header).size).write(size_of::<Header<T, I, O>>() as u64);
+        (*header).data.get().write(data);
+        (*header).processes.store(1, Relaxed);
+        (*header).next_node.store(1, Relaxed);
+        (*header).free.store(NO_NODE as u64, Relaxed);
+
+        let lock = Self::with_segment(segment, delegate);
+        lock.header()
+            .tail
+            .store(lock.offset(&lock.header().nodes[0]), Relaxed);
+        lock.header().magic.store(MAGIC, Release);
+
+        Ok(lock)
+    }
+
+    /// Register this process with the lock another process created in
+    /// `segment`.
+    ///
+    /// # Safety
+    ///
+    /// `segment` must have been set up by `create` with the same `T`, `I`
+    /// and delegate.
+    pub unsafe fn attach(segment: ShmSegment, delegate: F) -> io::Result<Self> {
+        if segment.len() < Self::segment_size() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "shared memory segment too small",
+            ));
+        }
+
+        // checked before the lock exists, whose drop would deregister
+        let header = &*segment.as_ptr().cast::<Header<T, I, O>>();
+
+        let begin = Instant::now();
+        let backoff = Backoff::new();
+        while header.magic.load(Acquire) != MAGIC {
+            if begin.elapsed() > ATTACH_TIMEOUT {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "shared memory segment was never set up",
+                ));
+            }
+            backoff.snooze();
+        }
+
+        if header.size != size_of::<Header<T, I, O>>() as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "shared memory segment holds a different lock",
+            ));
+        }
+
+        // once the count drops to 0 the data is being dropped, and stays
+        // dropped
+        let mut processes = header.processes.load(Acquire);
+        loop {
+            if processes == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "the lock in the shared memory segment has been dropped",
+                ));
+            }
+
+            match header
+                .processes
+                .compare_exchange_weak(processes, processes + 1, AcqRel, Acquire)
+            {
+                Ok(_) => break,
+                Err(current) => processes = current,
+            }
+        }
+
+        Ok(Self::with_segment(segment, delegate))
+    }
+
+    fn with_segment(segment: ShmSegment, delegate: F) -> Self {
+        Self {
+            segment,
+            delegate,
+            local_node: ThreadLocal::new(),
+            phantom: std::marker::PhantomData,
+        }
+    }
+
+    pub fn segment(&self) -> &ShmSegment {
+        &self.segment
+    }
+
+    /// Processes registered with the lock.
+    pub fn processes(&self) -> u32 {
+        self.header().processes.load(Acquire)
+    }
+
+    fn header(&self) -> &Header<T, I, O> {
+        unsafe { &*self.segment.as_ptr().cast::<Header<T, I, O>>() }
+    }
+
+    fn offset(&self, node: &Node<I, O>) -> u64 {
+        (node as *const Node<I, O> as usize - self.segment.as_ptr() as usize) as u64
+    }
+
+    fn node(&self, offset: u64) -> &Node<I, O> {
+        unsafe {
+            &*self
+                .segment
+                .as_ptr()
+                .add(offset as usize)
+                .cast::<Node<I, O>>()
+        }
+    }
+
+    fn local_node(&self) -> &Cell<u64> {
+        self.local_node.get_or(|| {
+            let offset = self.pop_free().unwrap_or_else(|| {
+                let index = self.header().next_node.fetch_add(1, Relaxed) as usize;
+                assert!(index < MAX_NODES, "too many threads on the shared lock");
+
+                self.offset(&self.header().nodes[index])
+            });
+
+            Cell::new(offset)
+        })
+    }
+
+    /// Hand back the node at `offset`, which no request may use anymore.
+    fn push_free(&self, offset: u64) {
+        let header = self.header();
+        let index = (offset - self.offset(&header.nodes[0])) / size_of_val(&header.nodes[0]) as u64;
+        let node = self.node(offset);
+
+        let mut free = header.free.load(Relaxed);
+        loop {
+            node.next.store(free & NO_NODE as u64, Relaxed);
+            let tag = (free >> 32).wrapping_add(1);
+
+            match header
+                .free
+                .compare_exchange_weak(free, tag << 32 | index, Release, Relaxed)
+            {
+                Ok(_) => return,
+                Err(current) => free = current,
+            }
+        }
+    }
+
+    /// Offset of a node another thread handed back.
+    fn pop_free(&self) -> Option<u64> {
+        let header = self.header();
+
+        let mut free = header.free.load(Acquire);
+        loop {
+            let index = free as u32;
+            if index == NO_NODE {
+                return None;
+            }
+
+            let node = &header.nodes[index as usize];
+            let next = node.next.load(Relaxed) & NO_NODE as u64;
+
+            match header.free.compare_exchange_weak(
+                free,
+                free & !(NO_NODE as u64) | next,
+                Acquire,
+                Acquire,
+            ) {
+                Ok(_) => return Some(self.offset(node)),
+                Err(current) => free = current,
+            }
+        }
+    }
+
+    /// Block until the node is served or its thread becomes the combiner.
+    fn wait(node: &Node<I, O>) {
+        let backoff = Backoff::new();
+
+        loop {
+            match node.wait.value.load(Acquire) {
+                DONE => return,
+                PARKED => {
+                    let _ = node.wait.wait(PARKED);
+                }
+                _ if backoff.is_completed() => {
+                    let _ = node
+                        .wait
+                        .value
+                        .compare_exchange(WAITING, PARKED, Relaxed, Relaxed);
+                }
+                _ => backoff.snooze(),
+            }
+        }
+    }
+
+    fn wake(node: &Node<I, O>) {
+        if node.wait.value.swap(DONE, Release) == PARKED {
+            node.wait.wake(1);
+        }
+    }
+}
+
+impl<T, I, O, F> Drop for ShmCC<T, I, O, F>
+where
+    F: DLock2Delegate<T, I, O>,
+{
+    fn drop(&mut self) {
+        // no thread of this process is queued anymore, the other processes
+        // can reuse the nodes its threads hold
+        let nodes = self
+            .local_node
+            .iter_mut()
+            .map(|node| node.get())
+            .collect::<Vec<_>>();
+        for offset in nodes {
+            self.push_free(offset);
+        }
+
+        let header = self.header();
+
+        if header.processes.fetch_sub(1, AcqRel) == 1 {
+            // a later `attach` must not find the dropped data set up
+            header.magic.store(0, Release);
+            unsafe { header.data.get().drop_in_place() };
+        }
+    }
+}
+
+unsafe impl<T, I, O, F> DLock2<I, O> for ShmCC<T, I, O, F>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
+{
+    fn lock(&self, data: I) -> O {
+        let local_node = self.local_node();
+        let next_offset = local_node.get();
+        let next_node = self.node(next_offset);
+
+        next_node.next.store(NULL, Relaxed);
+        next_node.completed.store(false, Relaxed);
+        next_node.wait.value.store(WAITING, Relaxed);
+
+        let current_offset = self.header().tail.swap(next_offset, AcqRel);
+        let current_node = self.node(current_offset);
+
+        unsafe { current_node.data.get().write(Slot::input(data)) };
+        current_node.next.store(next_offset, Release);
+        local_node.set(current_offset);
+
+        Self::wait(current_node);
+
+        if current_node.completed.load(Acquire) {
+            return unsafe { (*current_node.data.get()).take_output() };
+        }
+
+        // combiner
+        let mut tmp_node = current_node;
+        let mut counter = 0;
+
+        loop {
+            let next = tmp_node.next.load(Acquire);
+
+            if next == NULL || counter >= H {
+                break;
+            }
+
+            counter += 1;
+
+            unsafe {
+                tmp_node.data.get().write(Slot::output((self.delegate)(
+                    &mut *self.header().data.get(),
+                    (*tmp_node.data.get()).take_input(),
+                )));
+            }
+
+            tmp_node.completed.store(true, Release);
+            Self::wake(tmp_node);
+
+            tmp_node = self.node(next);
+        }
+
+        Self::wake(tmp_node);
+
+        unsafe { (*current_node.data.get()).take_output() }
+    }
+
+    #[cfg(feature = "combiner_stat")]
+    fn get_combine_time(&self) -> Option<u64> {
+        None
+    }
+}
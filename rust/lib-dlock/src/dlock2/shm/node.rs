@@ -0,0 +1,27 @@
+use std::{
+    cell::SyncUnsafeCell,
+    sync::atomic::{AtomicBool, AtomicU64},
+};
+
+use crate::dlock2::slot::Slot;
+
+use linux_futex::{Futex, Shared};
+
+/// Offset of no node, the start of the segment holds the header
+pub const NULL: u64 = 0;
+
+/// The request is still queued or being served
+pub const WAITING: u32 = 0;
+/// As `WAITING`, and the waiter sleeps on the futex
+pub const PARKED: u32 = 1;
+/// Served, or the waiter is the next combiner
+pub const DONE: u32 = 2;
+
+#[repr(C)]
+pub struct Node<I, O> {
+    pub data: SyncUnsafeCell<Slot<I, O>>,
+    pub completed: AtomicBool,
+    pub wait: Futex<Shared>,
+    /// Offset of the next node from the start of the segment
+    pub next: AtomicU64,
+}
@@ -0,0 +1,127 @@
+use std::{
+    ffi::CString,
+    io,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+    ptr::{self, NonNull},
+};
+
+/// A shared memory mapping backed by a `memfd` or a POSIX shared memory
+/// object.
+#[derive(Debug)]
+pub struct ShmSegment {
+    fd: OwnedFd,
+    base: NonNull<u8>,
+    len: usize,
+}
+
+unsafe impl Send for ShmSegment {}
+unsafe impl Sync for ShmSegment {}
+
+impl ShmSegment {
+    /// Anonymous segment of `len` bytes. Other processes map it through the
+    /// file descriptor, e.g. a child inheriting it across `fork`.
+    pub fn memfd(name: &str, len: usize) -> io::Result<Self> {
+        let name = CString::new(name)?;
+        let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+
+        Self::with_len(owned(fd)?, len)
+    }
+
+    /// Create the POSIX shared memory object `name` (e.g. `/dlock`) with
+    /// `len` bytes, failing if it exists.
+    pub fn create(name: &str, len: usize) -> io::Result<Self> {
+        let name = CString::new(name)?;
+        let fd = unsafe {
+            libc::shm_open(
+                name.as_ptr(),
+                libc::O_CREAT | libc::O_EXCL | libc::O_RDWR | libc::O_CLOEXEC,
+                0o600,
+            )
+        };
+
+        Self::with_len(owned(fd)?, len)
+    }
+
+    /// Map the existing POSIX shared memory object `name`.
+    pub fn open(name: &str) -> io::Result<Self> {
+        let name = CString::new(name)?;
+        let fd = unsafe { libc::shm_open(name.as_ptr(), libc::O_RDWR | libc::O_CLOEXEC, 0) };
+
+        Self::from_fd(owned(fd)?)
+    }
+
+    /// Remove the POSIX shared memory object `name`, existing mappings stay
+    /// valid.
+    pub fn unlink(name: &str) -> io::Result<()> {
+        let name = CString::new(name)?;
+        check(unsafe { libc::shm_unlink(name.as_ptr()) }).map(drop)
+    }
+
+    /// Map the whole file behind `fd`.
+    pub fn from_fd(fd: OwnedFd) -> io::Result<Self> {
+        let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+        check(unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) })?;
+
+        Self::map(fd, stat.st_size as usize)
+    }
+
+    fn with_len(fd: OwnedFd, len: usize) -> io::Result<Self> {
+        check(unsafe { libc::ftruncate(fd.as_raw_fd(), len as libc::off_t) })?;
+        Self::map(fd, len)
+    }
+
+    fn map(fd: OwnedFd, len: usize) -> io::Result<Self> {
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            fd,
+            base: NonNull::new(base.cast()).unwrap(),
+            len,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.base.as_ptr()
+    }
+}
+
+impl AsFd for ShmSegment {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl Drop for ShmSegment {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.base.as_ptr().cast(), self.len) };
+    }
+}
+
+fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+fn owned(fd: libc::c_int) -> io::Result<OwnedFd> {
+    check(fd).map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
+}
@@ -0,0 +1,55 @@
+//! Storage of a request in a node of a lock.
+//!
+//! A node holds the input of its request until the request is served and
+//! its output afterwards, never both at once. The slot is a union of the two
+//! so that a node is only as large as the larger of them.
+
+use std::{fmt::Debug, mem::ManuallyDrop, ptr};
+
+pub union Slot<I, O> {
+    input: ManuallyDrop<I>,
+    output: ManuallyDrop<O>,
+    empty: (),
+}
+
+impl<I, O> Slot<I, O> {
+    pub const fn empty() -> Self {
+        Self { empty: () }
+    }
+
+    pub const fn input(input: I) -> Self {
+        Self {
+            input: ManuallyDrop::new(input),
+        }
+    }
+
+    pub const fn output(output: O) -> Self {
+        Self {
+            output: ManuallyDrop::new(output),
+        }
+    }
+
+    /// Move the input out of the slot, which is empty afterwards.
+    ///
+    /// # Safety
+    ///
+    /// The slot must hold an input.
+    pub unsafe fn take_input(&self) -> I {
+        ManuallyDrop::into_inner(ptr::read(&self.input))
+    }
+
+    /// Move the output out of the slot, which is empty afterwards.
+    ///
+    /// # Safety
+    ///
+    /// The slot must hold an output.
+    pub unsafe fn take_output(&self) -> O {
+        ManuallyDrop::into_inner(ptr::read(&self.output))
+    }
+}
+
+impl<I, O> Debug for Slot<I, O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Slot")
+    }
+}
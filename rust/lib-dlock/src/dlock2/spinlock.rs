@@ -1,24 +1,29 @@
-use std::{cell::SyncUnsafeCell, ops::DerefMut};
+use std::{
+    cell::SyncUnsafeCell,
+    ops::DerefMut,
+    time::{Duration, Instant},
+};
 
+use crossbeam::utils::Backoff;
 use lock_api::RawMutex;
 
 use super::{DLock2, DLock2Delegate};
 
 #[derive(Debug)]
-pub struct DLock2Wrapper<T, I, F, L>
+pub struct DLock2Wrapper<T, I, O, F, L>
 where
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
     L: RawMutex,
 {
     delegate: F,
     data: SyncUnsafeCell<T>,
     lock: L,
-    phantom: std::marker::PhantomData<fn() -> I>,
+    phantom: std::marker::PhantomData<fn(I) -> O>,
 }
 
-impl<T, I, F, L> DLock2Wrapper<T, I, F, L>
+impl<T, I, O, F, L> DLock2Wrapper<T, I, O, F, L>
 where
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
     L: RawMutex,
 {
     pub fn new(data: T, delegate: F) -> Self {
@@ -31,14 +36,15 @@ where
     }
 }
 
-unsafe impl<T, I, F, L> DLock2<I> for DLock2Wrapper<T, I, F, L>
+unsafe impl<T, I, O, F, L> DLock2<I, O> for DLock2Wrapper<T, I, O, F, L>
 where
     T: Send + Sync,
     I: Send,
+    O: Send,
     L: RawMutex + Send + Sync,
-    F: DLock2Delegate<T, I>,
+    F: DLock2Delegate<T, I, O>,
 {
-    fn lock(&self, data: I) -> I {
+    fn lock(&self, data: I) -> O {
         self.lock.lock();
         let output = (self.delegate)(unsafe { self.data.get().as_mut().unwrap_unchecked() }, data);
         unsafe {
@@ -47,6 +53,28 @@ where
         output
     }
 
+    fn supports_timeout(&self) -> bool {
+        true
+    }
+
+    fn lock_timeout(&self, data: I, timeout: Duration) -> Result<O, I> {
+        let deadline = Instant::now() + timeout;
+        let backoff = Backoff::new();
+
+        while !self.lock.try_lock() {
+            if Instant::now() >= deadline {
+                return Err(data);
+            }
+            backoff.snooze();
+        }
+
+        let output = (self.delegate)(unsafe { self.data.get().as_mut().unwrap_unchecked() }, data);
+        unsafe {
+            self.lock.unlock();
+        }
+        Ok(output)
+    }
+
     #[cfg(feature = "combiner_stat")]
     fn get_combine_time(&self) -> Option<u64> {
         return None;
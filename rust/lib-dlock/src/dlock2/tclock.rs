@@ -0,0 +1,24 @@
+//! TCLocks-style transparent delegation.
+//!
+//! [`TCLock`] hands out ordinary guards while the critical section is still
+//! executed by the combiner: the requester's continuation is captured as a
+//! context, the combiner switches into it, and dropping the guard switches
+//! back. Switching only saves the callee-saved registers and the floating
+//! point control words on the stack, no system call is involved.
+//!
+//! Because the critical section runs on the combiner's thread, anything tied
+//! to a thread that it touches belongs to the combiner, which is why
+//! `TCLock::new` is unsafe. [`DLock2TCLock`] runs a delegate instead, which
+//! is safe like every other delegation lock.
+
+mod context;
+mod lock;
+mod mutex;
+mod node;
+mod wrapper;
+
+pub use self::{
+    lock::RawTCLock,
+    mutex::{TCLock, TCLockGuard},
+    wrapper::DLock2TCLock,
+};
@@ -0,0 +1,78 @@
+use std::{arch::naked_asm, cell::UnsafeCell, ptr::null_mut};
+
+/// Default MXCSR and x87 control word of a fresh context
+const MXCSR: u32 = 0x1f80;
+const FPU_CONTROL: u32 = 0x037f;
+
+/// A suspended execution context, the stack pointer it was suspended at.
+/// The callee-saved registers and the floating point control words sit on
+/// its stack, so switching never enters the kernel (unlike `swapcontext`,
+/// which saves the signal mask).
+#[repr(transparent)]
+pub struct Context {
+    sp: UnsafeCell<*mut u8>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self {
+            sp: UnsafeCell::new(null_mut()),
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut Context {
+        self as *const Self as *mut Self
+    }
+
+    /// Prepare the context to run `entry` on `stack` the first time it is
+    /// switched to. `entry` must never return.
+    pub unsafe fn prepare(&self, stack: &mut [u8], entry: extern "C" fn() -> !) {
+        let top = (stack.as_mut_ptr().add(stack.len()) as usize & !15) as *mut u64;
+
+        // what `switch` pops: the control words, rbp, rbx and r12-r15, then
+        // `entry` as the return address, entered like after a call
+        let frame = [
+            MXCSR as u64 | (FPU_CONTROL as u64) << 32,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            entry as usize as u64,
+            0,
+        ];
+        let sp = top.sub(frame.len());
+        sp.copy_from_nonoverlapping(frame.as_ptr(), frame.len());
+
+        *self.sp.get() = sp.cast();
+    }
+
+    /// Save the current context into `from` and resume `to`.
+    #[unsafe(naked)]
+    pub unsafe extern "C" fn switch(from: *mut Context, to: *const Context) {
+        naked_asm!(
+            "push rbp",
+            "push rbx",
+            "push r12",
+            "push r13",
+            "push r14",
+            "push r15",
+            "sub rsp, 8",
+            "stmxcsr [rsp]",
+            "fnstcw [rsp + 4]",
+            "mov [rdi], rsp",
+            "mov rsp, [rsi]",
+            "ldmxcsr [rsp]",
+            "fldcw [rsp + 4]",
+            "add rsp, 8",
+            "pop r15",
+            "pop r14",
+            "pop r13",
+            "pop r12",
+            "pop rbx",
+            "pop rbp",
+            "ret",
+        )
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    cell::Cell,
+    ptr::null_mut,
+    sync::atomic::{AtomicPtr, Ordering::*},
+};
+
+use crossbeam::utils::{Backoff, CachePadded};
+use thread_local::ThreadLocal;
+
+use crate::atomic_extension::AtomicExtension;
+
+use super::{context::Context, node::Node};
+
+const H: u32 = 64;
+
+thread_local! {
+    // hands the node to `waiter_entry` the first time its context is entered
+    static ENTERING: Cell<*const Node> = const { Cell::new(null_mut()) };
+}
+
+/// The queue lock behind `TCLock` and `DLock2TCLock`.
+///
+/// Between `lock` and `unlock` the caller runs on the combiner's thread, so
+/// its thread-locals and `thread::current()` are the combiner's. Values tied
+/// to a thread (e.g. `rand::thread_rng()`, an `Rc`) taken in there would
+/// cross to the caller's thread at `unlock`, which is why locking is unsafe.
+#[derive(Debug)]
+pub struct RawTCLock {
+    tail: CachePadded<AtomicPtr<Node>>,
+    /// context of the current combiner, which `unlock` switches back to
+    combiner: AtomicPtr<Context>,
+    /// node whose critical section is currently running on the combiner
+    serving: AtomicPtr<Node>,
+    local_node: ThreadLocal<Box<Node>>,
+}
+
+impl RawTCLock {
+    pub const fn new() -> Self {
+        Self {
+            tail: CachePadded::new(AtomicPtr::new(null_mut())),
+            combiner: AtomicPtr::new(null_mut()),
+            serving: AtomicPtr::new(null_mut()),
+            local_node: ThreadLocal::new(),
+        }
+    }
+
+    fn local_node(&self) -> &Node {
+        self.local_node.get_or(|| Node::new(waiter_entry))
+    }
+
+    /// Publish the continuation of the caller and switch to its waiter context.
+    ///
+    /// This returns on the combiner's thread once the combiner resumes the
+    /// continuation, or on the caller's thread if a `try_lock` failed.
+    fn delegate(&self, node: &Node) {
+        node.lock.store(self as *const _ as *mut _, Relaxed);
+        ENTERING.set(node);
+
+        unsafe {
+            Context::switch(node.requester.as_ptr(), node.waiter.as_ptr());
+        }
+    }
+
+    /// Queue protocol, executed on the waiter stack of `node`. Returns once the
+    /// critical section of `node` has been executed (or `try_lock` failed).
+    fn acquire(&self, node: &Node) {
+        let node_ptr = node as *const _ as *mut Node;
+
+        node.next.store(null_mut(), Relaxed);
+        node.failed.store(false, Relaxed);
+        node.completed.store(false, Relaxed);
+        node.wait.store(true, Relaxed);
+
+        if node.try_only.load(Relaxed) {
+            if self
+                .tail
+                .compare_exchange(null_mut(), node_ptr, AcqRel, Relaxed)
+                .is_err()
+            {
+                node.failed.store(true, Relaxed);
+                return;
+            }
+        } else {
+            let pred = self.tail.swap(node_ptr, AcqRel);
+
+            if let Some(pred) = unsafe { pred.as_ref() } {
+                pred.next.store_release(node_ptr);
+
+                let backoff = Backoff::new();
+                while node.wait.load_acquire() {
+                    backoff.snooze();
+                }
+
+                if node.completed.load_acquire() {
+                    return;
+                }
+            }
+        }
+
+        self.combine(node);
+    }
+
+    fn combine(&self, node: &Node) {
+        let combiner = Context::new();
+        self.combiner.store(combiner.as_ptr(), Relaxed);
+
+        let mut current = node;
+        let mut counter: u32 = 0;
+
+        loop {
+            let current_ptr = current as *const _ as *mut Node;
+
+            // run the critical section until the matching `unlock` switches back
+            self.serving.store(current_ptr, Relaxed);
+            unsafe {
+                Context::switch(combiner.as_ptr(), current.requester.as_ptr());
+            }
+
+            counter += 1;
+
+            // the successor has to be known before `current` is released,
+            // because its owner may reuse it right after
+            let mut next = current.next.load_acquire();
+
+            if next.is_null() {
+                if self
+                    .tail
+                    .compare_exchange(current_ptr, null_mut(), AcqRel, Acquire)
+                    .is_ok()
+                {
+                    Self::release(current);
+                    return;
+                }
+
+                let backoff = Backoff::new();
+                while {
+                    next = current.next.load_acquire();
+                    next.is_null()
+                } {
+                    backoff.spin();
+                }
+            }
+
+            Self::release(current);
+
+            let next = unsafe { &*next };
+
+            if counter >= H {
+                // hand over combining to the successor
+                next.wait.store_release(false);
+                return;
+            }
+
+            current = next;
+        }
+    }
+
+    fn release(node: &Node) {
+        node.completed.store_release(true);
+        node.wait.store_release(false);
+    }
+}
+
+impl RawTCLock {
+    /// Acquire the lock, the caller continues on the combiner's thread.
+    ///
+    /// # Safety
+    ///
+    /// Until the matching `unlock` the caller must not panic, lock this lock
+    /// again, or use anything tied to the thread it runs on: thread-locals,
+    /// the thread's identity, or values that are not `Send`.
+    pub unsafe fn lock(&self) {
+        let node = self.local_node();
+        node.try_only.store(false, Relaxed);
+        self.delegate(node);
+    }
+
+    /// Acquire the lock if nobody holds or waits for it.
+    ///
+    /// # Safety
+    ///
+    /// As `lock` if this returns true.
+    pub unsafe fn try_lock(&self) -> bool {
+        if !self.tail.load(Relaxed).is_null() {
+            return false;
+        }
+
+        let node = self.local_node();
+        node.try_only.store(true, Relaxed);
+        self.delegate(node);
+
+        !node.failed.load(Relaxed)
+    }
+
+    /// Release the lock and switch back to the caller's thread.
+    ///
+    /// # Safety
+    ///
+    /// The caller must hold the lock.
+    pub unsafe fn unlock(&self) {
+        let node = &*self.serving.load(Relaxed);
+
+        Context::switch(node.requester.as_ptr(), self.combiner.load(Relaxed));
+    }
+
+    pub fn is_locked(&self) -> bool {
+        !self.tail.load(Relaxed).is_null()
+    }
+}
+
+impl Default for RawTCLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+extern "C" fn waiter_entry() -> ! {
+    let node = unsafe { &*ENTERING.get() };
+
+    loop {
+        let lock = unsafe { &*node.lock.load(Relaxed) };
+
+        lock.acquire(node);
+
+        // resume the requester after its `unlock` (or its failed `try_lock`)
+        unsafe {
+            Context::switch(node.waiter.as_ptr(), node.requester.as_ptr());
+        }
+    }
+}
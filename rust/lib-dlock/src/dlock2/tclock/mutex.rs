@@ -0,0 +1,99 @@
+use std::{
+    cell::UnsafeCell,
+    fmt::Debug,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use super::lock::RawTCLock;
+
+/// A mutex whose critical sections run on the combiner's thread.
+pub struct TCLock<T> {
+    raw: RawTCLock,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for TCLock<T> {}
+unsafe impl<T: Send> Sync for TCLock<T> {}
+
+impl<T> TCLock<T> {
+    /// # Safety
+    ///
+    /// Every critical section of the lock, from `lock` or `try_lock` until
+    /// the guard is dropped, runs on another thread than the caller's. It
+    /// must not panic, lock this lock again, or use anything tied to the
+    /// thread it runs on: thread-locals (e.g. `rand::thread_rng()`), the
+    /// thread's identity (`thread::current()`), or values that are not
+    /// `Send`.
+    pub unsafe fn new(data: T) -> Self {
+        Self {
+            raw: RawTCLock::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> TCLockGuard<'_, T> {
+        // the contract of `new` covers the critical section
+        unsafe { self.raw.lock() };
+        TCLockGuard {
+            lock: self,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<TCLockGuard<'_, T>> {
+        unsafe { self.raw.try_lock() }.then_some(TCLockGuard {
+            lock: self,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.raw.is_locked()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T> Debug for TCLock<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TCLock")
+            .field("locked", &self.is_locked())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Access to the data of a `TCLock`, the guard stays on the thread that
+/// locked.
+pub struct TCLockGuard<'a, T> {
+    lock: &'a TCLock<T>,
+    phantom: PhantomData<*const ()>,
+}
+
+impl<T> Deref for TCLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for TCLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for TCLockGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe { self.lock.raw.unlock() };
+    }
+}
+
+impl<T: Debug> Debug for TCLockGuard<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    ptr::null_mut,
+    sync::atomic::{AtomicBool, AtomicPtr},
+};
+
+use super::{context::Context, lock::RawTCLock};
+
+pub(super) const STACK_SIZE: usize = 64 * 1024;
+
+pub struct Node {
+    /// continuation of the requester, resumed by the combiner
+    pub requester: Context,
+    /// context running the queue protocol on `stack`
+    pub waiter: Context,
+    pub stack: Box<[u8]>,
+    pub lock: AtomicPtr<RawTCLock>,
+    pub try_only: AtomicBool,
+    pub failed: AtomicBool,
+    pub wait: AtomicBool,
+    pub completed: AtomicBool,
+    pub next: AtomicPtr<Node>,
+}
+
+// The contexts are only touched by the owner of the node or the combiner serving it.
+unsafe impl Send for Node {}
+unsafe impl Sync for Node {}
+
+impl Node {
+    pub fn new(entry: extern "C" fn() -> !) -> Box<Self> {
+        let mut node = Box::new(Self {
+            requester: Context::new(),
+            waiter: Context::new(),
+            stack: vec![0u8; STACK_SIZE].into_boxed_slice(),
+            lock: AtomicPtr::new(null_mut()),
+            try_only: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+            wait: AtomicBool::new(false),
+            completed: AtomicBool::new(false),
+            next: AtomicPtr::new(null_mut()),
+        });
+
+        unsafe {
+            node.waiter.prepare(&mut node.stack, entry);
+        }
+
+        node
+    }
+}
+
+impl std::fmt::Debug for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Node")
+            .field("wait", &self.wait)
+            .field("completed", &self.completed)
+            .finish()
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+use crossbeam::utils::Backoff;
+
+use crate::dlock2::{DLock2, DLock2Delegate};
+
+use super::mutex::TCLock;
+
+/// `TCLock` running a delegate. The delegate runs on the combiner's thread as
+/// in every delegation lock and only its output comes back, so the contract
+/// of `TCLock::new` holds for any delegate.
+#[derive(Debug)]
+pub struct DLock2TCLock<T, I, O, F>
+where
+    F: DLock2Delegate<T, I, O>,
+{
+    delegate: F,
+    lock: TCLock<T>,
+    phantom: std::marker::PhantomData<fn(I) -> O>,
+}
+
+impl<T, I, O, F> DLock2TCLock<T, I, O, F>
+where
+    F: DLock2Delegate<T, I, O>,
+{
+    pub fn new(data: T, delegate: F) -> Self {
+        Self {
+            delegate,
+            lock: unsafe { TCLock::new(data) },
+            phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+unsafe impl<T, I, O, F> DLock2<I, O> for DLock2TCLock<T, I, O, F>
+where
+    T: Send + Sync,
+    I: Send,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
+{
+    fn lock(&self, data: I) -> O {
+        (self.delegate)(&mut self.lock.lock(), data)
+    }
+
+    fn supports_timeout(&self) -> bool {
+        true
+    }
+
+    fn lock_timeout(&self, data: I, timeout: Duration) -> Result<O, I> {
+        let deadline = Instant::now() + timeout;
+        let backoff = Backoff::new();
+
+        loop {
+            if let Some(mut guard) = self.lock.try_lock() {
+                return Ok((self.delegate)(&mut guard, data));
+            }
+            if Instant::now() >= deadline {
+                return Err(data);
+            }
+            backoff.snooze();
+        }
+    }
+
+    #[cfg(feature = "combiner_stat")]
+    fn get_combine_time(&self) -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,94 @@
+//! Requests kept in flight with `DLock2::submit`.
+//!
+//! A ticket stands for a request that sits in one of the calling thread's
+//! slots of the lock until `DLock2::wait` takes its output out. Locks
+//! without slots run the request inside `submit` and hand out a ticket that
+//! already holds the output. A pending ticket dropped without `wait` detaches
+//! its request like `DLock2::lock_detached`, the lock then drops the output.
+
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ptr::{self, NonNull},
+};
+
+/// Detached requests a thread keeps in flight on a lock before it waits for
+/// the oldest one
+pub const MAX_DETACHED: usize = 16;
+
+/// Hands the slot of a dropped pending ticket back to the lock at the
+/// address, as a detached request of the calling thread
+pub(crate) type Detach = unsafe fn(*const (), NonNull<u8>);
+
+#[must_use = "dropping a pending ticket detaches its request, use `lock_detached` to say so"]
+pub struct Ticket<'a, O> {
+    state: TicketState<O>,
+    /// Address of the lock the request was submitted to
+    lock: *const (),
+    detach: Option<Detach>,
+    /// The ticket borrows the lock and its slot belongs to the submitting
+    /// thread
+    phantom: PhantomData<(&'a (), *const ())>,
+}
+
+pub(crate) enum TicketState<O> {
+    Ready(O),
+    /// The slot holding the request
+    Pending(NonNull<u8>),
+}
+
+impl<'a, O> Ticket<'a, O> {
+    pub(crate) fn ready<L: ?Sized>(lock: &'a L, output: O) -> Self {
+        Self {
+            state: TicketState::Ready(output),
+            lock: address(lock),
+            detach: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// A ticket for the request in `slot`, which `detach` hands back to
+    /// `lock` if the ticket is dropped.
+    pub(crate) fn pending<L: ?Sized, N>(lock: &'a L, slot: &N, detach: Detach) -> Self {
+        Self {
+            state: TicketState::Pending(NonNull::from(slot).cast()),
+            lock: address(lock),
+            detach: Some(detach),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Whether the output is already in the ticket.
+    pub fn is_ready(&self) -> bool {
+        matches!(self.state, TicketState::Ready(_))
+    }
+
+    /// The state of a ticket handed out by `lock`.
+    pub(crate) fn redeem<L: ?Sized>(self, lock: &L) -> TicketState<O> {
+        assert_eq!(self.lock, address(lock), "ticket redeemed at another lock");
+
+        let ticket = ManuallyDrop::new(self);
+        unsafe { ptr::read(&ticket.state) }
+    }
+}
+
+impl<O> Drop for Ticket<'_, O> {
+    fn drop(&mut self) {
+        if let (TicketState::Pending(slot), Some(detach)) = (&self.state, self.detach) {
+            unsafe { detach(self.lock, *slot) };
+        }
+    }
+}
+
+impl<O> Debug for Ticket<'_, O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ticket")
+            .field("ready", &self.is_ready())
+            .finish()
+    }
+}
+
+fn address<L: ?Sized>(lock: &L) -> *const () {
+    (lock as *const L).cast()
+}
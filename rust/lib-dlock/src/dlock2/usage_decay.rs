@@ -0,0 +1,58 @@
+//! Decay of the usage that `FCPQ` and `FCSL` order their requests by.
+//!
+//! Without decay a thread that was heavy a long time ago stays behind every
+//! other thread for the rest of the run. The combiner applies the decay
+//! whenever it puts a node back into its priority structure, based on the
+//! tsc elapsed since the node's usage was last decayed.
+
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum UsageDecay {
+    /// Usage accumulates forever
+    #[default]
+    None,
+    /// Usage halves every `cycles` tsc
+    HalfLife { cycles: u64 },
+    /// Only usage within the last `cycles` tsc counts. The recorded usage is
+    /// assumed to be spread evenly over the window, so it fades out linearly.
+    Window { cycles: u64 },
+}
+
+impl UsageDecay {
+    #[inline]
+    pub fn apply(&self, usage: u64, elapsed: u64) -> u64 {
+        match *self {
+            UsageDecay::None => usage,
+            UsageDecay::HalfLife { cycles } => {
+                let halvings = elapsed as f64 / cycles.max(1) as f64;
+                (usage as f64 * (-halvings).exp2()) as u64
+            }
+            UsageDecay::Window { cycles } => {
+                if elapsed >= cycles {
+                    0
+                } else {
+                    (usage as u128 * (cycles - elapsed) as u128 / cycles as u128) as u64
+                }
+            }
+        }
+    }
+
+    /// Decay `usage` up to `now` and move its timestamp along.
+    #[inline]
+    pub fn update(&self, usage: u64, stamp: &mut u64, now: u64) -> u64 {
+        let elapsed = now.saturating_sub(*stamp);
+        *stamp = now;
+        self.apply(usage, elapsed)
+    }
+}
+
+impl Display for UsageDecay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsageDecay::None => write!(f, "none"),
+            UsageDecay::HalfLife { cycles } => write!(f, "half-life {cycles}"),
+            UsageDecay::Window { cycles } => write!(f, "window {cycles}"),
+        }
+    }
+}
@@ -5,20 +5,22 @@ use crate::u_scl::USCL;
 use super::{DLock2, DLock2Delegate};
 
 #[derive(Debug)]
-pub struct DLock2USCL<T, I, F>
+pub struct DLock2USCL<T, I, O, F>
 where
     I: Send,
-    F: DLock2Delegate<T, I>,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
 {
     delegate: F,
     data: USCL<T>,
-    phantom: std::marker::PhantomData<fn() -> I>,
+    phantom: std::marker::PhantomData<fn(I) -> O>,
 }
 
-impl<T, I, F> DLock2USCL<T, I, F>
+impl<T, I, O, F> DLock2USCL<T, I, O, F>
 where
     I: Send,
-    F: DLock2Delegate<T, I>,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
 {
     pub fn new(data: T, delegate: F) -> Self {
         Self {
@@ -29,13 +31,14 @@ where
     }
 }
 
-unsafe impl<T, I, F> DLock2<I> for DLock2USCL<T, I, F>
+unsafe impl<T, I, O, F> DLock2<I, O> for DLock2USCL<T, I, O, F>
 where
     T: Send + Sync,
     I: Send,
-    F: DLock2Delegate<T, I>,
+    O: Send,
+    F: DLock2Delegate<T, I, O>,
 {
-    fn lock(&self, data: I) -> I {
+    fn lock(&self, data: I) -> O {
         let mut lock_data = self.data.lock();
         (self.delegate)(lock_data.deref_mut(), data)
     }
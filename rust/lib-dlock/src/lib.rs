@@ -7,6 +7,9 @@
 #[cfg(not(target_arch = "x86_64"))]
 compile_error!("This crate requires x86_64 (uses __rdtscp and x86-specific C code)");
 
+// lets `#[delegated]`, which names `::libdlock`, expand inside this crate
+extern crate self as libdlock;
+
 pub mod spin_lock;
 mod syncptr;
 pub mod u_scl;
@@ -18,6 +21,7 @@ pub mod dlock2;
 pub mod parker;
 pub mod c_binding;
 pub mod sequential_priority_queue;
+pub mod topology;
 mod atomic_extension;
 
 #[allow(non_upper_case_globals, non_camel_case_types, non_snake_case)]
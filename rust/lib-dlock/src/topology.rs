@@ -0,0 +1,164 @@
+//! CPU clusters for the hierarchical locks.
+//!
+//! A `Topology` maps every CPU to a cluster. It is either read from
+//! `/sys/devices/system/cpu` (CPUs sharing a last-level cache, or CPUs of
+//! the same NUMA node) or given as a map, which allows treating groups of
+//! cores of a single-socket machine as clusters.
+
+use std::{collections::HashMap, fs, io, path::Path};
+
+const SYSFS_CPU: &str = "/sys/devices/system/cpu";
+const SYSFS_NODE: &str = "/sys/devices/system/node";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TopologyLevel {
+    /// CPUs sharing the last-level cache form a cluster
+    #[default]
+    Llc,
+    /// CPUs of the same NUMA node form a cluster
+    Numa,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topology {
+    /// Cluster of every CPU, indexed by CPU id
+    clusters: Vec<usize>,
+    num_clusters: usize,
+}
+
+impl Topology {
+    /// Every CPU in one cluster.
+    pub fn single() -> Self {
+        Self::from_map(vec![0])
+    }
+
+    /// Cluster `map[cpu]` for every CPU, the map is cycled for CPUs past its
+    /// end.
+    pub fn from_map(map: Vec<usize>) -> Self {
+        assert!(!map.is_empty(), "cluster map must not be empty");
+
+        let num_clusters = map.iter().max().unwrap() + 1;
+
+        Self {
+            clusters: map,
+            num_clusters,
+        }
+    }
+
+    pub fn detect(level: TopologyLevel) -> io::Result<Self> {
+        let cpus = parse_cpu_list(fs::read_to_string(Path::new(SYSFS_CPU).join("online"))?.trim())
+            .ok_or_else(|| invalid("online"))?;
+
+        let mut map = vec![0; cpus.iter().max().map_or(1, |max| max + 1)];
+
+        match level {
+            TopologyLevel::Llc => {
+                let mut caches = HashMap::new();
+
+                for &cpu in cpus.iter() {
+                    let shared = last_level_cache(cpu)?;
+                    let next = caches.len();
+                    map[cpu] = *caches.entry(shared).or_insert(next);
+                }
+            }
+            TopologyLevel::Numa => {
+                // without NUMA support every CPU stays in node 0
+                if let Ok(entries) = fs::read_dir(SYSFS_NODE) {
+                    for entry in entries {
+                        let entry = entry?;
+                        let name = entry.file_name();
+                        let Some(node) = name
+                            .to_str()
+                            .and_then(|name| name.strip_prefix("node"))
+                            .and_then(|node| node.parse::<usize>().ok())
+                        else {
+                            continue;
+                        };
+
+                        let cpulist = fs::read_to_string(entry.path().join("cpulist"))?;
+                        for cpu in
+                            parse_cpu_list(cpulist.trim()).ok_or_else(|| invalid("cpulist"))?
+                        {
+                            if cpu < map.len() {
+                                map[cpu] = node;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Self::from_map(map))
+    }
+
+    /// `detect`, falling back to a single cluster if sysfs cannot be read.
+    pub fn detect_or_single(level: TopologyLevel) -> Self {
+        Self::detect(level).unwrap_or_else(|_| Self::single())
+    }
+
+    pub fn num_clusters(&self) -> usize {
+        self.num_clusters
+    }
+
+    pub fn cluster_of(&self, cpu: usize) -> usize {
+        self.clusters[cpu % self.clusters.len()]
+    }
+
+    /// Cluster of the CPU the calling thread currently runs on.
+    pub fn current_cluster(&self) -> usize {
+        self.cluster_of(current_cpu())
+    }
+}
+
+impl Default for Topology {
+    fn default() -> Self {
+        Self::single()
+    }
+}
+
+pub fn current_cpu() -> usize {
+    unsafe { libc::sched_getcpu() }.max(0) as usize
+}
+
+/// Parse a kernel CPU list such as `0-3,8,10-11`.
+pub fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = vec![];
+
+    for range in list.split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<usize>().ok()?..=last.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+
+    Some(cpus)
+}
+
+/// `shared_cpu_list` of the highest cache level of `cpu`
+fn last_level_cache(cpu: usize) -> io::Result<String> {
+    let mut best: Option<(u32, String)> = None;
+
+    for entry in fs::read_dir(Path::new(SYSFS_CPU).join(format!("cpu{cpu}/cache")))? {
+        let path = entry?.path();
+
+        let Ok(level) = fs::read_to_string(path.join("level")) else {
+            continue;
+        };
+        let level = level.trim().parse::<u32>().map_err(|_| invalid("level"))?;
+        let shared = fs::read_to_string(path.join("shared_cpu_list"))?;
+
+        if best.as_ref().map_or(true, |(best, _)| level > *best) {
+            best = Some((level, shared.trim().to_string()));
+        }
+    }
+
+    best.map(|(_, shared)| shared)
+        .ok_or_else(|| invalid("cache"))
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected sysfs {what}"),
+    )
+}
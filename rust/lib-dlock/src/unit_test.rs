@@ -15,6 +15,8 @@ use crate::{
     parker::spin_parker::SpinParker,
 };
 
+mod dlock2;
+
 #[test]
 pub fn fc_test() {
     panic_after(Duration::from_secs(60), || {
@@ -0,0 +1,1633 @@
+//! Conformance suite shared by every `DLock2Impl` variant.
+//!
+//! Each variant is driven by the same audited counter: the delegate checks
+//! that no two delegates overlap, that every thread's requests are applied in
+//! the order they were issued, and each caller checks that it gets back the
+//! result of its own request.
+
+use std::{
+    arch::x86_64::__rdtscp,
+    cell::Cell,
+    collections::{BTreeSet, BinaryHeap},
+    hint::black_box,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering::*},
+        mpsc::{channel, RecvTimeoutError},
+        Arc, OnceLock,
+    },
+    thread::{self, available_parallelism},
+    time::Duration,
+};
+
+use serial_test::serial;
+
+use crate::{
+    c_binding::{ccsynch::CCCSynch, flatcombining::CFlatCombining},
+    dlock2::{
+        ban_policy::{credit_ban, AdaptiveBan, AdaptiveBanConfig, BanPolicy},
+        batch::BatchRequest,
+        cc::CCSynch,
+        cc_ban::CCBan,
+        cohort::{CBoMcs, CTktTkt},
+        delegated::{delegated, Delegatable, Delegated},
+        dsm::DSMSynch,
+        fc::FC,
+        fc_ban::FCBan,
+        fc_edf::FCEDF,
+        fc_pq::{
+            admission::SoftBan,
+            locality::Locality,
+            policy::{CombiningPolicy, Fifo, LeastUsage, Lottery, Stride},
+            FCPQ,
+        },
+        fc_sl::FCSL,
+        ffwd::{Ffwd, GROUP_SIZE, MAX_GROUPS},
+        group::{Groups, DEFAULT_GROUP},
+        hsynch::HSynch,
+        mutex::DLock2Mutex,
+        nesting::take_lock_order_cycles,
+        shfl::{
+            policy::{NoShuffle, NumaGrouping, UsageFair},
+            ShflLock,
+        },
+        shm::{segment::ShmSegment, ShmCC, MAX_NODES},
+        spinlock::DLock2Wrapper,
+        tclock::{DLock2TCLock, TCLock},
+        usage_decay::UsageDecay,
+        DLock2, DLock2Impl,
+    },
+    spin_lock::RawSpinLock,
+    topology::{parse_cpu_list, Topology, TopologyLevel},
+};
+
+const ITERATION: u64 = 2000;
+const OVERSUBSCRIBED_ITERATION: u64 = 200;
+const OVERSUBSCRIPTION: usize = 4;
+const MIN_OVERSUBSCRIBED_THREAD: usize = 16;
+const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Request {
+    thread: usize,
+    seq: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Response {
+    thread: usize,
+    seq: u64,
+    counter: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Auditor {
+    inside: AtomicBool,
+    overlaps: AtomicUsize,
+    reordered: AtomicUsize,
+}
+
+#[derive(Debug)]
+pub struct Shared {
+    auditor: Arc<Auditor>,
+    counter: u64,
+    next_seq: Vec<u64>,
+}
+
+type Delegate = fn(&mut Shared, Request) -> Response;
+type Lock = DLock2Impl<Shared, Request, Response, Delegate>;
+
+fn audited_increment(shared: &mut Shared, request: Request) -> Response {
+    let auditor = shared.auditor.clone();
+
+    if auditor.inside.swap(true, AcqRel) {
+        auditor.overlaps.fetch_add(1, Relaxed);
+    }
+
+    let expected = &mut shared.next_seq[request.thread];
+    if *expected != request.seq {
+        auditor.reordered.fetch_add(1, Relaxed);
+    }
+    *expected = request.seq + 1;
+
+    shared.counter += 1;
+
+    // widen the window in which an overlapping delegate would be observed
+    for i in 0..16 {
+        black_box(i);
+    }
+
+    let counter = shared.counter;
+
+    auditor.inside.store(false, Release);
+
+    Response {
+        thread: request.thread,
+        seq: request.seq,
+        counter,
+    }
+}
+
+/// Answers every other request, the lock runs the rest with its delegate.
+fn audited_batch(shared: &mut Shared, requests: &mut [BatchRequest<Request, Response>]) {
+    for request in requests.iter_mut().step_by(2) {
+        request.answer(|request| audited_increment(shared, request));
+    }
+}
+
+fn cpu_count() -> usize {
+    available_parallelism().unwrap().get()
+}
+
+/// Run `f` on a separate thread and panic with `name` and the per-thread
+/// progress if it does not finish within `timeout`.
+fn watchdog<F>(name: &str, timeout: Duration, progress: Arc<Vec<AtomicU64>>, f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let (done_tx, done_rx) = channel();
+    let handle = thread::spawn(move || {
+        f();
+        done_tx.send(()).expect("Unable to send completion signal");
+    });
+
+    match done_rx.recv_timeout(timeout) {
+        Ok(_) | Err(RecvTimeoutError::Disconnected) => handle.join().expect("Thread panicked"),
+        Err(RecvTimeoutError::Timeout) => {
+            let progress = progress.iter().map(|x| x.load(Relaxed)).collect::<Vec<_>>();
+            panic!("{name} hung after {timeout:?}, completed requests per thread: {progress:?}")
+        }
+    }
+}
+
+fn conformance(new_lock: fn(Shared, Delegate) -> Lock, thread_num: usize, iteration: u64) {
+    let auditor = Arc::new(Auditor::default());
+
+    let lock = Arc::new(new_lock(
+        Shared {
+            auditor: auditor.clone(),
+            counter: 0,
+            // one extra slot for the final read
+            next_seq: vec![0; thread_num + 1],
+        },
+        audited_increment,
+    ));
+
+    let name = lock.to_string();
+    let progress = Arc::new(
+        (0..thread_num)
+            .map(|_| AtomicU64::new(0))
+            .collect::<Vec<_>>(),
+    );
+
+    let lock_ref = lock.clone();
+    let progress_ref = progress.clone();
+
+    watchdog(&name, WATCHDOG_TIMEOUT, progress.clone(), move || {
+        let cpu_count = cpu_count();
+
+        let handles = (0..thread_num)
+            .map(|id| {
+                let lock = lock_ref.clone();
+                let progress = progress_ref.clone();
+
+                thread::Builder::new()
+                    .name(id.to_string())
+                    .spawn(move || {
+                        core_affinity::set_for_current(core_affinity::CoreId {
+                            id: id % cpu_count,
+                        });
+
+                        let mut last_counter = 0;
+
+                        for seq in 0..iteration {
+                            let response = lock.lock(Request { thread: id, seq });
+
+                            assert_eq!(response.thread, id, "received another thread's result");
+                            assert_eq!(response.seq, seq, "received a stale result");
+                            assert!(
+                                response.counter > last_counter,
+                                "results of a thread went backwards"
+                            );
+
+                            last_counter = response.counter;
+                            progress[id].fetch_add(1, Relaxed);
+                        }
+                    })
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    });
+
+    let total = lock.lock(Request {
+        thread: thread_num,
+        seq: 0,
+    });
+
+    assert_eq!(
+        auditor.overlaps.load(Acquire),
+        0,
+        "{name}: delegates overlapped"
+    );
+    assert_eq!(
+        auditor.reordered.load(Acquire),
+        0,
+        "{name}: requests were reordered"
+    );
+    assert_eq!(
+        total.counter,
+        thread_num as u64 * iteration + 1,
+        "{name}: lost updates"
+    );
+
+    println!("finish testing {}", name);
+}
+
+macro_rules! conformance_suite {
+    ($($variant:ident => $new_lock:expr;)*) => {
+        $(
+            mod $variant {
+                use super::*;
+
+                #[test]
+                #[serial]
+                fn counter() {
+                    conformance($new_lock, cpu_count(), ITERATION);
+                }
+
+                #[test]
+                #[serial]
+                fn oversubscribed() {
+                    conformance(
+                        $new_lock,
+                        (cpu_count() * OVERSUBSCRIPTION).max(MIN_OVERSUBSCRIBED_THREAD),
+                        OVERSUBSCRIBED_ITERATION,
+                    );
+                }
+            }
+        )*
+    };
+}
+
+conformance_suite! {
+    fc => |data, f| FC::new(data, f).into();
+    fc_ban => |data, f| FCBan::new(data, f).into();
+    fc_ban_adaptive => |data, f| FCBan::with_ban_policy(data, f, AdaptiveBan::default()).into();
+    cc => |data, f| CCSynch::new(data, f).into();
+    cc_ban => |data, f| CCBan::new(data, f).into();
+    cc_ban_adaptive => |data, f| CCBan::with_ban_policy(data, f, AdaptiveBan::default()).into();
+    dsm => |data, f| DSMSynch::new(data, f).into();
+    hsynch => |data, f| HSynch::new(data, f).into();
+    hsynch_clusters => |data, f| {
+        HSynch::with_topology(data, f, Topology::from_map(vec![0, 1])).into()
+    };
+    ffwd => |data, f| Ffwd::new(data, f).into();
+    shm_cc => |data, f| ShmCC::new(data, f).into();
+    c_bo_mcs => |data, f| {
+        CBoMcs::with_topology(data, f, Topology::from_map(vec![0, 1])).into()
+    };
+    c_tkt_tkt => |data, f| {
+        CTktTkt::with_topology(data, f, Topology::from_map(vec![0, 1])).into()
+    };
+    shfl_lock => |data, f| ShflLock::<_, _, _, _, NoShuffle>::new(data, f).into();
+    shfl_lock_numa => |data, f| {
+        ShflLock::<_, _, _, _, NumaGrouping>::with_topology(data, f, Topology::from_map(vec![0, 1]))
+            .into()
+    };
+    shfl_lock_usage_fair => |data, f| ShflLock::<_, _, _, _, UsageFair>::new(data, f).into();
+    fc_edf => |data, f| FCEDF::new(data, f).into();
+    fc_sl => |data, f| FCSL::new(data, f).into();
+    fc_pq_btree => |data, f| FCPQ::<_, _, _, BTreeSet<_>, _>::new(data, f).into();
+    fc_pq_bheap => |data, f| FCPQ::<_, _, _, BinaryHeap<_>, _>::new(data, f).into();
+    fc_pq_btree_soft_ban => |data, f| {
+        FCPQ::<_, _, _, BTreeSet<_>, _, _, _>::with_admission(data, f, SoftBan::default()).into()
+    };
+    fc_pq_bheap_soft_ban => |data, f| {
+        FCPQ::<_, _, _, BinaryHeap<_>, _, _, _>::with_admission(data, f, SoftBan::default()).into()
+    };
+    fc_pq_btree_fifo => |data, f| {
+        FCPQ::<_, _, _, BTreeSet<_>, _, _, _, _>::with_policy(data, f, Fifo::default()).into()
+    };
+    fc_pq_btree_stride => |data, f| {
+        FCPQ::<_, _, _, BTreeSet<_>, _, _, _, _>::with_policy(data, f, Stride::default()).into()
+    };
+    fc_pq_btree_lottery => |data, f| {
+        FCPQ::<_, _, _, BTreeSet<_>, _, _, _, _>::with_policy(data, f, Lottery::default()).into()
+    };
+    fc_sl_half_life => |data, f| {
+        FCSL::new(data, f).with_usage_decay(UsageDecay::HalfLife { cycles: 10_000 }).into()
+    };
+    fc_pq_btree_window => |data, f| {
+        FCPQ::<_, _, _, BTreeSet<_>, _>::new(data, f)
+            .with_usage_decay(UsageDecay::Window { cycles: 100_000 })
+            .into()
+    };
+    fc_pq_btree_credit => |data, f| {
+        FCPQ::<_, _, _, BTreeSet<_>, _>::new(data, f).with_combiner_credit(0.5).into()
+    };
+    fc_pq_btree_locality => |data, f| {
+        FCPQ::<_, _, _, BTreeSet<_>, _>::new(data, f)
+            .with_locality(Some(Locality {
+                topology: Topology::from_map(vec![0, 1]),
+                epsilon: u64::MAX,
+            }))
+            .into()
+    };
+    fc_batch => |data, f| FC::new(data, f).with_batch_delegate(audited_batch).into();
+    cc_batch => |data, f| CCSynch::new(data, f).with_batch_delegate(audited_batch).into();
+    dsm_batch => |data, f| DSMSynch::new(data, f).with_batch_delegate(audited_batch).into();
+    fc_pq_btree_batch => |data, f| {
+        FCPQ::<_, _, _, BTreeSet<_>, _>::new(data, f)
+            .with_batch_delegate(audited_batch)
+            .into()
+    };
+    fc_ban_credit => |data, f| FCBan::new(data, f).with_combiner_credit(0.5).into();
+    cc_ban_credit => |data, f| CCBan::new(data, f).with_combiner_credit(0.5).into();
+    c_fc => |data, f| CFlatCombining::new(data, f).into();
+    c_cc => |data, f| CCCSynch::new(data, f).into();
+    tclock => |data, f| DLock2TCLock::new(data, f).into();
+}
+
+#[test]
+fn adaptive_ban_controller() {
+    let controller = AdaptiveBan::new(AdaptiveBanConfig {
+        window: 100,
+        ..Default::default()
+    });
+
+    // four threads registered, but only two of them ever get served
+    let ids = (0..4).map(|_| controller.register()).collect::<Vec<_>>();
+    let initial = controller.multiplier();
+
+    for i in 0..1000 {
+        let (id, cs) = if i % 2 == 0 {
+            (ids[0], 300)
+        } else {
+            (ids[1], 100)
+        };
+        controller.penalty(id, cs, ids.len() as u64);
+    }
+
+    let history = controller.history();
+    assert_eq!(history.len(), 10);
+    assert!(history.iter().all(|sample| sample.active_threads == 2));
+    assert!((history[0].max_share - 0.75).abs() < 1e-9);
+    assert!(
+        controller.multiplier() > initial,
+        "unfair shares must raise the penalty"
+    );
+
+    // equal shares relax the multiplier back towards the minimum
+    for i in 0..10000 {
+        controller.penalty(ids[i % 2], 100, ids.len() as u64);
+    }
+
+    assert_eq!(controller.multiplier(), controller.config().min_multiplier);
+
+    // only the most recent windows are kept
+    let controller = AdaptiveBan::new(AdaptiveBanConfig {
+        window: 10,
+        history: 4,
+        ..Default::default()
+    });
+    let id = controller.register();
+    for _ in 0..100 {
+        controller.penalty(id, 100, 1);
+    }
+
+    let windows = controller
+        .history()
+        .iter()
+        .map(|sample| sample.window)
+        .collect::<Vec<_>>();
+    assert_eq!(windows, [6, 7, 8, 9]);
+}
+
+#[test]
+fn combiner_credit() {
+    // a running ban is shortened, but never ends before now
+    let mut banned_until = 1000;
+    credit_ban(&mut banned_until, 300, 500);
+    assert_eq!(banned_until, 700);
+    credit_ban(&mut banned_until, 300, 500);
+    assert_eq!(banned_until, 500);
+
+    // an expired ban is not turned into credit for later
+    let mut banned_until = 400;
+    credit_ban(&mut banned_until, 300, 500);
+    assert_eq!(banned_until, 400);
+}
+
+#[test]
+fn topology() {
+    assert_eq!(
+        parse_cpu_list("0-3,8,10-11"),
+        Some(vec![0, 1, 2, 3, 8, 10, 11])
+    );
+    assert_eq!(parse_cpu_list("0-x"), None);
+
+    let topology = Topology::from_map(vec![0, 0, 1, 1]);
+    assert_eq!(topology.num_clusters(), 2);
+    assert_eq!(topology.cluster_of(2), 1);
+    assert_eq!(topology.cluster_of(5), 0);
+
+    for level in [TopologyLevel::Llc, TopologyLevel::Numa] {
+        let topology = Topology::detect_or_single(level);
+        assert!(topology.current_cluster() < topology.num_clusters());
+    }
+}
+
+#[test]
+#[serial]
+fn hsynch_clusters() {
+    const CLUSTERS: usize = 3;
+
+    let lock = Arc::new(HSynch::<_, _, _, _, RawSpinLock>::with_topology(
+        0u64,
+        |data: &mut u64, input: u64| {
+            *data += input;
+            *data
+        },
+        Topology::from_map((0..CLUSTERS).collect()),
+    ));
+
+    let handles = (0..CLUSTERS * 2)
+        .map(|id| {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                lock.bind_cluster(id % CLUSTERS);
+                for _ in 0..ITERATION {
+                    lock.lock(1);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(lock.lock(0), ITERATION * CLUSTERS as u64 * 2);
+    assert!(lock.cluster_usage().iter().all(|&usage| usage > 0));
+}
+
+#[test]
+#[serial]
+fn cohort_clusters() {
+    const CLUSTERS: usize = 3;
+
+    fn run<L: DLock2<u64, u64> + 'static>(lock: L, bind: fn(&L, usize)) -> L {
+        let lock = Arc::new(lock);
+
+        let handles = (0..CLUSTERS * 2)
+            .map(|id| {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    bind(&lock, id % CLUSTERS);
+                    for _ in 0..ITERATION {
+                        lock.lock(1);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(lock.lock(0), ITERATION * CLUSTERS as u64 * 2);
+        Arc::into_inner(lock).unwrap()
+    }
+
+    let add = |data: &mut u64, input: u64| {
+        *data += input;
+        *data
+    };
+    let topology = Topology::from_map((0..CLUSTERS).collect());
+
+    // without handoffs every acquisition goes through the global lock
+    for max_handoffs in [0, 4] {
+        let lock = run(
+            CBoMcs::with_topology(0u64, add, topology.clone()).with_max_handoffs(max_handoffs),
+            |lock, cluster| lock.bind_cluster(cluster),
+        );
+        assert!(lock.cluster_usage().iter().all(|&usage| usage > 0));
+
+        let lock = run(
+            CTktTkt::with_topology(0u64, add, topology.clone()).with_max_handoffs(max_handoffs),
+            |lock, cluster| lock.bind_cluster(cluster),
+        );
+        assert!(lock.cluster_usage().iter().all(|&usage| usage > 0));
+    }
+}
+
+#[test]
+#[serial]
+fn ffwd_requests_need_not_be_sync() {
+    let lock = Ffwd::new(0u64, |data: &mut u64, input: Cell<u64>| {
+        *data += input.get();
+        Cell::new(*data)
+    });
+
+    assert_eq!(lock.lock(Cell::new(2)).get(), 2);
+    assert_eq!(lock.lock(Cell::new(3)).get(), 5);
+}
+
+#[test]
+#[serial]
+fn ffwd_groups() {
+    const CLIENTS: usize = GROUP_SIZE + 2;
+
+    let lock = Arc::new(Ffwd::new(0u64, |data: &mut u64, input: u64| {
+        *data += input;
+        *data
+    }));
+
+    let handles = (0..CLIENTS)
+        .map(|_| {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                for _ in 0..ITERATION {
+                    lock.lock(1);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(lock.lock(0), ITERATION * CLIENTS as u64);
+    assert_eq!(lock.num_groups(), 2);
+
+    // threads that exited gave their slots back
+    for _ in 0..MAX_GROUPS * GROUP_SIZE + 1 {
+        let lock = lock.clone();
+        thread::spawn(move || lock.lock(1)).join().unwrap();
+    }
+    assert_eq!(lock.num_groups(), 2);
+}
+
+/// Name of the segment `shm_cc_child` attaches to
+const SHM_SEGMENT_ENV: &str = "DLOCK_TEST_SHM_SEGMENT";
+
+type ShmCounter = ShmCC<u64, u64, u64, fn(&mut u64, u64) -> u64>;
+
+fn shm_add(data: &mut u64, input: u64) -> u64 {
+    *data += input;
+    *data
+}
+
+#[test]
+#[serial]
+fn shm_cc_processes() {
+    let name = format!("/dlock-test-{}", std::process::id());
+    let segment = ShmSegment::create(&name, ShmCounter::segment_size()).unwrap();
+    let lock = unsafe { ShmCounter::create(segment, 0, shm_add) }.unwrap();
+
+    let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+        .args([
+            "--exact",
+            "unit_test::dlock2::shm_cc_child",
+            "--ignored",
+            "--nocapture",
+        ])
+        .env(SHM_SEGMENT_ENV, &name)
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    for _ in 0..ITERATION {
+        lock.lock(1);
+    }
+
+    let status = child.wait().unwrap();
+    ShmSegment::unlink(&name).unwrap();
+
+    assert!(status.success());
+    assert_eq!(lock.lock(0), ITERATION * 2);
+    assert_eq!(lock.processes(), 1);
+}
+
+#[test]
+#[serial]
+fn shm_cc_reuses_nodes() {
+    let name = format!("/dlock-test-nodes-{}", std::process::id());
+    let segment = ShmSegment::create(&name, ShmCounter::segment_size()).unwrap();
+    let lock = unsafe { ShmCounter::create(segment, 0, shm_add) }.unwrap();
+
+    // every attachment takes a node for this thread and gives it back when
+    // it is dropped
+    for _ in 0..MAX_NODES * 2 {
+        let attached =
+            unsafe { ShmCounter::attach(ShmSegment::open(&name).unwrap(), shm_add) }.unwrap();
+        attached.lock(1);
+    }
+
+    ShmSegment::unlink(&name).unwrap();
+
+    assert_eq!(lock.lock(0), MAX_NODES as u64 * 2);
+    assert_eq!(lock.processes(), 1);
+}
+
+#[test]
+#[serial]
+fn shm_cc_attach_after_drop() {
+    let name = format!("/dlock-test-dropped-{}", std::process::id());
+    let segment = ShmSegment::create(&name, ShmCounter::segment_size()).unwrap();
+    drop(unsafe { ShmCounter::create(segment, 0, shm_add) }.unwrap());
+
+    let attached = unsafe { ShmCounter::attach(ShmSegment::open(&name).unwrap(), shm_add) };
+    ShmSegment::unlink(&name).unwrap();
+
+    assert!(attached.is_err());
+}
+
+/// The other process of `shm_cc_processes`
+#[test]
+#[ignore]
+fn shm_cc_child() {
+    let Ok(name) = std::env::var(SHM_SEGMENT_ENV) else {
+        return;
+    };
+
+    let lock = unsafe { ShmCounter::attach(ShmSegment::open(&name).unwrap(), shm_add) }.unwrap();
+
+    for _ in 0..ITERATION {
+        lock.lock(1);
+    }
+}
+
+#[test]
+#[serial]
+fn shfl_lock_clusters() {
+    const CLUSTERS: usize = 3;
+
+    let lock = Arc::new(ShflLock::<_, _, _, _, NumaGrouping>::with_topology(
+        0u64,
+        |data: &mut u64, input: u64| {
+            *data += input;
+            *data
+        },
+        Topology::from_map((0..CLUSTERS).collect()),
+    ));
+
+    let handles = (0..CLUSTERS * 2)
+        .map(|id| {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                lock.bind_cluster(id % CLUSTERS);
+                for _ in 0..ITERATION {
+                    lock.lock(1);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(lock.lock(0), ITERATION * CLUSTERS as u64 * 2);
+}
+
+#[test]
+#[serial]
+fn fc_pq_locality() {
+    const CLUSTERS: usize = 2;
+
+    let lock = Arc::new(
+        FCPQ::<_, _, _, BTreeSet<_>, _>::new(0u64, |data: &mut u64, input: u64| {
+            *data += input;
+            *data
+        })
+        .with_locality(Some(Locality {
+            topology: Topology::from_map(vec![0]),
+            epsilon: 1000,
+        })),
+    );
+
+    let handles = (0..CLUSTERS * 2)
+        .map(|id| {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                lock.bind_cluster(id % CLUSTERS);
+                for _ in 0..ITERATION {
+                    lock.lock(1);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(lock.lock(0), ITERATION * CLUSTERS as u64 * 2);
+}
+
+#[test]
+#[serial]
+fn batch_delegate() {
+    const THREADS: usize = 4;
+
+    type Unbatched = fn(&mut u64, u64) -> u64;
+
+    // the plain delegate must never run
+    let unbatched: Unbatched = |_, _| unreachable!();
+
+    let locks: Vec<DLock2Impl<u64, u64, u64, Unbatched>> = vec![
+        FC::new(0, unbatched).into(),
+        CCSynch::new(0, unbatched).into(),
+        DSMSynch::new(0, unbatched).into(),
+        FCPQ::<_, _, _, BTreeSet<_>, _>::new(0, unbatched).into(),
+    ];
+
+    for lock in locks {
+        let batches = Arc::new(AtomicU64::new(0));
+
+        let lock = Arc::new(lock.with_batch_delegate({
+            let batches = batches.clone();
+            move |data: &mut u64, requests: &mut [BatchRequest<u64, u64>]| {
+                batches.fetch_add(1, Relaxed);
+
+                // one addition for the whole batch
+                let base = *data;
+                *data += requests.iter().filter_map(|r| r.input()).sum::<u64>();
+
+                let mut prefix = base;
+                for request in requests.iter_mut() {
+                    request.answer(|input| {
+                        prefix += input;
+                        prefix
+                    });
+                }
+            }
+        }));
+        assert!(lock.is_batched());
+
+        let handles = (0..THREADS)
+            .map(|_| {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    let mut last = 0;
+                    for _ in 0..ITERATION {
+                        let output = lock.lock(1);
+                        assert!(output > last);
+                        last = output;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(lock.lock(0), ITERATION * THREADS as u64);
+        assert!(batches.load(Relaxed) > 0);
+    }
+}
+
+#[test]
+#[serial]
+fn pipelined_submissions() {
+    const THREADS: usize = 4;
+    const IN_FLIGHT: usize = 4;
+
+    type Increment = fn(&mut u64, u64) -> u64;
+
+    let increment: Increment = |data, input| {
+        *data += input;
+        *data
+    };
+
+    let locks: Vec<DLock2Impl<u64, u64, u64, Increment>> = vec![
+        FC::new(0, increment).into(),
+        CCSynch::new(0, increment).into(),
+        // no slots, every ticket is ready
+        DLock2Mutex::new(0, increment).into(),
+    ];
+
+    for lock in locks {
+        let lock = Arc::new(lock);
+
+        let handles = (0..THREADS)
+            .map(|_| {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    let mut tickets = Vec::new();
+                    let mut outputs = Vec::new();
+
+                    for _ in 0..ITERATION {
+                        if tickets.len() == IN_FLIGHT {
+                            outputs.push(lock.wait(tickets.remove(0)));
+                        }
+                        tickets.push(lock.submit(1));
+                    }
+
+                    outputs.extend(tickets.into_iter().map(|ticket| lock.wait(ticket)));
+                    outputs
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut outputs = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+        outputs.sort_unstable();
+
+        // every request was served exactly once
+        let total = ITERATION * THREADS as u64;
+        assert_eq!(outputs, (1..=total).collect::<Vec<_>>(), "{}", lock);
+        assert_eq!(lock.lock(0), total);
+    }
+}
+
+#[test]
+#[serial]
+fn detached_submissions() {
+    const THREADS: usize = 4;
+
+    type Increment = fn(&mut u64, u64) -> u64;
+
+    let increment: Increment = |data, input| {
+        *data += input;
+        *data
+    };
+
+    let locks: Vec<DLock2Impl<u64, u64, u64, Increment>> = vec![
+        FC::new(0, increment).into(),
+        CCSynch::new(0, increment).into(),
+        DLock2Mutex::new(0, increment).into(),
+    ];
+
+    for lock in locks {
+        let lock = Arc::new(lock);
+
+        let handles = (0..THREADS)
+            .map(|_| {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    for i in 0..ITERATION {
+                        // a dropped ticket detaches its request
+                        if i % 2 == 0 {
+                            lock.lock_detached(1);
+                        } else {
+                            drop(lock.submit(1));
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // fc serves the requests left behind at a later combining pass
+        let total = ITERATION * THREADS as u64;
+        let passes = (0..1000).take_while(|_| lock.lock(0) != total).count();
+        assert!(passes < 1000, "{} lost detached requests", lock);
+    }
+}
+
+#[test]
+#[should_panic(expected = "ticket redeemed at another lock")]
+fn ticket_of_another_lock() {
+    let increment = |data: &mut u64, input: u64| {
+        *data += input;
+        *data
+    };
+
+    let first = CCSynch::new(0, increment);
+    let second = CCSynch::new(0, increment);
+
+    let ticket = first.submit(1);
+    second.wait(ticket);
+}
+
+#[test]
+#[serial]
+fn optimistic_reads() {
+    const WRITERS: usize = 2;
+    const READERS: usize = 2;
+
+    type Pair = (u64, u64);
+    type Increment = fn(&mut Pair, u64) -> u64;
+
+    // the pair is only consistent between two combining passes
+    let increment: Increment = |pair, input| {
+        pair.0 += input;
+        black_box(&mut *pair);
+        pair.1 += input;
+        pair.1
+    };
+
+    let locks: Vec<DLock2Impl<Pair, u64, u64, Increment>> = vec![
+        FC::new((0, 0), increment).into(),
+        CCSynch::new((0, 0), increment).into(),
+    ];
+
+    for lock in locks {
+        let lock = Arc::new(lock);
+        let done = Arc::new(AtomicUsize::new(0));
+
+        let writers = (0..WRITERS).map(|_| {
+            let lock = lock.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                for _ in 0..ITERATION {
+                    lock.lock(1);
+                }
+                done.fetch_add(1, Release);
+            })
+        });
+
+        let readers = (0..READERS).map(|_| {
+            let lock = lock.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut last = 0;
+                while done.load(Acquire) < WRITERS {
+                    let read = unsafe { lock.try_read(|pair| (pair.0, pair.1)) };
+
+                    if let Some((first, second)) = read {
+                        assert_eq!(first, second);
+                        assert!(first >= last);
+                        last = first;
+                    }
+                }
+            })
+        });
+
+        for handle in writers.chain(readers).collect::<Vec<_>>() {
+            handle.join().unwrap();
+        }
+
+        let total = ITERATION * WRITERS as u64;
+        assert_eq!(unsafe { lock.try_read(|pair| *pair) }, Some((total, total)));
+    }
+
+    // no version to check, reads have to be delegated
+    let dsm = DLock2Impl::from(DSMSynch::new((0, 0), increment));
+    assert_eq!(unsafe { dsm.try_read(|pair| *pair) }, None);
+}
+
+#[test]
+#[serial]
+fn lock_when_parks_requests() {
+    const CAPACITY: usize = 2;
+    const ITEMS: u64 = 32;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Op {
+        Put(u64),
+        Take,
+    }
+
+    type Buffer = Vec<u64>;
+    type Delegate = fn(&mut Buffer, Op) -> Option<u64>;
+
+    // a parked request is only served once it can go through
+    let delegate: Delegate = |buffer, op| match op {
+        Op::Put(item) => {
+            assert!(buffer.len() < CAPACITY);
+            buffer.push(item);
+            None
+        }
+        Op::Take => Some(buffer.remove(0)),
+    };
+
+    let locks: Vec<DLock2Impl<Buffer, Op, Option<u64>, Delegate>> = vec![
+        FC::new(Vec::new(), delegate).into(),
+        CCSynch::new(Vec::new(), delegate).into(),
+    ];
+
+    for lock in locks {
+        assert!(lock.supports_lock_when());
+
+        let lock = Arc::new(lock);
+
+        let consumer = thread::spawn({
+            let lock = lock.clone();
+            move || {
+                (0..ITEMS)
+                    .map(|_| lock.lock_when(Op::Take, |buffer| !buffer.is_empty()))
+                    .collect::<Vec<_>>()
+            }
+        });
+
+        for item in 0..ITEMS {
+            let output = lock.lock_when(Op::Put(item), |buffer| buffer.len() < CAPACITY);
+            assert_eq!(output, None);
+        }
+
+        let items = consumer.join().unwrap();
+        assert_eq!(items, (0..ITEMS).map(Some).collect::<Vec<_>>());
+    }
+
+    let dsm = DLock2Impl::from(DSMSynch::new(Vec::new(), delegate));
+    assert!(!dsm.supports_lock_when());
+}
+
+#[test]
+#[serial]
+fn nested_delegation() {
+    const THREADS: usize = 4;
+
+    // every increment of `outer` is passed on to `inner` by its combiner
+    let inner = Arc::new(CCSynch::new(0u64, |data: &mut u64, input: u64| {
+        *data += input;
+        *data
+    }));
+
+    let outer = Arc::new(FC::<_, _, _, _, RawSpinLock>::new(0u64, {
+        let inner = inner.clone();
+        move |data: &mut u64, input: u64| {
+            *data += input;
+            inner.lock(input);
+            *data
+        }
+    }));
+
+    let handles = (0..THREADS)
+        .map(|_| {
+            let outer = outer.clone();
+            let inner = inner.clone();
+            thread::spawn(move || {
+                for _ in 0..ITERATION {
+                    outer.lock(1);
+                    inner.lock(1);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let total = ITERATION * THREADS as u64;
+    assert_eq!(outer.lock(0), total);
+    assert_eq!(inner.lock(0), 2 * total);
+}
+
+type Nested = fn(&mut u64, u64) -> u64;
+
+#[test]
+#[serial]
+fn reentrant_delegation_panics() {
+    // the delegate of the lock at index `input` locks it again
+    static LOCKS: OnceLock<Vec<DLock2Impl<u64, u64, u64, Nested>>> = OnceLock::new();
+
+    let delegate: Nested = |_, input| LOCKS.get().unwrap()[input as usize].lock(input);
+
+    LOCKS.get_or_init(|| {
+        vec![
+            FC::new(0, delegate).into(),
+            CCSynch::new(0, delegate).into(),
+            FCBan::new(0, delegate).into(),
+            CCBan::new(0, delegate).into(),
+            FCPQ::<_, _, _, BTreeSet<_>, _>::new(0, delegate).into(),
+            FCSL::new(0, delegate).into(),
+            DSMSynch::new(0, delegate).into(),
+            FCEDF::new(0, delegate).into(),
+            HSynch::new(0, delegate).into(),
+        ]
+    });
+
+    for (i, lock) in LOCKS.get().unwrap().iter().enumerate() {
+        // the locks are left broken behind
+        let run = move || LOCKS.get().unwrap()[i].lock(i as u64);
+        let panic = std::panic::catch_unwind(run).unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+
+        assert!(
+            message.contains("re-entrant delegation"),
+            "{}: {}",
+            lock,
+            message
+        );
+    }
+}
+
+#[test]
+#[serial]
+fn ffwd_reentrant_delegation_panics() {
+    static LOCK: OnceLock<Ffwd<u64, u64, u64, Nested>> = OnceLock::new();
+
+    LOCK.get_or_init(|| Ffwd::new(0, |_, input| LOCK.get().unwrap().lock(input)));
+
+    // the delegate panics on the server, which the client notices
+    let run = || LOCK.get().unwrap().lock(0);
+    let panic = std::panic::catch_unwind(run).unwrap_err();
+
+    assert_eq!(panic.downcast_ref::<&str>(), Some(&"ffwd server panicked"));
+}
+
+#[test]
+#[serial]
+#[cfg(debug_assertions)]
+fn lock_order_cycle() {
+    static FIRST: OnceLock<FC<u64, u64, u64, Nested>> = OnceLock::new();
+    static SECOND: OnceLock<CCSynch<u64, u64, u64, Nested>> = OnceLock::new();
+
+    // 1 makes the delegate lock the other lock
+    FIRST.get_or_init(|| {
+        FC::new(0, |_, input| match input {
+            1 => SECOND.get().unwrap().lock(0),
+            _ => 0,
+        })
+    });
+    SECOND.get_or_init(|| {
+        CCSynch::new(0, |_, input| match input {
+            1 => FIRST.get().unwrap().lock(0),
+            _ => 0,
+        })
+    });
+
+    take_lock_order_cycles();
+
+    // one after another nothing waits, the order still is a cycle
+    FIRST.get().unwrap().lock(1);
+    assert!(take_lock_order_cycles().is_empty());
+
+    SECOND.get().unwrap().lock(1);
+    let cycles = take_lock_order_cycles();
+
+    assert_eq!(cycles.len(), 1);
+    assert!(cycles[0].starts_with("CCSynch@"), "{}", cycles[0]);
+    assert!(cycles[0].contains(" -> FC@"), "{}", cycles[0]);
+}
+
+type Gated = fn(&mut u64, u64) -> u64;
+
+#[test]
+#[serial]
+fn timed_requests() {
+    const THREADS: usize = 4;
+    const SECOND: Duration = Duration::from_secs(1);
+
+    static GATE: AtomicBool = AtomicBool::new(true);
+    static HELD: AtomicBool = AtomicBool::new(false);
+
+    // 0 holds the lock until the gate opens, anything else is added
+    let delegate: Gated = |data, input| {
+        if input == 0 {
+            HELD.store(true, Release);
+            while !GATE.load(Acquire) {
+                thread::yield_now();
+            }
+        }
+        *data += input;
+        *data
+    };
+
+    let locks: Vec<DLock2Impl<u64, u64, u64, Gated>> = vec![
+        FC::new(0, delegate).into(),
+        FCBan::new(0, delegate).into(),
+        FCPQ::<_, _, _, BTreeSet<_>, _>::new(0, delegate).into(),
+        FCPQ::<_, _, _, BTreeSet<_>, _, _, _>::with_admission(0, delegate, SoftBan::default())
+            .into(),
+        CCSynch::new(0, delegate).into(),
+        CCBan::new(0, delegate).into(),
+        DLock2Wrapper::<_, _, _, _, RawSpinLock>::new(0, delegate).into(),
+        DLock2Mutex::new(0, delegate).into(),
+    ];
+
+    for lock in locks {
+        assert!(lock.supports_timeout());
+
+        let name = lock.to_string();
+        let lock = Arc::new(lock);
+
+        assert_eq!(lock.lock_timeout(1, SECOND), Ok(1), "{}", name);
+
+        // a banned thread would give up
+        if !name.contains("Ban") {
+            assert_eq!(lock.try_lock(1), Ok(2), "{}", name);
+        } else {
+            lock.lock(1);
+        }
+
+        GATE.store(false, Release);
+        HELD.store(false, Release);
+
+        let holder = thread::spawn({
+            let lock = lock.clone();
+            move || lock.lock(0)
+        });
+
+        while !HELD.load(Acquire) {
+            thread::yield_now();
+        }
+
+        // the requests are handed back without having run
+        assert_eq!(lock.try_lock(5), Err(5), "{}", name);
+        assert_eq!(
+            lock.lock_timeout(7, Duration::from_millis(10)),
+            Err(7),
+            "{}",
+            name
+        );
+
+        GATE.store(true, Release);
+        assert_eq!(holder.join().unwrap(), 2, "{}", name);
+        assert_eq!(lock.lock_timeout(3, SECOND), Ok(5), "{}", name);
+
+        // every request that returned `Ok` ran exactly once
+        let handles = (0..THREADS)
+            .map(|thread| {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    let mut outputs = Vec::new();
+                    for i in 0..ITERATION / 4 {
+                        let output = match (thread as u64 + i) % 3 {
+                            0 => lock.try_lock(1),
+                            1 => lock.lock_timeout(1, Duration::from_micros(i % 4 * 10)),
+                            _ => Ok(lock.lock(1)),
+                        };
+                        match output {
+                            Ok(output) => outputs.push(output),
+                            Err(input) => assert_eq!(input, 1),
+                        }
+                    }
+                    outputs
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut outputs = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+        outputs.sort();
+
+        let served = outputs.len() as u64;
+        assert_eq!(outputs, (6..6 + served).collect::<Vec<_>>(), "{}", name);
+        assert_eq!(lock.lock(0), 5 + served, "{}", name);
+    }
+
+    let dsm = DLock2Impl::from(DSMSynch::new(0, delegate));
+    assert!(!dsm.supports_timeout());
+}
+
+#[test]
+fn combining_policies() {
+    let mut least_usage = LeastUsage;
+    assert_eq!(least_usage.on_insert(7, 100), 100);
+    assert_eq!(least_usage.on_serve(100, 150, 50), 150);
+
+    // arrival order, a served request goes to the back
+    let mut fifo = Fifo::default();
+    let first = fifo.on_insert(0, 1000);
+    let second = fifo.on_insert(0, 0);
+    assert!(first < second);
+    assert!(fifo.on_serve(first, 1000, 10) > second);
+
+    // a thread returning from a long break starts at the global pass
+    let mut stride = Stride::default();
+    let busy = stride.on_insert(0, 0);
+    let busy = stride.on_serve(busy, 0, 500);
+    let busy = stride.on_serve(busy, 0, 500);
+    assert_eq!(busy, 1000);
+    assert_eq!(stride.on_insert(0, 0), 500);
+    assert_eq!(stride.on_insert(2000, 0), 2000);
+
+    let mut a = Lottery::with_seed(42);
+    let mut b = Lottery::with_seed(42);
+    let draws = (0..16).map(|_| a.on_insert(0, 0)).collect::<Vec<_>>();
+    assert_eq!(
+        draws,
+        (0..16).map(|_| b.on_serve(0, 0, 0)).collect::<Vec<_>>()
+    );
+    assert!(draws.iter().collect::<BTreeSet<_>>().len() == draws.len());
+}
+
+#[test]
+fn groups() {
+    let groups = Groups::new();
+
+    for _ in 0..4 {
+        assert_eq!(groups.register(), DEFAULT_GROUP);
+    }
+    assert!(!groups.in_use());
+
+    let a = groups.join("a", 1, DEFAULT_GROUP);
+    let b = groups.join("b", 3, DEFAULT_GROUP);
+    assert_eq!(groups.join("b", 3, DEFAULT_GROUP), b);
+    assert!(groups.in_use());
+    assert_eq!((groups.members(a), groups.members(b)), (1, 2));
+
+    // the default group still has a member
+    assert_eq!(groups.total_weight(), 5);
+    assert_eq!(groups.scale_penalty(b, 300), 500);
+
+    groups.charge(a, 100);
+    groups.charge(b, 300);
+    assert_eq!(groups.virtual_time(a), groups.virtual_time(b));
+
+    let usage = groups.usage();
+    assert_eq!(usage[b].name, "b");
+    assert_eq!(usage[b].usage, 300);
+
+    // a group that sat idle resumes at the current virtual time instead of
+    // catching up on what the others were served meanwhile
+    for _ in 0..10 {
+        groups.charge(b, 300);
+    }
+    assert_eq!(groups.virtual_time(a), 1000);
+    groups.charge(a, 100);
+    assert_eq!(groups.virtual_time(a), 1100);
+    assert_eq!(groups.usage()[a].usage, 200);
+
+    // locks without groups ignore the call
+    let add = |data: &mut u64, input: u64| {
+        *data += input;
+        *data
+    };
+    let fc = FC::<_, _, _, _, RawSpinLock>::new(0u64, add);
+    assert_eq!(fc.join_group("a", 1), None);
+
+    let fc_pq = FCPQ::<_, _, _, BTreeSet<_>, _>::new(0u64, add);
+    let fc_ban = FCBan::<_, _, _, _>::new(0u64, add);
+
+    for lock in [&fc_pq as &dyn DLock2<u64, u64>, &fc_ban] {
+        let group = lock.join_group("a", 2).unwrap();
+        for i in 1..=10 {
+            assert_eq!(lock.lock(1), i);
+        }
+
+        let usage = lock.groups().unwrap().usage();
+        assert_eq!(usage[group].weight, 2);
+        assert_eq!(usage[group].members, 1);
+        assert_eq!(usage[DEFAULT_GROUP].members, 0);
+        assert!(usage[group].usage > 0);
+    }
+}
+
+#[test]
+fn usage_decay() {
+    assert_eq!(UsageDecay::None.apply(1000, u64::MAX), 1000);
+
+    let half_life = UsageDecay::HalfLife { cycles: 100 };
+    assert_eq!(half_life.apply(1000, 0), 1000);
+    assert_eq!(half_life.apply(1000, 100), 500);
+    assert_eq!(half_life.apply(1000, 300), 125);
+    assert_eq!(half_life.apply(u64::MAX, u64::MAX), 0);
+
+    let window = UsageDecay::Window { cycles: 100 };
+    assert_eq!(window.apply(1000, 25), 750);
+    assert_eq!(window.apply(1000, 100), 0);
+    assert_eq!(window.apply(u64::MAX, 50), u64::MAX / 2);
+
+    let mut stamp = 100;
+    assert_eq!(half_life.update(1000, &mut stamp, 200), 500);
+    assert_eq!(stamp, 200);
+    // a stale timestamp never inflates usage
+    assert_eq!(half_life.update(1000, &mut stamp, 150), 1000);
+}
+
+#[test]
+fn soft_ban_is_work_conserving() {
+    // every thread is above half of the average once it has been served, so
+    // a lone thread is held out on every other pass but must still be served
+    let lock = FCPQ::<_, _, _, BTreeSet<_>, _, RawSpinLock, _>::with_admission(
+        0u64,
+        |data: &mut u64, input: u64| {
+            *data += input;
+            *data
+        },
+        SoftBan {
+            threshold: 0.5,
+            passes: 1,
+        },
+    );
+
+    for i in 1..=100 {
+        assert_eq!(lock.lock(1), i);
+    }
+
+    assert!(lock.get_soft_ban_count().unwrap() > 0);
+}
+
+#[test]
+#[serial]
+fn edf_serves_earliest_deadline_first() {
+    const GATE: usize = usize::MAX;
+    const WAITERS: usize = 4;
+
+    let gate_entered = Arc::new(AtomicBool::new(false));
+    let published = Arc::new(AtomicUsize::new(0));
+    let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let lock = {
+        let gate_entered = gate_entered.clone();
+        let published = published.clone();
+        let order = order.clone();
+
+        Arc::new(FCEDF::<_, _, _, _, RawSpinLock>::new(
+            (),
+            move |_: &mut (), id: usize| {
+                if id == GATE {
+                    // hold the combiner until every waiter has published
+                    gate_entered.store(true, Release);
+                    while published.load(Acquire) < WAITERS + 1 {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    thread::sleep(Duration::from_millis(100));
+                } else {
+                    order.lock().unwrap().push(id);
+                }
+                id
+            },
+        ))
+    };
+
+    let gate = {
+        let lock = lock.clone();
+        thread::spawn(move || lock.lock(GATE))
+    };
+
+    while !gate_entered.load(Acquire) {
+        thread::yield_now();
+    }
+
+    let now = unsafe { __rdtscp(&mut 0) };
+
+    // waiter `id` is due later the smaller its id, waiter `WAITERS` has no
+    // deadline
+    let waiters = (0..=WAITERS)
+        .map(|id| {
+            let lock = lock.clone();
+            let published = published.clone();
+
+            thread::spawn(move || {
+                published.fetch_add(1, Release);
+                if id == WAITERS {
+                    lock.lock(id);
+                } else {
+                    let deadline = now + (((WAITERS - id) as u64) << 40);
+                    lock.lock_with_deadline(id, deadline);
+                }
+                lock.get_deadline_miss_count()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    gate.join().unwrap();
+
+    for waiter in waiters {
+        assert_eq!(waiter.join().unwrap(), Some(0));
+    }
+
+    let expected = (0..WAITERS).rev().chain([WAITERS]).collect::<Vec<_>>();
+    assert_eq!(*order.lock().unwrap(), expected);
+
+    // a deadline in the past is always missed, plain requests never are
+    lock.lock_with_deadline(0, 0);
+    lock.lock(0);
+    assert_eq!(lock.get_deadline_miss_count(), Some(1));
+}
+
+#[test]
+#[serial]
+fn tclock_guard() {
+    let thread_num = (cpu_count() * OVERSUBSCRIPTION).max(MIN_OVERSUBSCRIBED_THREAD);
+    let progress = Arc::new(
+        (0..thread_num)
+            .map(|_| AtomicU64::new(0))
+            .collect::<Vec<_>>(),
+    );
+
+    // the critical sections below only touch the vector
+    let lock = Arc::new(unsafe { TCLock::new(Vec::new()) });
+    let lock_ref = lock.clone();
+    let progress_ref = progress.clone();
+
+    watchdog("TCLock (guard)", WATCHDOG_TIMEOUT, progress, move || {
+        let handles = (0..thread_num)
+            .map(|id| {
+                let lock = lock_ref.clone();
+                let progress = progress_ref.clone();
+
+                thread::spawn(move || {
+                    for seq in 0..OVERSUBSCRIBED_ITERATION {
+                        let mut guard = lock.lock();
+                        guard.push((id, seq));
+                        let len = guard.len();
+                        drop(guard);
+
+                        // the continuation is back on the caller's stack
+                        assert!(len > 0);
+                        progress[id].fetch_add(1, Relaxed);
+
+                        if let Some(guard) = lock.try_lock() {
+                            assert!(!guard.is_empty());
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    });
+
+    let entries = lock.lock();
+    assert_eq!(
+        entries.len(),
+        thread_num * OVERSUBSCRIBED_ITERATION as usize
+    );
+
+    for id in 0..thread_num {
+        let seqs = entries
+            .iter()
+            .filter(|(thread, _)| *thread == id)
+            .map(|(_, seq)| *seq)
+            .collect::<Vec<_>>();
+        assert_eq!(seqs, (0..OVERSUBSCRIBED_ITERATION).collect::<Vec<_>>());
+    }
+}
+
+struct DelegatedStack {
+    items: Vec<u64>,
+    pushes: u64,
+}
+
+#[delegated]
+impl DelegatedStack {
+    // not a request, the macro leaves it alone
+    fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            pushes: 0,
+        }
+    }
+
+    fn push(&mut self, item: u64) {
+        self.items.push(item);
+        self.pushes += 1;
+    }
+
+    fn pop(&mut self) -> Option<u64> {
+        self.items.pop()
+    }
+
+    fn push_many(&mut self, item: u64, count: usize) -> usize {
+        for _ in 0..count {
+            self.push(item);
+        }
+        self.items.len()
+    }
+
+    fn pushes(&mut self) -> u64 {
+        self.pushes
+    }
+}
+
+#[test]
+#[serial]
+fn delegated_object() {
+    const THREADS: usize = 4;
+    const ITEMS: u64 = 1000;
+
+    type Delegate = fn(&mut DelegatedStack, DelegatedStackOp) -> DelegatedStackOutput;
+
+    let delegate: Delegate = DelegatedStack::delegate;
+    let locks: Vec<DLock2Impl<DelegatedStack, _, _, Delegate>> = vec![
+        FC::new(DelegatedStack::new(), delegate).into(),
+        CCSynch::new(DelegatedStack::new(), delegate).into(),
+    ];
+
+    for lock in locks {
+        let stack = Delegated::<DelegatedStack, _>::new(lock);
+
+        thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    for item in 0..ITEMS {
+                        stack.push(item);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(stack.push_many(7, 3), THREADS * ITEMS as usize + 3);
+
+        let mut popped = 0;
+        while let Some(item) = stack.pop() {
+            assert!(item < ITEMS);
+            popped += 1;
+        }
+        assert_eq!(popped, THREADS as u64 * ITEMS + 3);
+        assert_eq!(stack.pushes(), popped);
+    }
+}
@@ -1,5 +1,6 @@
 use std::{path::Path, sync::Arc};
 
+use core_affinity::CoreId;
 use libdlock::dlock::BenchmarkType;
 
 use crate::{
@@ -52,6 +53,13 @@ impl<'a> Bencher<'a> {
                 self.benchmark_dlock2(&DLock2Option {
                     experiment: None,
                     lock_targets: None,
+                    ban_controller: Default::default(),
+                    soft_ban: Default::default(),
+                    usage_decay: Default::default(),
+                    combiner_credit: Default::default(),
+                    topology: Default::default(),
+                    locality: Default::default(),
+                    batch: Default::default(),
                 });
             }
         }
@@ -80,6 +88,21 @@ where
     pub verbose: bool,
 }
 
+/// Cores to run the benchmark threads on, leaving out `server_cpu` unless it
+/// is the only one.
+pub fn client_core_ids(server_cpu: Option<usize>) -> Vec<CoreId> {
+    let core_ids = core_affinity::get_core_ids().unwrap();
+
+    if core_ids.len() == 1 {
+        return core_ids;
+    }
+
+    core_ids
+        .into_iter()
+        .filter(|core_id| Some(core_id.id) != server_cpu)
+        .collect()
+}
+
 pub fn to_dyn<'a, F>(f: F) -> Box<dyn Fn(LockBenchInfo<u64>) + 'a>
 where
     F: Fn(LockBenchInfo<u64>) + 'a,
@@ -1,25 +1,52 @@
 use std::collections::{BTreeSet, BinaryHeap, LinkedList, VecDeque};
+use std::time::Duration;
 
+use crate::benchmark::dlock2::bounded_buffer::bounded_buffer;
+use crate::benchmark::dlock2::counter_deadline::counter_deadline;
+use crate::benchmark::dlock2::counter_multi_process::counter_multi_process;
+use crate::benchmark::dlock2::counter_phase_swap::counter_phase_swap;
+use crate::benchmark::dlock2::counter_pipelined::{counter_pipelined, Pipelining};
 use crate::benchmark::dlock2::fetch_and_multiply::fetch_and_multiply;
+use crate::benchmark::dlock2::read_mostly_map::read_mostly_map;
+use crate::benchmark::dlock2::transfer::transfer;
 use crate::experiment::*;
 use itertools::Itertools;
+use libdlock::dlock2::usage_decay::UsageDecay;
 
 use strum::IntoEnumIterator;
 
-use crate::benchmark::dlock2::proportional_counter::proportional_counter;
+use crate::benchmark::dlock2::proportional_counter::{proportional_counter, GroupMapping};
 use crate::experiment::{DLock2Experiment, DLock2Option};
-use crate::lock_target::DLock2Target;
+use crate::lock_target::{DLock2Target, LockConfig};
 
 use super::bencher::Bencher;
 
+mod bounded_buffer;
+mod counter_deadline;
+mod counter_multi_process;
+mod counter_phase_swap;
+mod counter_pipelined;
 mod fetch_and_multiply;
 pub mod priority_queue;
 mod proportional_counter;
 pub mod queue;
+mod read_mostly_map;
+pub mod stack;
+mod transfer;
 
 pub fn benchmark_dlock2(bencher: &Bencher, option: &DLock2Option) {
     let experiment = &option.experiment;
 
+    let config = &LockConfig {
+        adaptive_ban: (&option.ban_controller).into(),
+        soft_ban: (&option.soft_ban).into(),
+        usage_decay: (&option.usage_decay).into(),
+        combiner_credit: option.combiner_credit.fraction,
+        topology: (&option.topology).into(),
+        locality_epsilon: option.locality.epsilon,
+        batch: option.batch.enabled,
+    };
+
     let experiments = match experiment {
         Some(ref e) => vec![e],
         None => DLock2Experiment::to_vec_ref(),
@@ -42,43 +69,210 @@ pub fn benchmark_dlock2(bencher: &Bencher, option: &DLock2Option) {
                 file_name,
                 include_lock_free,
                 stat_hold_time,
+                thread_groups,
+                group_weights,
             } => proportional_counter(
                 bencher,
                 file_name.as_deref().unwrap_or_else(|| {
-                    name_maybe.insert(format!(
-                        "counter cs {:?} noncs {:?}",
-                        cs_loops, non_cs_loops
-                    ))
+                    let mut name = format!("counter cs {:?} noncs {:?}", cs_loops, non_cs_loops);
+                    if !thread_groups.is_empty() {
+                        name += &format!(" groups {:?} weights {:?}", thread_groups, group_weights);
+                    }
+                    if option.combiner_credit.fraction > 0.0 {
+                        name += &format!(" credit {}", option.combiner_credit.fraction);
+                    }
+                    if option.batch.enabled {
+                        name += " batch";
+                    }
+                    name_maybe.insert(name)
                 }),
                 targets.iter(),
+                config,
                 cs_loops.iter().copied(),
                 non_cs_loops.iter().copied(),
                 *include_lock_free,
                 *stat_hold_time,
+                GroupMapping {
+                    groups: thread_groups,
+                    weights: group_weights,
+                },
+            ),
+            DLock2Experiment::CounterPhaseSwap {
+                cs_loops,
+                non_cs_loops,
+                file_name,
+                sample_interval,
+            } => counter_phase_swap(
+                bencher,
+                file_name.as_deref().unwrap_or_else(|| {
+                    let usage_decay = UsageDecay::from(&option.usage_decay);
+                    let mut name =
+                        format!("counter swap cs {:?} noncs {:?}", cs_loops, non_cs_loops);
+                    if usage_decay != UsageDecay::None {
+                        name += &format!(" decay {}", usage_decay);
+                    }
+                    name_maybe.insert(name)
+                }),
+                targets.iter(),
+                config,
+                cs_loops,
+                non_cs_loops,
+                Duration::from_millis(*sample_interval),
+            ),
+            DLock2Experiment::CounterDeadline {
+                cs_loops,
+                non_cs_loops,
+                slos,
+                file_name,
+            } => counter_deadline(
+                bencher,
+                file_name.as_deref().unwrap_or_else(|| {
+                    name_maybe.insert(format!(
+                        "counter deadline cs {:?} noncs {:?} slo {:?}",
+                        cs_loops, non_cs_loops, slos
+                    ))
+                }),
+                targets.iter(),
+                config,
+                cs_loops,
+                non_cs_loops,
+                slos,
+            ),
+            DLock2Experiment::CounterMultiProcess {
+                processes,
+                cs_loops,
+                non_cs_loops,
+                file_name,
+            } => counter_multi_process(
+                bencher,
+                file_name.as_deref().unwrap_or_else(|| {
+                    name_maybe.insert(format!(
+                        "counter processes {} cs {:?} noncs {:?}",
+                        processes, cs_loops, non_cs_loops
+                    ))
+                }),
+                *processes,
+                cs_loops,
+                non_cs_loops,
+            ),
+            DLock2Experiment::CounterPipelined {
+                cs_loops,
+                non_cs_loops,
+                in_flight,
+                detached,
+                file_name,
+            } => {
+                let runs = in_flight
+                    .iter()
+                    .map(|&n| Pipelining::InFlight(n.max(1)))
+                    .chain(detached.then_some(Pipelining::Detached));
+
+                for pipelining in runs {
+                    let name = match (file_name, pipelining) {
+                        (Some(file_name), Pipelining::InFlight(n)) => {
+                            format!("{} in-flight {}", file_name, n)
+                        }
+                        (Some(file_name), Pipelining::Detached) => {
+                            format!("{} detached", file_name)
+                        }
+                        (None, Pipelining::InFlight(n)) => format!(
+                            "counter cs {:?} noncs {:?} in-flight {}",
+                            cs_loops, non_cs_loops, n
+                        ),
+                        (None, Pipelining::Detached) => format!(
+                            "counter cs {:?} noncs {:?} detached",
+                            cs_loops, non_cs_loops
+                        ),
+                    };
+
+                    counter_pipelined(
+                        bencher,
+                        &name,
+                        targets.iter(),
+                        config,
+                        cs_loops,
+                        non_cs_loops,
+                        pipelining,
+                    );
+                }
+            }
+            DLock2Experiment::ReadMostlyMap {
+                read_ratios,
+                keys,
+                file_name,
+            } => read_mostly_map(
+                bencher,
+                file_name.as_deref().unwrap_or_else(|| {
+                    name_maybe.insert(format!("map keys {} read {:?}", keys, read_ratios))
+                }),
+                targets.iter(),
+                config,
+                read_ratios,
+                *keys,
+            ),
+            DLock2Experiment::BoundedBuffer {
+                capacity,
+                file_name,
+            } => bounded_buffer(
+                bencher,
+                file_name.as_deref().unwrap_or_else(|| {
+                    name_maybe.insert(format!("bounded buffer capacity {}", capacity))
+                }),
+                targets.iter(),
+                config,
+                *capacity,
+            ),
+            DLock2Experiment::Transfer {
+                banks,
+                accounts,
+                file_name,
+            } => transfer(
+                bencher,
+                file_name.as_deref().unwrap_or_else(|| {
+                    name_maybe.insert(format!("transfer banks {} accounts {}", banks, accounts))
+                }),
+                targets.iter(),
+                config,
+                *banks,
+                *accounts,
             ),
             DLock2Experiment::FetchAndMultiply { include_lock_free } => {
-                fetch_and_multiply(bencher, targets.iter(), *include_lock_free)
+                fetch_and_multiply(bencher, targets.iter(), config, *include_lock_free)
             }
             DLock2Experiment::Queue {
                 lock_free_queues,
                 seq_queue_type,
-            } => match seq_queue_type {
-                SeqQueueType::LinkedList => {
-                    queue::benchmark_queue(bencher, LinkedList::new, targets.iter())
-                }
-                SeqQueueType::VecDeque => {
-                    queue::benchmark_queue(bencher, VecDeque::new, targets.iter())
+            } => {
+                match seq_queue_type {
+                    SeqQueueType::LinkedList => {
+                        queue::benchmark_queue(bencher, LinkedList::new, targets.iter(), config)
+                    }
+                    SeqQueueType::VecDeque => {
+                        queue::benchmark_queue(bencher, VecDeque::new, targets.iter(), config)
+                    }
                 }
-            },
+
+                queue::lockfree_queue(bencher, lock_free_queues.iter());
+            }
             DLock2Experiment::PriorityQueue { sequencial_pq_type } => match sequencial_pq_type {
                 SeqPQType::BTreeSet => {
-                    priority_queue::benchmark_pq(bencher, BTreeSet::new, targets.iter())
+                    priority_queue::benchmark_pq(bencher, BTreeSet::new, targets.iter(), config)
                 }
                 SeqPQType::BinaryHeap => {
-                    priority_queue::benchmark_pq(bencher, BinaryHeap::new, targets.iter())
+                    priority_queue::benchmark_pq(bencher, BinaryHeap::new, targets.iter(), config)
                 }
                 SeqPQType::PairingHeap => todo!(),
             },
+            DLock2Experiment::Stack {
+                push_ratios,
+                lock_free_stacks,
+            } => stack::benchmark_stack(
+                bencher,
+                targets.iter(),
+                config,
+                push_ratios.iter().copied(),
+                lock_free_stacks.iter(),
+            ),
         }
     }
 }
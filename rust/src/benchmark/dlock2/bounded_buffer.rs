@@ -0,0 +1,193 @@
+use std::{
+    collections::VecDeque,
+    hint::black_box,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use libdlock::dlock2::{DLock2, DLock2Impl};
+
+use crate::{
+    benchmark::{
+        bencher::{client_core_ids, Bencher},
+        records::{write_results, Records},
+    },
+    lock_target::{DLock2Target, LockConfig},
+};
+
+#[derive(Debug, Clone, Copy)]
+enum BufferOp {
+    /// Only runs a combining pass
+    Nothing,
+    Put(u64),
+    Take,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BufferResult {
+    Done,
+    Taken(u64),
+    /// The buffer had no room for the item, or no item to take
+    Rejected,
+}
+
+/// Producers and consumers on a buffer of `capacity` items, every second
+/// thread is a consumer. Parked runs wait for room or items with
+/// `lock_when`, the others retry a rejected request until it goes through.
+pub fn bounded_buffer<'a>(
+    bencher: &Bencher,
+    file_name: &str,
+    targets: impl Iterator<Item = &'a DLock2Target>,
+    config: &LockConfig,
+    capacity: usize,
+) {
+    for target in targets {
+        for parked in [false, true] {
+            let lock = target.to_locktype(
+                VecDeque::with_capacity(capacity),
+                move |buffer: &mut VecDeque<u64>, op: BufferOp| match op {
+                    BufferOp::Put(item) if buffer.len() < capacity => {
+                        buffer.push_back(item);
+                        BufferResult::Done
+                    }
+                    BufferOp::Take => match buffer.pop_front() {
+                        Some(item) => BufferResult::Taken(item),
+                        None => BufferResult::Rejected,
+                    },
+                    BufferOp::Put(_) => BufferResult::Rejected,
+                    BufferOp::Nothing => BufferResult::Done,
+                },
+                config,
+            );
+
+            match lock {
+                Some(lock) if parked && !lock.supports_lock_when() => {
+                    println!("{} cannot park requests, skipped", lock);
+                }
+                Some(lock) => {
+                    start_benchmark(bencher, file_name, capacity, parked, Arc::new(lock));
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+fn start_benchmark<F>(
+    bencher: &Bencher,
+    file_name: &str,
+    capacity: usize,
+    parked: bool,
+    lock: Arc<DLock2Impl<VecDeque<u64>, BufferOp, BufferResult, F>>,
+) where
+    F: Fn(&mut VecDeque<u64>, BufferOp) -> BufferResult + Send + Sync + 'static,
+{
+    let lock_name = lock.to_string();
+    let mode = if parked { "parked" } else { "retry" };
+    println!(
+        "Start benchmark for {} (bounded buffer, {})",
+        lock_name, mode
+    );
+
+    let stop_signal = Arc::new(AtomicBool::new(false));
+
+    let core_ids = client_core_ids(lock.server_cpu());
+    let core_ids = core_ids.iter().take(bencher.num_thread);
+
+    let records = thread::scope(|scope| {
+        let handles = core_ids
+            .cycle()
+            .take(bencher.num_thread)
+            .enumerate()
+            .map(|(id, core_id)| {
+                let lock_ref = lock.clone();
+                let core_id = *core_id;
+                let stop_signal = stop_signal.clone();
+                let consumer = id % 2 == 1;
+
+                scope.spawn(move || {
+                    core_affinity::set_for_current(core_id);
+
+                    let mut loop_count = 0;
+                    let mut num_acquire = 0;
+
+                    // a parked request gives up once the benchmark is over
+                    let stopped = || stop_signal.load(Ordering::Acquire);
+
+                    while !stopped() {
+                        let op = if consumer {
+                            BufferOp::Take
+                        } else {
+                            BufferOp::Put(loop_count)
+                        };
+
+                        let output = if !parked {
+                            lock_ref.lock(op)
+                        } else if consumer {
+                            lock_ref.lock_when(op, |buffer| !buffer.is_empty() || stopped())
+                        } else {
+                            lock_ref.lock_when(op, |buffer| buffer.len() < capacity || stopped())
+                        };
+
+                        num_acquire += 1;
+
+                        match output {
+                            BufferResult::Rejected => continue,
+                            BufferResult::Taken(item) => {
+                                black_box(item);
+                            }
+                            BufferResult::Done => {}
+                        }
+
+                        loop_count += 1;
+                    }
+
+                    Records {
+                        id,
+                        cpu_id: core_id.id,
+                        loop_count,
+                        num_acquire,
+                        combine_time: lock_ref.get_combine_time(),
+                        locktype: lock_ref.to_string(),
+                        role: Some(if consumer { "consumer" } else { "producer" }.to_string()),
+                        ..Records::from_bencher(bencher)
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        thread::sleep(Duration::from_secs(bencher.duration));
+
+        stop_signal.store(true, Ordering::Release);
+
+        // parked requests only see the stop signal in a combining pass
+        while handles.iter().any(|handle| !handle.is_finished()) {
+            lock.lock(BufferOp::Nothing);
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    for record in records.iter() {
+        println!(
+            "{} {} (requests {})",
+            record.role.as_deref().unwrap_or_default(),
+            record.loop_count,
+            record.num_acquire
+        );
+    }
+
+    write_results(
+        &bencher.output_path.join(&lock_name),
+        &format!("{} {}", file_name, mode),
+        &records,
+    );
+}
@@ -0,0 +1,163 @@
+use std::{
+    arch::x86_64::__rdtscp,
+    hint::black_box,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use itertools::izip;
+use libdlock::dlock2::{fc_edf::NO_DEADLINE, DLock2, DLock2Impl};
+
+use crate::{
+    benchmark::{
+        bencher::{client_core_ids, Bencher},
+        records::{write_results, Records},
+    },
+    lock_target::{DLock2Target, LockConfig},
+};
+
+/// Proportional counter where every request of a thread is due `slo` cycles
+/// after it was issued (no deadline for an `slo` of 0). Misses are counted by
+/// the caller, so locks that ignore deadlines are measured the same way.
+pub fn counter_deadline<'a>(
+    bencher: &Bencher,
+    file_name: &str,
+    targets: impl Iterator<Item = &'a DLock2Target>,
+    config: &LockConfig,
+    cs_loops: &[u64],
+    non_cs_loops: &[u64],
+    slos: &[u64],
+) {
+    for target in targets {
+        let lock = target.to_locktype(
+            0usize,
+            #[inline(never)]
+            |data: &mut usize, loop_limit: u64| {
+                for _ in 0..loop_limit {
+                    *black_box(&mut *data) += 1;
+                }
+                loop_limit
+            },
+            config,
+        );
+
+        if let Some(lock) = lock {
+            start_benchmark(
+                bencher,
+                file_name,
+                cs_loops,
+                non_cs_loops,
+                slos,
+                Arc::new(lock),
+            );
+        }
+    }
+}
+
+fn start_benchmark<F>(
+    bencher: &Bencher,
+    file_name: &str,
+    cs_loops: &[u64],
+    non_cs_loops: &[u64],
+    slos: &[u64],
+    lock: Arc<DLock2Impl<usize, u64, u64, F>>,
+) where
+    F: Fn(&mut usize, u64) -> u64 + Send + Sync + 'static,
+{
+    let lock_name = lock.to_string();
+    println!("Start benchmark for {} (deadline)", lock_name);
+
+    let stop_signal = Arc::new(AtomicBool::new(false));
+
+    let core_ids = client_core_ids(lock.server_cpu());
+    let core_ids = core_ids.iter().take(bencher.num_thread);
+
+    let records = thread::scope(|scope| {
+        let handles = izip!(
+            cs_loops.iter().cycle(),
+            non_cs_loops.iter().cycle(),
+            slos.iter().cycle(),
+            core_ids.cycle()
+        )
+        .take(bencher.num_thread)
+        .enumerate()
+        .map(|(id, (&cs_loop, &non_cs_loop, &slo, core_id))| {
+            let lock_ref = lock.clone();
+            let core_id = *core_id;
+            let stop_signal = stop_signal.clone();
+
+            scope.spawn(move || {
+                core_affinity::set_for_current(core_id);
+
+                let mut loop_count = 0;
+                let mut num_acquire = 0;
+                let mut misses = 0;
+                let mut aux = 0;
+
+                while !stop_signal.load(Ordering::Acquire) {
+                    let begin = unsafe { __rdtscp(&mut aux) };
+
+                    if slo == 0 {
+                        lock_ref.lock_with_deadline(cs_loop, NO_DEADLINE);
+                    } else {
+                        lock_ref.lock_with_deadline(cs_loop, begin + slo);
+
+                        if unsafe { __rdtscp(&mut aux) } > begin + slo {
+                            misses += 1;
+                        }
+                    }
+
+                    loop_count += cs_loop;
+                    num_acquire += 1;
+
+                    for i in 0..non_cs_loop {
+                        black_box(i);
+                    }
+                }
+
+                Records {
+                    id,
+                    cpu_id: core_id.id,
+                    loop_count,
+                    num_acquire,
+                    cs_length: cs_loop,
+                    non_cs_length: Some(non_cs_loop),
+                    combine_time: lock_ref.get_combine_time(),
+                    slo: (slo != 0).then_some(slo),
+                    deadline_miss_count: (slo != 0).then_some(misses),
+                    locktype: lock_ref.to_string(),
+                    ..Records::from_bencher(bencher)
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+        thread::sleep(Duration::from_secs(bencher.duration));
+
+        stop_signal.store(true, Ordering::Release);
+
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    for record in records.iter() {
+        match (record.slo, record.deadline_miss_count) {
+            (Some(slo), Some(misses)) => println!(
+                "{} (cs {}, slo {}): {:.2}% missed",
+                record.loop_count,
+                record.cs_length,
+                slo,
+                misses as f64 * 100.0 / record.num_acquire.max(1) as f64
+            ),
+            _ => println!("{} (cs {})", record.loop_count, record.cs_length),
+        }
+    }
+
+    write_results(&bencher.output_path.join(&lock_name), file_name, &records);
+}
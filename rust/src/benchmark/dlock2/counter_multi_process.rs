@@ -0,0 +1,175 @@
+use std::{
+    fs::File,
+    hint::black_box,
+    io,
+    os::fd::{AsFd, FromRawFd, OwnedFd},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use itertools::izip;
+use libdlock::dlock2::{
+    shm::{segment::ShmSegment, ShmCC},
+    DLock2, DLock2Delegate,
+};
+use nix::libc;
+
+use crate::benchmark::{
+    bencher::{client_core_ids, Bencher},
+    records::{write_results, Records},
+};
+
+#[inline(never)]
+fn delegate(data: &mut usize, loop_limit: u64) -> u64 {
+    for _ in 0..loop_limit {
+        *black_box(&mut *data) += 1;
+    }
+    loop_limit
+}
+
+/// Proportional counter on a `ShmCC` shared by `processes` processes, each
+/// running the configured number of threads. The extra processes are forked
+/// before any thread starts and send their records back through a pipe.
+pub fn counter_multi_process(
+    bencher: &Bencher,
+    file_name: &str,
+    processes: usize,
+    cs_loops: &[u64],
+    non_cs_loops: &[u64],
+) {
+    type Lock = ShmCC<usize, u64, u64, fn(&mut usize, u64) -> u64>;
+
+    let segment = ShmSegment::memfd("dlock-counter", Lock::segment_size())
+        .expect("failed to create the shared memory segment");
+    let lock = unsafe { Lock::create(segment, 0, delegate) }.unwrap();
+
+    println!("Start benchmark for ShmCC ({} processes)", processes);
+
+    let children = (1..processes)
+        .map(|process| {
+            let (read, write) = pipe().unwrap();
+            let fd = lock.segment().as_fd().try_clone_to_owned().unwrap();
+
+            match unsafe { libc::fork() } {
+                -1 => panic!("fork failed: {}", io::Error::last_os_error()),
+                0 => {
+                    drop(read);
+
+                    let segment = ShmSegment::from_fd(fd).unwrap();
+                    let lock = unsafe { Lock::attach(segment, delegate) }.unwrap();
+                    let records = start_threads(bencher, process, cs_loops, non_cs_loops, &lock);
+                    drop(lock);
+
+                    serde_json::to_writer(File::from(write), &records).unwrap();
+                    unsafe { libc::_exit(0) }
+                }
+                pid => (pid, read),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut records = start_threads(bencher, 0, cs_loops, non_cs_loops, &lock);
+
+    for (pid, read) in children {
+        let child_records: Vec<Records> = serde_json::from_reader(File::from(read)).unwrap();
+        records.extend(child_records);
+        unsafe { libc::waitpid(pid, std::ptr::null_mut(), 0) };
+    }
+
+    for record in records.iter() {
+        println!(
+            "{} (process {}, cs {})",
+            record.loop_count,
+            record.id / bencher.num_thread,
+            record.cs_length
+        );
+    }
+
+    println!(
+        "Total loop count: {}",
+        records.iter().map(|record| record.loop_count).sum::<u64>()
+    );
+
+    write_results(&bencher.output_path.join("ShmCC"), file_name, &records);
+}
+
+fn start_threads<F>(
+    bencher: &Bencher,
+    process: usize,
+    cs_loops: &[u64],
+    non_cs_loops: &[u64],
+    lock: &ShmCC<usize, u64, u64, F>,
+) -> Vec<Records>
+where
+    F: DLock2Delegate<usize, u64, u64>,
+{
+    let stop_signal = AtomicBool::new(false);
+
+    let core_ids = client_core_ids(None);
+    let core_ids = core_ids
+        .iter()
+        .cycle()
+        .skip(process * bencher.num_thread)
+        .take(bencher.num_thread);
+
+    thread::scope(|scope| {
+        let handles = izip!(
+            cs_loops.iter().cycle(),
+            non_cs_loops.iter().cycle(),
+            core_ids
+        )
+        .enumerate()
+        .map(|(thread, (&cs_loop, &non_cs_loop, core_id))| {
+            let core_id = *core_id;
+            let stop_signal = &stop_signal;
+
+            scope.spawn(move || {
+                core_affinity::set_for_current(core_id);
+
+                let mut loop_count = 0;
+                let mut num_acquire = 0;
+
+                while !stop_signal.load(Ordering::Acquire) {
+                    loop_count += lock.lock(cs_loop);
+                    num_acquire += 1;
+
+                    for i in 0..non_cs_loop {
+                        black_box(i);
+                    }
+                }
+
+                Records {
+                    id: process * bencher.num_thread + thread,
+                    cpu_id: core_id.id,
+                    loop_count,
+                    num_acquire,
+                    cs_length: cs_loop,
+                    non_cs_length: Some(non_cs_loop),
+                    locktype: "ShmCC".to_string(),
+                    ..Records::from_bencher(bencher)
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+        thread::sleep(Duration::from_secs(bencher.duration));
+
+        stop_signal.store(true, Ordering::Release);
+
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    })
+}
+
+fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
@@ -0,0 +1,249 @@
+use std::{
+    hint::black_box,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use csv::Writer;
+use itertools::izip;
+use libdlock::dlock2::{DLock2, DLock2Impl};
+use serde::Serialize;
+
+use crate::{
+    benchmark::{
+        bencher::{client_core_ids, Bencher},
+        helper::create_plain_writer,
+        records::{write_results, Records},
+    },
+    lock_target::{DLock2Target, LockConfig},
+};
+
+const NUM_PHASE: usize = 2;
+
+#[derive(Serialize)]
+struct TimelineRecord<'a> {
+    locktype: &'a str,
+    elapsed_ms: u128,
+    id: usize,
+    phase: usize,
+    cs_length: u64,
+    loop_count: u64,
+}
+
+/// Proportional counter in two phases: halfway through the run every thread
+/// takes the CS length of its mirror thread (the list of CS lengths is
+/// reversed), so a thread that was light becomes heavy and vice versa.
+/// Cumulative loop counts are sampled periodically to show how quickly
+/// fairness re-converges.
+pub fn counter_phase_swap<'a>(
+    bencher: &Bencher,
+    file_name: &str,
+    targets: impl Iterator<Item = &'a DLock2Target>,
+    config: &LockConfig,
+    cs_loops: &[u64],
+    non_cs_loops: &[u64],
+    sample_interval: Duration,
+) {
+    for target in targets {
+        let lock = target.to_locktype(
+            0usize,
+            #[inline(never)]
+            |data: &mut usize, loop_limit: u64| {
+                for _ in 0..loop_limit {
+                    *black_box(&mut *data) += 1;
+                }
+                loop_limit
+            },
+            config,
+        );
+
+        if let Some(lock) = lock {
+            let lock = Arc::new(lock);
+
+            start_benchmark(
+                bencher,
+                file_name,
+                cs_loops,
+                non_cs_loops,
+                sample_interval,
+                lock,
+            );
+        }
+    }
+}
+
+fn cs_of(cs_loops: &[u64], id: usize, phase: usize) -> u64 {
+    let index = id % cs_loops.len();
+
+    if phase == 0 {
+        cs_loops[index]
+    } else {
+        cs_loops[cs_loops.len() - 1 - index]
+    }
+}
+
+fn start_benchmark<F>(
+    bencher: &Bencher,
+    file_name: &str,
+    cs_loops: &[u64],
+    non_cs_loops: &[u64],
+    sample_interval: Duration,
+    lock: Arc<DLock2Impl<usize, u64, u64, F>>,
+) where
+    F: Fn(&mut usize, u64) -> u64 + Send + Sync + 'static,
+{
+    let lock_name = lock.to_string();
+    println!("Start benchmark for {} (phase swap)", lock_name);
+
+    let stop_signal = Arc::new(AtomicBool::new(false));
+    let phase = Arc::new(AtomicUsize::new(0));
+    let loop_counts = Arc::new(
+        (0..bencher.num_thread)
+            .map(|_| AtomicU64::new(0))
+            .collect::<Vec<_>>(),
+    );
+
+    let core_ids = client_core_ids(lock.server_cpu());
+    let core_ids = core_ids.iter().take(bencher.num_thread);
+
+    let mut timeline = vec![];
+
+    let (phase_records, phase_durations): (Vec<Vec<Records>>, Vec<Duration>) =
+        thread::scope(|scope| {
+            let handles = izip!(non_cs_loops.iter().cycle(), core_ids.cycle())
+                .take(bencher.num_thread)
+                .enumerate()
+                .map(|(id, (non_cs_loop, core_id))| {
+                    let lock_ref = lock.clone();
+                    let core_id = *core_id;
+                    let non_cs_loop = *non_cs_loop;
+                    let stop_signal = stop_signal.clone();
+                    let phase = phase.clone();
+                    let loop_counts = loop_counts.clone();
+
+                    scope.spawn(move || {
+                        core_affinity::set_for_current(core_id);
+
+                        let mut loop_count = [0; NUM_PHASE];
+                        let mut num_acquire = [0; NUM_PHASE];
+
+                        while !stop_signal.load(Ordering::Acquire) {
+                            let current_phase = phase.load(Ordering::Relaxed);
+                            let cs_loop = cs_of(cs_loops, id, current_phase);
+
+                            lock_ref.lock(cs_loop);
+
+                            loop_count[current_phase] += cs_loop;
+                            num_acquire[current_phase] += 1;
+                            loop_counts[id].fetch_add(cs_loop, Ordering::Relaxed);
+
+                            for i in 0..non_cs_loop {
+                                black_box(i);
+                            }
+                        }
+
+                        (0..NUM_PHASE)
+                            .map(|p| Records {
+                                id,
+                                cpu_id: core_id.id,
+                                loop_count: loop_count[p],
+                                num_acquire: num_acquire[p],
+                                cs_length: cs_of(cs_loops, id, p),
+                                non_cs_length: Some(non_cs_loop),
+                                combine_time: lock_ref.get_combine_time(),
+                                soft_ban_count: lock_ref.get_soft_ban_count(),
+                                locktype: lock_ref.to_string(),
+                                ..Records::from_bencher(bencher)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let begin = Instant::now();
+            let half = Duration::from_secs(bencher.duration) / 2;
+            let mut switched_at = None;
+
+            while begin.elapsed() < half * 2 {
+                thread::sleep(sample_interval);
+
+                if switched_at.is_none() && begin.elapsed() >= half {
+                    phase.store(1, Ordering::Relaxed);
+                    switched_at = Some(begin.elapsed());
+                }
+
+                let elapsed = begin.elapsed();
+                let current_phase = phase.load(Ordering::Relaxed);
+
+                for (id, loop_count) in loop_counts.iter().enumerate() {
+                    timeline.push((
+                        elapsed.as_millis(),
+                        id,
+                        current_phase,
+                        cs_of(cs_loops, id, current_phase),
+                        loop_count.load(Ordering::Relaxed),
+                    ));
+                }
+            }
+
+            stop_signal.store(true, Ordering::Release);
+            let total = begin.elapsed();
+            let switched_at = switched_at.unwrap_or(half);
+
+            let mut phase_records = (0..NUM_PHASE).map(|_| vec![]).collect::<Vec<_>>();
+
+            for handle in handles {
+                for (p, record) in handle.join().unwrap().into_iter().enumerate() {
+                    phase_records[p].push(record);
+                }
+            }
+
+            (phase_records, vec![switched_at, total - switched_at])
+        });
+
+    let folder = bencher.output_path.join(&lock_name);
+
+    for (p, (records, duration)) in phase_records.iter().zip(phase_durations).enumerate() {
+        println!("Phase {} ({:?})", p, duration);
+
+        for record in records.iter() {
+            println!("{} (cs {})", record.loop_count, record.cs_length);
+        }
+
+        write_results(&folder, &format!("{file_name} phase {p}"), records);
+    }
+
+    write_timeline(&folder, file_name, &lock_name, &timeline);
+}
+
+fn write_timeline(
+    folder: &Path,
+    file_name: &str,
+    lock_name: &str,
+    timeline: &[(u128, usize, usize, u64, u64)],
+) {
+    let mut writer = Writer::from_writer(
+        create_plain_writer(folder.join(format!("{file_name} (timeline).csv")))
+            .expect("Failed to create writer"),
+    );
+
+    for &(elapsed_ms, id, phase, cs_length, loop_count) in timeline {
+        writer
+            .serialize(TimelineRecord {
+                locktype: lock_name,
+                elapsed_ms,
+                id,
+                phase,
+                cs_length,
+                loop_count,
+            })
+            .expect("Failed to write timeline");
+    }
+
+    writer.flush().expect("Failed to flush timeline");
+}
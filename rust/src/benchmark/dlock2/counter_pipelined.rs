@@ -0,0 +1,169 @@
+use std::{
+    collections::VecDeque,
+    hint::black_box,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use itertools::izip;
+use libdlock::dlock2::{DLock2, DLock2Impl};
+
+use crate::{
+    benchmark::{
+        bencher::{client_core_ids, Bencher},
+        records::{write_results, Records},
+    },
+    lock_target::{DLock2Target, LockConfig},
+};
+
+/// How a thread issues its requests
+#[derive(Debug, Clone, Copy)]
+pub enum Pipelining {
+    /// Up to the given number of submitted requests per thread, waiting for
+    /// the oldest one once they are all in flight. 1 is a plain `lock`.
+    InFlight(usize),
+    /// Every request is detached, its output is never looked at
+    Detached,
+}
+
+/// Proportional counter whose threads keep several requests in flight
+/// instead of waiting for every one of them.
+pub fn counter_pipelined<'a>(
+    bencher: &Bencher,
+    file_name: &str,
+    targets: impl Iterator<Item = &'a DLock2Target>,
+    config: &LockConfig,
+    cs_loops: &[u64],
+    non_cs_loops: &[u64],
+    pipelining: Pipelining,
+) {
+    for target in targets {
+        let lock = target.to_locktype(
+            0usize,
+            #[inline(never)]
+            |data: &mut usize, loop_limit: u64| {
+                for _ in 0..loop_limit {
+                    *black_box(&mut *data) += 1;
+                }
+                loop_limit
+            },
+            config,
+        );
+
+        if let Some(lock) = lock {
+            start_benchmark(
+                bencher,
+                file_name,
+                cs_loops,
+                non_cs_loops,
+                pipelining,
+                Arc::new(lock),
+            );
+        }
+    }
+}
+
+fn start_benchmark<F>(
+    bencher: &Bencher,
+    file_name: &str,
+    cs_loops: &[u64],
+    non_cs_loops: &[u64],
+    pipelining: Pipelining,
+    lock: Arc<DLock2Impl<usize, u64, u64, F>>,
+) where
+    F: Fn(&mut usize, u64) -> u64 + Send + Sync + 'static,
+{
+    let lock_name = lock.to_string();
+    println!("Start benchmark for {} ({:?})", lock_name, pipelining);
+
+    let stop_signal = Arc::new(AtomicBool::new(false));
+
+    let core_ids = client_core_ids(lock.server_cpu());
+    let core_ids = core_ids.iter().take(bencher.num_thread);
+
+    let records = thread::scope(|scope| {
+        let handles = izip!(
+            cs_loops.iter().cycle(),
+            non_cs_loops.iter().cycle(),
+            core_ids.cycle()
+        )
+        .take(bencher.num_thread)
+        .enumerate()
+        .map(|(id, (&cs_loop, &non_cs_loop, core_id))| {
+            let lock_ref = lock.clone();
+            let core_id = *core_id;
+            let stop_signal = stop_signal.clone();
+
+            scope.spawn(move || {
+                core_affinity::set_for_current(core_id);
+
+                let mut loop_count = 0;
+                let mut num_acquire = 0;
+                let mut tickets = VecDeque::new();
+
+                while !stop_signal.load(Ordering::Acquire) {
+                    match pipelining {
+                        Pipelining::InFlight(1) => loop_count += lock_ref.lock(cs_loop),
+                        Pipelining::InFlight(in_flight) => {
+                            if tickets.len() == in_flight {
+                                loop_count += lock_ref.wait(tickets.pop_front().unwrap());
+                            }
+                            tickets.push_back(lock_ref.submit(cs_loop));
+                        }
+                        Pipelining::Detached => {
+                            lock_ref.lock_detached(cs_loop);
+                            loop_count += cs_loop;
+                        }
+                    }
+
+                    num_acquire += 1;
+
+                    for i in 0..non_cs_loop {
+                        black_box(i);
+                    }
+                }
+
+                for ticket in tickets {
+                    loop_count += lock_ref.wait(ticket);
+                }
+
+                Records {
+                    id,
+                    cpu_id: core_id.id,
+                    loop_count,
+                    num_acquire,
+                    cs_length: cs_loop,
+                    non_cs_length: Some(non_cs_loop),
+                    combine_time: lock_ref.get_combine_time(),
+                    locktype: lock_ref.to_string(),
+                    ..Records::from_bencher(bencher)
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+        thread::sleep(Duration::from_secs(bencher.duration));
+
+        stop_signal.store(true, Ordering::Release);
+
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    for record in records.iter() {
+        println!("{} (cs {})", record.loop_count, record.cs_length);
+    }
+
+    println!(
+        "Total loop count: {}",
+        records.iter().map(|record| record.loop_count).sum::<u64>()
+    );
+
+    write_results(&bencher.output_path.join(&lock_name), file_name, &records);
+}
@@ -16,10 +16,10 @@ use rand::Rng;
 
 use crate::{
     benchmark::{
-        bencher::Bencher,
+        bencher::{client_core_ids, Bencher},
         records::{write_results, Records},
     },
-    lock_target::DLock2Target,
+    lock_target::{DLock2Target, LockConfig},
 };
 
 pub struct AtomicF64 {
@@ -88,19 +88,17 @@ pub struct FetchAndMultiplyDLock2 {
     data: AtomicF64,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
-pub enum Data {
-    #[default]
-    Nothing,
-    Input {
-        data: f64,
-        thread_id: ThreadId,
-    },
-    Output {
-        timestamp: u64,
-        is_combiner: bool,
-        data: f64,
-    },
+#[derive(Debug, Clone, Copy)]
+pub struct Input {
+    data: f64,
+    thread_id: ThreadId,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Output {
+    timestamp: u64,
+    is_combiner: bool,
+    data: f64,
 }
 
 impl FetchAndMultiplyDLock2 {
@@ -117,33 +115,27 @@ impl Display for FetchAndMultiplyDLock2 {
     }
 }
 
-unsafe impl DLock2<Data> for FetchAndMultiplyDLock2 {
-    fn lock(&self, input: Data) -> Data {
-        if let Data::Input { thread_id: _, data } = input {
-            // compare and exchange loop for fetch and multiply self.data
-
-            let mut current = self.data.load(Ordering::Acquire);
-
-            loop {
-                let new = current * data;
-                match self.data.compare_exchange_weak(
-                    current,
-                    new,
-                    Ordering::AcqRel,
-                    Ordering::Acquire,
-                ) {
-                    Ok(_) => {
-                        return Data::Output {
-                            timestamp: 0,
-                            is_combiner: false,
-                            data: new,
-                        }
+unsafe impl DLock2<Input, Output> for FetchAndMultiplyDLock2 {
+    fn lock(&self, input: Input) -> Output {
+        // compare and exchange loop for fetch and multiply self.data
+
+        let mut current = self.data.load(Ordering::Acquire);
+
+        loop {
+            let new = current * input.data;
+            match self
+                .data
+                .compare_exchange_weak(current, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => {
+                    return Output {
+                        timestamp: 0,
+                        is_combiner: false,
+                        data: new,
                     }
-                    Err(actual) => current = actual,
                 }
+                Err(actual) => current = actual,
             }
-        } else {
-            panic!("Invalid input")
         }
     }
 
@@ -156,37 +148,34 @@ unsafe impl DLock2<Data> for FetchAndMultiplyDLock2 {
 pub fn fetch_and_multiply<'a>(
     bencher: &Bencher,
     targets: impl Iterator<Item = &'a DLock2Target>,
+    config: &LockConfig,
     include_lock_free: bool,
 ) {
     for target in targets {
         let stat_response_time = bencher.stat_response_time;
 
-        let lock = target.to_locktype(1.0, Data::default(), move |data: &mut f64, input: Data| {
-            let timestamp = unsafe {
-                if stat_response_time {
-                    __rdtscp(&mut 0)
-                } else {
-                    0
-                }
-            };
+        let lock = target.to_locktype(
+            1.0,
+            move |data: &mut f64, input: Input| {
+                let timestamp = unsafe {
+                    if stat_response_time {
+                        __rdtscp(&mut 0)
+                    } else {
+                        0
+                    }
+                };
 
-            if let Data::Input {
-                thread_id,
-                data: multiplier,
-            } = input
-            {
                 let old_value = *data;
-                *data *= multiplier;
+                *data *= input.data;
 
-                return Data::Output {
+                Output {
                     timestamp,
-                    is_combiner: current().id() == thread_id,
+                    is_combiner: current().id() == input.thread_id,
                     data: old_value,
-                };
-            }
-
-            panic!("Invalid input")
-        });
+                }
+            },
+            config,
+        );
 
         if let Some(lock) = lock {
             let lock = Arc::new(lock);
@@ -217,14 +206,14 @@ pub fn fetch_and_multiply<'a>(
 
 fn start_benchmark<'a>(
     bencher: &Bencher,
-    lock_target: Arc<impl DLock2<Data> + 'a + Display>,
+    lock_target: Arc<impl DLock2<Input, Output> + 'a + Display>,
 ) -> Vec<Records> {
     println!("Start benchmark for {}", lock_target);
 
     let stop_signal = Arc::new(AtomicBool::new(false));
     let lock_ref = lock_target;
 
-    let core_ids = core_affinity::get_core_ids().unwrap();
+    let core_ids = client_core_ids(lock_ref.server_cpu());
     let core_ids = core_ids.iter().take(bencher.num_thread);
 
     // println!("{:?}", bencher);
@@ -250,7 +239,7 @@ fn start_benchmark<'a>(
                     let mut num_acquire = 0;
                     let mut aux = 0;
 
-                    let data = Data::Input {
+                    let data = Input {
                         data: 1.000001,
                         thread_id: current().id(),
                     };
@@ -270,16 +259,12 @@ fn start_benchmark<'a>(
 
                         if stat_response_time {
                             let end = unsafe { __rdtscp(&mut aux) };
-                            if let Data::Output { is_combiner, .. } = output {
-                                if is_combiner {
-                                    &mut combiner_latency
-                                } else {
-                                    &mut waiter_latency
-                                }
-                                .push(end - begin);
+                            if output.is_combiner {
+                                &mut combiner_latency
                             } else {
-                                panic!("Invalid output");
+                                &mut waiter_latency
                             }
+                            .push(end - begin);
                         }
 
                         loop_count += 1;
@@ -326,7 +311,7 @@ fn finish_benchmark<'a>(
     output_path: &Path,
     file_name: &str,
     records: Vec<Records>,
-    lock_target: Arc<impl DLock2<Data> + 'static + Display>,
+    lock_target: Arc<impl DLock2<Input, Output> + 'static + Display>,
 ) {
     write_results(output_path, file_name, &records);
 
@@ -338,14 +323,12 @@ fn finish_benchmark<'a>(
 
     println!("Total loop count: {}", total_loop_count);
 
-    let data = Data::Input {
+    let data = Input {
         data: 1.0,
         thread_id: current().id(),
     };
 
     let result = lock_target.lock(data);
 
-    if let Data::Output { data, .. } = result {
-        println!("final result: {}", data);
-    }
+    println!("final result: {}", result.data);
 }
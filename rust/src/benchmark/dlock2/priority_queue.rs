@@ -11,59 +11,39 @@ use std::{
     time::Duration,
 };
 
+use libdlock::dlock2::delegated::{Delegatable, Delegated};
 use rand::Rng;
 
 use crate::{
     benchmark::{
-        bencher::Bencher,
+        bencher::{client_core_ids, Bencher},
         records::{write_results, Records},
     },
-    lock_target::DLock2Target,
+    lock_target::{DLock2Target, LockConfig},
 };
 
-use self::extension::{
-    ConcurrentPriorityQueue, DLock2PriorityQueue, PQData, SequentialPriorityQueue,
-};
+use self::extension::{ConcurrentPriorityQueue, LockedPriorityQueue, SequentialPriorityQueue};
 
 mod extension;
 
-fn pq_operation<'a, T>(
-    queue: &'a mut impl SequentialPriorityQueue<T>,
-    input: PQData<T>,
-) -> PQData<T>
-where
-    T: Send + Default + Ord + Sync + Copy,
-{
-    match input {
-        PQData::Push { data } => {
-            queue.push(data);
-            PQData::Nothing
-        }
-        PQData::Pop => {
-            let output = queue.pop();
-            PQData::PopResult(output)
-        }
-        PQData::Peek => {
-            let output = queue.peek();
-            PQData::PeekResult(output.map(|x| *x))
-        }
-        _ => panic!("Invalid input"),
-    }
-}
-
 pub fn benchmark_pq<'a, S: SequentialPriorityQueue<u64> + Send + Sync + 'static>(
     bencher: &Bencher,
     sequencial_pq: impl Fn() -> S,
     targets: impl Iterator<Item = &'a DLock2Target>,
+    config: &LockConfig,
     // lock_free_queues: &Vec<LockFreeQueue>,
 ) {
     for target in targets {
-        let lock = target.to_locktype(sequencial_pq(), PQData::<u64>::default(), pq_operation);
+        let lock = target.to_locktype(
+            LockedPriorityQueue(sequencial_pq()),
+            LockedPriorityQueue::delegate,
+            config,
+        );
 
         if let Some(lock) = lock {
-            let queue = DLock2PriorityQueue::<u64, S, _>::new(lock);
+            let queue = Delegated::<LockedPriorityQueue<S>, _>::new(lock);
 
-            let lockname = format!("{}-queue", queue.inner);
+            let lockname = format!("{}-queue", queue);
             let records = start_benchmark(bencher, queue, &lockname);
             finish_benchmark(
                 &bencher.output_path,
@@ -92,7 +72,7 @@ where
     let stop_signal = Arc::new(AtomicBool::new(false));
     let lock_ref = Arc::new(concurrent_queue);
 
-    let core_ids = core_affinity::get_core_ids().unwrap();
+    let core_ids = client_core_ids(lock_ref.server_cpu());
     let core_ids = core_ids.iter().take(bencher.num_thread);
 
     // println!("{:?}", bencher);
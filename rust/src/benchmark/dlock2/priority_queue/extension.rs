@@ -1,6 +1,9 @@
 use std::collections::{BTreeSet, BinaryHeap};
 
-use libdlock::dlock2::DLock2;
+use libdlock::dlock2::{
+    delegated::{delegated, Delegated},
+    DLock2,
+};
 
 use crossbeam_skiplist::SkipSet;
 
@@ -11,6 +14,11 @@ where
     fn push(&self, item: T);
     fn peek(&self) -> Option<T>;
     fn pop(&self) -> Option<T>;
+
+    /// CPU taken by a dedicated server thread, see `DLock2::server_cpu`
+    fn server_cpu(&self) -> Option<usize> {
+        None
+    }
 }
 
 unsafe impl<T> ConcurrentPriorityQueue<T> for SkipSet<T>
@@ -38,87 +46,43 @@ where
     fn pop(&mut self) -> Option<T>;
 }
 
-pub struct DLock2PriorityQueue<'a, T, Q, L>
-where
-    T: PartialOrd + Ord + Eq + Send + Sync + 'a,
-    Q: SequentialPriorityQueue<T>,
-    L: DLock2<PQData<T>>,
-{
-    pub(crate) inner: L,
-    pub(crate) phantom: std::marker::PhantomData<T>,
-    pub(crate) phantom2: std::marker::PhantomData<Q>,
-    phantom3: std::marker::PhantomData<&'a ()>,
-}
+/// A sequential priority queue whose operations are delegated to a lock
+pub struct LockedPriorityQueue<Q>(pub Q);
 
-impl<'a, T, Q, L> DLock2PriorityQueue<'a, T, Q, L>
-where
-    T: PartialOrd + Ord + Eq + Send + Sync,
-    Q: SequentialPriorityQueue<T>,
-    L: DLock2<PQData<T>>,
-{
-    pub fn new(inner: L) -> Self {
-        DLock2PriorityQueue {
-            inner,
-            phantom: std::marker::PhantomData,
-            phantom2: std::marker::PhantomData,
-            phantom3: std::marker::PhantomData,
-        }
+#[delegated]
+impl<Q: SequentialPriorityQueue<u64>> LockedPriorityQueue<Q> {
+    pub fn push(&mut self, item: u64) {
+        self.0.push(item);
     }
-}
-
-unsafe impl<'a, T, Q, L> Send for DLock2PriorityQueue<'a, T, Q, L>
-where
-    T: PartialOrd + Ord + Eq + Send + Sync,
-    Q: SequentialPriorityQueue<T>,
-    L: DLock2<PQData<T>>,
-{
-}
 
-unsafe impl<'a, T, Q, L> Sync for DLock2PriorityQueue<'a, T, Q, L>
-where
-    T: PartialOrd + Ord + Eq + Send + Sync,
-    Q: SequentialPriorityQueue<T>,
-    L: DLock2<PQData<T>>,
-{
-}
+    pub fn peek(&mut self) -> Option<u64> {
+        self.0.peek().copied()
+    }
 
-#[derive(Debug, Default)]
-pub(crate) enum PQData<T> {
-    #[default]
-    Nothing,
-    Push {
-        data: T,
-    },
-    Pop,
-    Peek,
-    PeekResult(Option<T>),
-    PopResult(Option<T>),
+    pub fn pop(&mut self) -> Option<u64> {
+        self.0.pop()
+    }
 }
 
-unsafe impl<'a, T, Q, L> ConcurrentPriorityQueue<T> for DLock2PriorityQueue<'a, T, Q, L>
+unsafe impl<Q, L> ConcurrentPriorityQueue<u64> for Delegated<LockedPriorityQueue<Q>, L>
 where
-    T: PartialOrd + Ord + Eq + Send + Sync,
-    Q: SequentialPriorityQueue<T>,
-    L: DLock2<PQData<T>>,
+    Q: SequentialPriorityQueue<u64>,
+    L: DLock2<LockedPriorityQueueOp, LockedPriorityQueueOutput>,
 {
-    fn push(&self, item: T) {
-        self.inner.lock(PQData::Push { data: item });
+    fn push(&self, item: u64) {
+        DelegatedLockedPriorityQueue::push(self, item);
     }
 
-    fn pop(&self) -> Option<T> {
-        if let PQData::PopResult(result) = self.inner.lock(PQData::Pop) {
-            result
-        } else {
-            panic!("DLock2PriorityQueue::pop: unexpected result");
-        }
+    fn pop(&self) -> Option<u64> {
+        DelegatedLockedPriorityQueue::pop(self)
     }
 
-    fn peek(&self) -> Option<T> {
-        if let PQData::PeekResult(item) = self.inner.lock(PQData::Peek) {
-            item
-        } else {
-            panic!("DLock2PriorityQueue::peek: unexpected result");
-        }
+    fn peek(&self) -> Option<u64> {
+        DelegatedLockedPriorityQueue::peek(self)
+    }
+
+    fn server_cpu(&self) -> Option<usize> {
+        self.inner().server_cpu()
     }
 }
 
@@ -1,5 +1,6 @@
 use std::{
     arch::x86_64::__rdtscp,
+    collections::BTreeSet,
     fmt::Display,
     hint::black_box,
     iter::zip,
@@ -13,49 +14,45 @@ use std::{
 };
 
 use bitvec::prelude::*;
+use csv::Writer;
 use itertools::izip;
-use libdlock::{dlock2::DLock2, FILENAME_MAX};
+use libdlock::{
+    dlock2::{ban_policy::AdaptiveBan, batch::BatchRequest, DLock2},
+    FILENAME_MAX,
+};
+use serde::Serialize;
 
 use crate::{
     benchmark::{
-        bencher::Bencher,
+        bencher::{client_core_ids, Bencher},
+        helper::create_plain_writer,
         records::{write_results, Records},
     },
-    lock_target::DLock2Target,
+    lock_target::{DLock2Target, LockConfig},
 };
 
 struct FetchAddDlock2 {
     data: AtomicUsize,
 }
 
-unsafe impl DLock2<Data> for FetchAddDlock2 {
+unsafe impl DLock2<Input, Output> for FetchAddDlock2 {
     #[inline(always)]
-    fn lock(&self, input: Data) -> Data {
-        let input = black_box(input);
-
-        if let Data::Input {
-            thread_id: _,
-            data: loop_limit,
-        } = input
-        {
-            // it is very important to have black_box here
-            let mut loop_limit = loop_limit;
-
-            let mut last_value = 0;
-
-            while loop_limit > 0 {
-                last_value = self.data.fetch_add(1, Ordering::AcqRel);
-                loop_limit -= 1;
-            }
+    fn lock(&self, input: Input) -> Output {
+        // it is very important to have black_box here
+        let mut loop_limit = black_box(input).data;
+
+        let mut last_value = 0;
 
-            return Data::Output {
-                hold_time: 0,
-                is_combiner: true,
-                data: last_value + 1,
-            };
+        while loop_limit > 0 {
+            last_value = self.data.fetch_add(1, Ordering::AcqRel);
+            loop_limit -= 1;
         }
 
-        panic!("Invalid input")
+        Output {
+            hold_time: 0,
+            is_combiner: true,
+            data: last_value + 1,
+        }
     }
 
     fn get_combine_time(&self) -> std::option::Option<u64> {
@@ -73,25 +70,58 @@ pub fn proportional_counter<'a>(
     bencher: &Bencher,
     file_name: &str,
     targets: impl Iterator<Item = &'a DLock2Target>,
+    config: &LockConfig,
     cs_loop: impl Iterator<Item = u64> + Clone,
     non_cs_loop: impl Iterator<Item = u64> + Clone,
     include_lock_free: bool,
     stat_hold_time: bool,
+    groups: GroupMapping,
 ) {
     for target in targets {
         let lock = target.to_locktype(
             0usize,
-            Data::default(),
             #[inline(never)]
-            move |data: &mut usize, input: Data| {
+            move |data: &mut usize, input: Input| {
                 let data = data;
-                let input = input;
+                let mut loop_limit = input.data;
 
-                if let Data::Input {
-                    thread_id,
-                    data: mut loop_limit,
-                } = input
-                {
+                let timestamp = unsafe {
+                    if stat_hold_time {
+                        __rdtscp(&mut 0)
+                    } else {
+                        0
+                    }
+                };
+
+                while loop_limit > 0 {
+                    *black_box(&mut *data) += 1;
+                    loop_limit -= 1;
+                }
+
+                let hold_time = if stat_hold_time {
+                    let end = unsafe { __rdtscp(&mut 0) };
+                    end - timestamp
+                } else {
+                    0
+                };
+
+                Output {
+                    hold_time,
+                    is_combiner: current().id() == input.thread_id,
+                    data: *data,
+                }
+            },
+            config,
+        );
+
+        // the increments of a whole pass are added at once
+        let lock = lock.map(|lock| {
+            if !config.batch {
+                return lock;
+            }
+
+            lock.with_batch_delegate(
+                move |data: &mut usize, requests: &mut [BatchRequest<Input, Output>]| {
                     let timestamp = unsafe {
                         if stat_hold_time {
                             __rdtscp(&mut 0)
@@ -100,28 +130,37 @@ pub fn proportional_counter<'a>(
                         }
                     };
 
-                    while loop_limit > 0 {
-                        *black_box(&mut *data) += 1;
-                        loop_limit -= 1;
-                    }
+                    let base = *data;
+                    let total = requests
+                        .iter()
+                        .filter_map(|request| request.input())
+                        .map(|input| input.data as usize)
+                        .sum::<usize>();
 
-                    let hold_time = if stat_hold_time {
+                    *black_box(&mut *data) += total;
+
+                    let share = if stat_hold_time {
                         let end = unsafe { __rdtscp(&mut 0) };
-                        end - timestamp
+                        (end - timestamp) / requests.len() as u64
                     } else {
                         0
                     };
 
-                    return Data::Output {
-                        hold_time,
-                        is_combiner: current().id() == thread_id,
-                        data: *data,
-                    };
-                }
+                    let mut prefix = base;
+                    for request in requests.iter_mut() {
+                        request.answer(|input| {
+                            prefix += input.data as usize;
 
-                panic!("Invalid input")
-            },
-        );
+                            Output {
+                                hold_time: share,
+                                is_combiner: current().id() == input.thread_id,
+                                data: prefix,
+                            }
+                        });
+                    }
+                },
+            )
+        });
 
         if let Some(lock) = lock {
             let lock = Arc::new(lock);
@@ -131,9 +170,19 @@ pub fn proportional_counter<'a>(
                 stat_hold_time,
                 cs_loop.clone(),
                 non_cs_loop.clone(),
+                groups,
                 lock.clone(),
             );
             finish_benchmark(&bencher.output_path, file_name, &lock.to_string(), records);
+
+            if let Some(controller) = lock.ban_controller() {
+                write_ban_controller_log(
+                    &bencher.output_path.join(lock.to_string()),
+                    file_name,
+                    &lock.to_string(),
+                    controller,
+                );
+            }
         }
     }
 
@@ -147,25 +196,49 @@ pub fn proportional_counter<'a>(
             stat_hold_time,
             cs_loop.clone(),
             non_cs_loop.clone(),
+            groups,
             Arc::new(lock),
         );
         finish_benchmark(&bencher.output_path, file_name, "Fetch&Add", records);
     }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
-pub enum Data {
-    #[default]
-    Nothing,
-    Input {
-        data: u64,
-        thread_id: ThreadId,
-    },
-    Output {
-        hold_time: u64,
-        is_combiner: bool,
-        data: usize,
-    },
+/// Thread-to-group mapping: thread `i` joins group `groups[i]` with weight
+/// `weights[groups[i]]`, both lists are cycled.
+#[derive(Debug, Clone, Copy)]
+pub struct GroupMapping<'a> {
+    pub groups: &'a [usize],
+    pub weights: &'a [u64],
+}
+
+impl GroupMapping<'_> {
+    fn of(&self, id: usize) -> Option<(usize, u64)> {
+        if self.groups.is_empty() {
+            return None;
+        }
+
+        let group = self.groups[id % self.groups.len()];
+        let weight = if self.weights.is_empty() {
+            1
+        } else {
+            self.weights[group % self.weights.len()]
+        };
+
+        Some((group, weight))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Input {
+    data: u64,
+    thread_id: ThreadId,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Output {
+    hold_time: u64,
+    is_combiner: bool,
+    data: usize,
 }
 
 fn start_benchmark<L>(
@@ -173,16 +246,17 @@ fn start_benchmark<L>(
     stat_hold_time: bool,
     cs_loop: impl Iterator<Item = u64> + Clone,
     non_cs_loop: impl Iterator<Item = u64> + Clone,
+    groups: GroupMapping,
     lock: Arc<L>,
 ) -> Vec<Records>
 where
-    L: DLock2<Data> + 'static + Display,
+    L: DLock2<Input, Output> + 'static + Display,
 {
     println!("Start benchmark for {}", lock);
 
     let stop_signal = Arc::new(AtomicBool::new(false));
 
-    let core_ids = core_affinity::get_core_ids().unwrap();
+    let core_ids = client_core_ids(lock.server_cpu());
     let core_ids = core_ids.iter().take(bencher.num_thread);
 
     // println!("{:?}", bencher);
@@ -197,9 +271,15 @@ where
                 let stop_signal = stop_signal.clone();
                 let stat_response_time = bencher.stat_response_time;
 
+                let group = groups.of(id);
+
                 scope.spawn(move || {
                     core_affinity::set_for_current(core_id);
 
+                    if let Some((group, weight)) = group {
+                        lock_ref.join_group(&format!("group {group}"), weight);
+                    }
+
                     let stop_signal = stop_signal;
                     let mut latencies = vec![];
                     let mut is_combiners: BitVec<usize, Lsb0> = BitVec::new();
@@ -207,7 +287,7 @@ where
                     let mut num_acquire = 0;
                     let mut aux = 0;
 
-                    let data = Data::Input {
+                    let data = Input {
                         data: cs_loop,
                         thread_id: current().id(),
                     };
@@ -225,23 +305,14 @@ where
 
                         num_acquire += 1;
 
-                        if let Data::Output {
-                            is_combiner,
-                            hold_time: current_hold_time,
-                            ..
-                        } = output
-                        {
-                            if stat_response_time {
-                                let end = unsafe { __rdtscp(&mut aux) };
-                                latencies.push(end - begin);
-                                is_combiners.push(is_combiner);
-                            }
+                        if stat_response_time {
+                            let end = unsafe { __rdtscp(&mut aux) };
+                            latencies.push(end - begin);
+                            is_combiners.push(output.is_combiner);
+                        }
 
-                            if stat_hold_time {
-                                hold_time += current_hold_time;
-                            }
-                        } else {
-                            unreachable!();
+                        if stat_hold_time {
+                            hold_time += output.hold_time;
                         }
 
                         loop_count += cs_loop;
@@ -278,6 +349,8 @@ where
                         waiter_latency,
                         hold_time,
                         combine_time: lock_ref.get_combine_time(),
+                        soft_ban_count: lock_ref.get_soft_ban_count(),
+                        group: group.map(|(group, _)| group),
                         locktype: format!("{}", lock_ref),
                         waiter_type: "".to_string(),
                         ..Records::from_bencher(bencher)
@@ -320,4 +393,94 @@ fn finish_benchmark<'a>(
     let total_loop_count: u64 = records.iter().map(|r| r.loop_count).sum();
 
     println!("Total loop count: {}", total_loop_count);
+
+    // Jain's fairness index of the per-thread loop counts, 1 when all are equal
+    let sum_of_squares: f64 = records.iter().map(|r| (r.loop_count as f64).powi(2)).sum();
+    if sum_of_squares > 0.0 {
+        println!(
+            "Fairness index: {:.4}",
+            (total_loop_count as f64).powi(2) / (records.len() as f64 * sum_of_squares)
+        );
+    }
+
+    let groups = records
+        .iter()
+        .filter_map(|r| r.group)
+        .collect::<BTreeSet<_>>();
+
+    for group in groups {
+        let loop_count: u64 = records
+            .iter()
+            .filter(|r| r.group == Some(group))
+            .map(|r| r.loop_count)
+            .sum();
+
+        println!(
+            "Group {}: {:.2}%",
+            group,
+            loop_count as f64 * 100.0 / total_loop_count.max(1) as f64
+        );
+    }
+}
+
+#[derive(Serialize)]
+struct BanControllerRecord<'a> {
+    locktype: &'a str,
+    window: u64,
+    tsc: u64,
+    active_threads: u64,
+    max_share: f64,
+    min_share: f64,
+    error: f64,
+    multiplier: f64,
+    config_window: u64,
+    gain: f64,
+    tolerance: f64,
+}
+
+/// Dump one row per controller window so the convergence of the penalty
+/// multiplier can be plotted next to the throughput records.
+fn write_ban_controller_log(
+    folder: &Path,
+    file_name: &str,
+    lock_name: &str,
+    controller: &AdaptiveBan,
+) {
+    let config = controller.config();
+    let history = controller.history();
+
+    let mut writer = Writer::from_writer(
+        create_plain_writer(folder.join(format!("{file_name} (ban controller).csv")))
+            .expect("Failed to create writer"),
+    );
+
+    for sample in history.iter() {
+        writer
+            .serialize(BanControllerRecord {
+                locktype: lock_name,
+                window: sample.window,
+                tsc: sample.tsc,
+                active_threads: sample.active_threads,
+                max_share: sample.max_share,
+                min_share: sample.min_share,
+                error: sample.error,
+                multiplier: sample.multiplier,
+                config_window: config.window,
+                gain: config.gain,
+                tolerance: config.tolerance,
+            })
+            .expect("Failed to write ban controller log");
+    }
+
+    writer.flush().expect("Failed to flush ban controller log");
+
+    if let Some(last) = history.last() {
+        println!(
+            "Ban controller: {} windows, {} active threads, multiplier {:.3}, error {:.3}",
+            last.window + 1,
+            last.active_threads,
+            last.multiplier,
+            last.error
+        );
+    }
 }
@@ -1,5 +1,6 @@
 use std::{
     arch::x86_64::__rdtscp,
+    fmt::Display,
     hint::black_box,
     path::Path,
     sync::{
@@ -11,51 +12,73 @@ use std::{
 };
 
 use arrow::record_batch;
+use libdlock::dlock2::{
+    batch::BatchRequest,
+    delegated::{Delegatable, Delegated},
+};
 use rand::Rng;
 
 use crate::{
-    benchmark::{bencher::Bencher, records::*},
-    lock_target::DLock2Target,
+    benchmark::{
+        bencher::{client_core_ids, Bencher},
+        records::*,
+    },
+    lock_target::{DLock2Target, LockConfig},
 };
 
 use self::extension::*;
 
 pub mod extension;
 
-pub fn lockfree_queue<'a>(bencher: &Bencher, queues: Vec<LockFreeQueue>) {
+pub fn lockfree_queue<'a>(bencher: &Bencher, queues: impl Iterator<Item = &'a LockFreeQueue>) {
     for queue in queues {
-        todo!()
+        match queue {
+            LockFreeQueue::MSQueue => run_lockfree_queue(bencher, MSQueue::<u64>::new()),
+            LockFreeQueue::SegQueue => run_lockfree_queue(bencher, CrossbeamSegQueue::<u64>::new()),
+        }
     }
 }
 
+fn run_lockfree_queue(bencher: &Bencher, queue: impl ConcurrentQueue<u64> + Display) {
+    let queue_name = format!("{}-queue", queue);
+    let records = start_benchmark(bencher, queue, &queue_name);
+    finish_benchmark(
+        &bencher.output_path,
+        &queue_name,
+        if bencher.stat_response_time {
+            "Queue (latency)"
+        } else {
+            "Queue"
+        },
+        records,
+    );
+}
+
 pub fn benchmark_queue<'a, Q: SequentialQueue<u64> + Send + Sync + 'static>(
     bencher: &Bencher,
     queue: impl Fn() -> Q,
     targets: impl Iterator<Item = &'a DLock2Target>,
+    config: &LockConfig,
 ) {
     for target in targets {
-        let lock = target.to_locktype(
-            queue(),
-            QueueData::default(),
-            move |queue: &mut Q, input: QueueData<u64>| match input {
-                QueueData::Push { data } => {
-                    queue.push(data);
-                    QueueData::Nothing
-                }
-                QueueData::Pop => {
-                    let output = queue.pop();
-                    match output {
-                        Some(data) => QueueData::OutputT { data },
-                        None => QueueData::OutputEmpty,
-                    }
-                }
-                _ => panic!("Invalid input"),
-            },
-        );
+        let lock = target.to_locktype(LockedQueue(queue()), LockedQueue::delegate, config);
+
+        let lock = lock.map(|lock| {
+            if config.batch {
+                lock.with_batch_delegate(eliminate::<Q>)
+            } else {
+                lock
+            }
+        });
 
         if let Some(lock) = lock {
-            let queue_name = format!("{}-queue", lock);
-            let records = start_benchmark(bencher, lock, &queue_name);
+            let queue_name = if lock.is_batched() {
+                format!("{}-batch-queue", lock)
+            } else {
+                format!("{}-queue", lock)
+            };
+            let queue = Delegated::<LockedQueue<Q>, _>::new(lock);
+            let records = start_benchmark(bencher, queue, &queue_name);
             finish_benchmark(
                 &bencher.output_path,
                 &queue_name,
@@ -70,6 +93,41 @@ pub fn benchmark_queue<'a, Q: SequentialQueue<u64> + Send + Sync + 'static>(
     }
 }
 
+/// Batch delegate that hands the values pushed in a pass straight to the
+/// pops that come after them once the queue is empty, those pushes and pops
+/// never touch the queue. The pushes left over stay unanswered, so the lock
+/// applies them at the end, behind everything already in the queue, which
+/// keeps the queue FIFO.
+fn eliminate<Q: SequentialQueue<u64>>(
+    queue: &mut LockedQueue<Q>,
+    requests: &mut [BatchRequest<LockedQueueOp, LockedQueueOutput>],
+) {
+    // the pushes of the batch before it were handed to a pop
+    let mut next_push = 0;
+
+    for i in 0..requests.len() {
+        if !matches!(requests[i].input(), Some(LockedQueueOp::Pop)) {
+            continue;
+        }
+
+        let mut output = queue.pop();
+
+        while output.is_none() && next_push < i {
+            if let Some(LockedQueueOp::Push { .. }) = requests[next_push].input() {
+                requests[next_push].answer(|input| {
+                    if let LockedQueueOp::Push { value } = input {
+                        output = Some(value);
+                    }
+                    LockedQueueOutput::Push(())
+                });
+            }
+            next_push += 1;
+        }
+
+        requests[i].answer(|_| LockedQueueOutput::Pop(output));
+    }
+}
+
 fn start_benchmark<T>(
     bencher: &Bencher,
     concurrent_queue: impl ConcurrentQueue<T>,
@@ -83,7 +141,7 @@ where
     let stop_signal = Arc::new(AtomicBool::new(false));
     let lock_ref = Arc::new(concurrent_queue);
 
-    let core_ids = core_affinity::get_core_ids().unwrap();
+    let core_ids = client_core_ids(lock_ref.server_cpu());
     let core_ids = core_ids.iter().take(bencher.num_thread);
 
     // println!("{:?}", bencher);
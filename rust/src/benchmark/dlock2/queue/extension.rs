@@ -1,29 +1,25 @@
 use std::collections::LinkedList;
-
 use std::collections::VecDeque;
+use std::fmt::Display;
+use std::mem::MaybeUninit;
+use std::sync::atomic::Ordering::*;
 
 use clap::ValueEnum;
-use libdlock::dlock2::DLock2;
-use strum::Display;
+use crossbeam::epoch::{self, Atomic, Owned, Shared};
+use crossbeam::queue::SegQueue;
+use crossbeam::utils::CachePadded;
+use libdlock::dlock2::{
+    delegated::{delegated, Delegated},
+    DLock2,
+};
 use strum::EnumIter;
 
-#[derive(Debug, Clone, ValueEnum, EnumIter, Display)]
+#[derive(Debug, Clone, Copy, ValueEnum, EnumIter, strum::Display)]
 pub enum LockFreeQueue {
-    MCSQueue,
-}
-
-#[derive(Debug, Clone, Copy, Default)]
-pub enum QueueData<T: Send> {
-    #[default]
-    Nothing,
-    Push {
-        data: T,
-    },
-    Pop,
-    OutputT {
-        data: T,
-    },
-    OutputEmpty,
+    /// Michael-Scott queue
+    MSQueue,
+    /// crossbeam's SegQueue
+    SegQueue,
 }
 
 pub unsafe trait ConcurrentQueue<T>: Send + Sync
@@ -32,6 +28,11 @@ where
 {
     fn push(&self, value: T);
     fn pop(&self) -> Option<T>;
+
+    /// CPU taken by a dedicated server thread, see `DLock2::server_cpu`
+    fn server_cpu(&self) -> Option<usize> {
+        None
+    }
 }
 
 pub trait SequentialQueue<T> {
@@ -39,21 +40,35 @@ pub trait SequentialQueue<T> {
     fn pop(&mut self) -> Option<T>;
 }
 
-unsafe impl<T, L> ConcurrentQueue<T> for L
+/// A sequential queue whose operations are delegated to a lock
+pub struct LockedQueue<Q>(pub Q);
+
+#[delegated]
+impl<Q: SequentialQueue<u64>> LockedQueue<Q> {
+    pub fn push(&mut self, value: u64) {
+        self.0.push(value);
+    }
+
+    pub fn pop(&mut self) -> Option<u64> {
+        self.0.pop()
+    }
+}
+
+unsafe impl<Q, L> ConcurrentQueue<u64> for Delegated<LockedQueue<Q>, L>
 where
-    T: Send,
-    L: DLock2<QueueData<T>>,
+    Q: SequentialQueue<u64>,
+    L: DLock2<LockedQueueOp, LockedQueueOutput>,
 {
-    fn push(&self, value: T) {
-        self.lock(QueueData::Push { data: value });
+    fn push(&self, value: u64) {
+        DelegatedLockedQueue::push(self, value);
     }
 
-    fn pop(&self) -> Option<T> {
-        match self.lock(QueueData::Pop) {
-            QueueData::OutputT { data } => Some(data),
-            QueueData::OutputEmpty => None,
-            _ => panic!("Invalid output"),
-        }
+    fn pop(&self) -> Option<u64> {
+        DelegatedLockedQueue::pop(self)
+    }
+
+    fn server_cpu(&self) -> Option<usize> {
+        self.inner().server_cpu()
     }
 }
 
@@ -76,3 +91,160 @@ impl<T: Send> SequentialQueue<T> for LinkedList<T> {
         self.pop_front()
     }
 }
+
+struct MSNode<T> {
+    data: MaybeUninit<T>,
+    next: Atomic<MSNode<T>>,
+}
+
+/// Michael-Scott lock-free queue, with nodes reclaimed through crossbeam's epoch.
+pub struct MSQueue<T> {
+    head: CachePadded<Atomic<MSNode<T>>>,
+    tail: CachePadded<Atomic<MSNode<T>>>,
+}
+
+unsafe impl<T: Send> Send for MSQueue<T> {}
+unsafe impl<T: Send> Sync for MSQueue<T> {}
+
+impl<T> MSQueue<T> {
+    pub fn new() -> Self {
+        let queue = Self {
+            head: Atomic::null().into(),
+            tail: Atomic::null().into(),
+        };
+
+        let sentinel = Owned::new(MSNode {
+            data: MaybeUninit::uninit(),
+            next: Atomic::null(),
+        });
+
+        unsafe {
+            let sentinel = sentinel.into_shared(epoch::unprotected());
+            queue.head.store(sentinel, Relaxed);
+            queue.tail.store(sentinel, Relaxed);
+        }
+
+        queue
+    }
+}
+
+impl<T> Drop for MSQueue<T> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+
+            let mut current = self.head.load(Relaxed, guard);
+            let mut is_sentinel = true;
+
+            while let Some(node) = current.as_ref() {
+                let next = node.next.load(Relaxed, guard);
+                let mut owned = current.into_owned();
+
+                if !is_sentinel {
+                    owned.data.assume_init_drop();
+                }
+
+                is_sentinel = false;
+                current = next;
+            }
+        }
+    }
+}
+
+impl<T> Display for MSQueue<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LockFree (MSQueue)")
+    }
+}
+
+unsafe impl<T: Send> ConcurrentQueue<T> for MSQueue<T> {
+    fn push(&self, value: T) {
+        let guard = &epoch::pin();
+
+        let node = Owned::new(MSNode {
+            data: MaybeUninit::new(value),
+            next: Atomic::null(),
+        })
+        .into_shared(guard);
+
+        loop {
+            let tail = self.tail.load(Acquire, guard);
+            let tail_ref = unsafe { tail.deref() };
+            let next = tail_ref.next.load(Acquire, guard);
+
+            // tail is lagging behind, help to swing it forward
+            if !next.is_null() {
+                let _ = self
+                    .tail
+                    .compare_exchange(tail, next, Release, Relaxed, guard);
+                continue;
+            }
+
+            if tail_ref
+                .next
+                .compare_exchange(Shared::null(), node, Release, Relaxed, guard)
+                .is_ok()
+            {
+                let _ = self
+                    .tail
+                    .compare_exchange(tail, node, Release, Relaxed, guard);
+                return;
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let guard = &epoch::pin();
+
+        loop {
+            let head = self.head.load(Acquire, guard);
+            let next = unsafe { head.deref() }.next.load(Acquire, guard);
+            let next_ref = unsafe { next.as_ref() }?;
+
+            if self
+                .head
+                .compare_exchange(head, next, Release, Relaxed, guard)
+                .is_ok()
+            {
+                // make sure tail never points to a retired node
+                let tail = self.tail.load(Acquire, guard);
+                if tail == head {
+                    let _ = self
+                        .tail
+                        .compare_exchange(tail, next, Release, Relaxed, guard);
+                }
+
+                unsafe {
+                    guard.defer_destroy(head);
+                    // `next` becomes the new sentinel, so its data is moved out exactly once
+                    return Some(next_ref.data.assume_init_read());
+                }
+            }
+        }
+    }
+}
+
+/// crossbeam's SegQueue
+pub struct CrossbeamSegQueue<T>(SegQueue<T>);
+
+impl<T> CrossbeamSegQueue<T> {
+    pub fn new() -> Self {
+        Self(SegQueue::new())
+    }
+}
+
+impl<T> Display for CrossbeamSegQueue<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LockFree (SegQueue)")
+    }
+}
+
+unsafe impl<T: Send> ConcurrentQueue<T> for CrossbeamSegQueue<T> {
+    fn push(&self, value: T) {
+        self.0.push(value);
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.0.pop()
+    }
+}
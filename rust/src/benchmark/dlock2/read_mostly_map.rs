@@ -0,0 +1,187 @@
+use std::{
+    collections::HashMap,
+    hint::black_box,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use libdlock::dlock2::{DLock2, DLock2Impl};
+use rand::Rng;
+
+use crate::{
+    benchmark::{
+        bencher::{client_core_ids, Bencher},
+        records::{write_results, Records},
+    },
+    lock_target::{DLock2Target, LockConfig},
+};
+
+#[derive(Debug, Clone, Copy)]
+enum MapOp {
+    Get(u64),
+    Put(u64, u64),
+}
+
+/// The value of the key, the previous one for a `Put`
+fn delegate(map: &mut HashMap<u64, u64>, op: MapOp) -> Option<u64> {
+    match op {
+        MapOp::Get(key) => map.get(&key).copied(),
+        MapOp::Put(key, value) => map.insert(key, value),
+    }
+}
+
+/// Lookups and updates on a hash map with every key present from the
+/// start. Updates only overwrite values and never resize the map, which
+/// keeps lookups sound while they overlap with a combining pass. Every run
+/// is done once with lookups delegated and once with optimistic lookups.
+pub fn read_mostly_map<'a>(
+    bencher: &Bencher,
+    file_name: &str,
+    targets: impl Iterator<Item = &'a DLock2Target>,
+    config: &LockConfig,
+    read_ratios: &[f64],
+    keys: u64,
+) {
+    for target in targets {
+        for &read_ratio in read_ratios {
+            for optimistic in [false, true] {
+                let map = (0..keys).map(|key| (key, key)).collect::<HashMap<_, _>>();
+
+                let lock = target.to_locktype(map, delegate, config);
+
+                if let Some(lock) = lock {
+                    start_benchmark(
+                        bencher,
+                        file_name,
+                        read_ratio,
+                        keys,
+                        optimistic,
+                        Arc::new(lock),
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn start_benchmark<F>(
+    bencher: &Bencher,
+    file_name: &str,
+    read_ratio: f64,
+    keys: u64,
+    optimistic: bool,
+    lock: Arc<DLock2Impl<HashMap<u64, u64>, MapOp, Option<u64>, F>>,
+) where
+    F: Fn(&mut HashMap<u64, u64>, MapOp) -> Option<u64> + Send + Sync + 'static,
+{
+    let lock_name = lock.to_string();
+    println!(
+        "Start benchmark for {} (read ratio {}, {} reads)",
+        lock_name,
+        read_ratio,
+        if optimistic {
+            "optimistic"
+        } else {
+            "delegated"
+        }
+    );
+
+    let stop_signal = Arc::new(AtomicBool::new(false));
+
+    let core_ids = client_core_ids(lock.server_cpu());
+    let core_ids = core_ids.iter().take(bencher.num_thread);
+
+    let records = thread::scope(|scope| {
+        let handles = core_ids
+            .cycle()
+            .take(bencher.num_thread)
+            .enumerate()
+            .map(|(id, core_id)| {
+                let lock_ref = lock.clone();
+                let core_id = *core_id;
+                let stop_signal = stop_signal.clone();
+
+                scope.spawn(move || {
+                    core_affinity::set_for_current(core_id);
+
+                    let rng = &mut rand::thread_rng();
+
+                    let mut loop_count = 0;
+                    let mut num_acquire = 0;
+                    let mut fallbacks = 0;
+
+                    while !stop_signal.load(Ordering::Acquire) {
+                        let key = rng.gen_range(0..keys);
+
+                        if !rng.gen_bool(read_ratio) {
+                            lock_ref.lock(MapOp::Put(key, loop_count));
+                            num_acquire += 1;
+                        } else {
+                            // the map never resizes, a torn lookup only
+                            // reads a stale value
+                            let value = optimistic
+                                .then(|| unsafe { lock_ref.try_read(|map| map.get(&key).copied()) })
+                                .flatten();
+
+                            let value = value.unwrap_or_else(|| {
+                                num_acquire += 1;
+                                fallbacks += optimistic as u64;
+
+                                lock_ref.lock(MapOp::Get(key))
+                            });
+
+                            black_box(value);
+                        }
+
+                        loop_count += 1;
+                    }
+
+                    Records {
+                        id,
+                        cpu_id: core_id.id,
+                        loop_count,
+                        num_acquire,
+                        combine_time: lock_ref.get_combine_time(),
+                        read_ratio: Some(read_ratio),
+                        read_fallback_count: optimistic.then_some(fallbacks),
+                        locktype: lock_ref.to_string(),
+                        ..Records::from_bencher(bencher)
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        thread::sleep(Duration::from_secs(bencher.duration));
+
+        stop_signal.store(true, Ordering::Release);
+
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    println!(
+        "Total operations: {}, delegated: {}",
+        records.iter().map(|record| record.loop_count).sum::<u64>(),
+        records.iter().map(|record| record.num_acquire).sum::<u64>()
+    );
+
+    write_results(
+        &bencher.output_path.join(&lock_name),
+        &format!(
+            "{} {}",
+            file_name,
+            if optimistic {
+                "optimistic"
+            } else {
+                "delegated"
+            }
+        ),
+        &records,
+    );
+}
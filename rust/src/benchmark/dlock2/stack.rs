@@ -0,0 +1,199 @@
+use std::{
+    arch::x86_64::__rdtscp,
+    fmt::Display,
+    hint::black_box,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self},
+    time::Duration,
+};
+
+use rand::Rng;
+
+use crate::{
+    benchmark::{
+        bencher::{client_core_ids, Bencher},
+        records::*,
+    },
+    lock_target::{DLock2Target, LockConfig},
+};
+
+use self::extension::*;
+
+pub mod extension;
+
+pub fn benchmark_stack<'a>(
+    bencher: &Bencher,
+    targets: impl Iterator<Item = &'a DLock2Target> + Clone,
+    config: &LockConfig,
+    push_ratios: impl Iterator<Item = f64>,
+    lock_free_stacks: impl Iterator<Item = &'a LockFreeStack> + Clone,
+) {
+    for push_ratio in push_ratios {
+        for target in targets.clone() {
+            let lock = target.to_locktype(
+                Vec::new(),
+                move |stack: &mut Vec<u64>, input: StackData<u64>| match input {
+                    StackData::Push { data } => {
+                        SequentialStack::push(stack, data);
+                        None
+                    }
+                    StackData::Pop => SequentialStack::pop(stack),
+                },
+                config,
+            );
+
+            if let Some(lock) = lock {
+                run_stack(bencher, lock, push_ratio);
+            }
+        }
+
+        for stack in lock_free_stacks.clone() {
+            match stack {
+                LockFreeStack::TreiberStack => {
+                    run_stack(bencher, TreiberStack::<u64>::new(), push_ratio)
+                }
+                LockFreeStack::EliminationStack => {
+                    run_stack(bencher, EliminationStack::<u64>::new(), push_ratio)
+                }
+            }
+        }
+    }
+}
+
+fn run_stack(bencher: &Bencher, stack: impl ConcurrentStack<u64> + Display, push_ratio: f64) {
+    let stack_name = format!("{}-stack", stack);
+    let records = start_benchmark(bencher, stack, &stack_name, push_ratio);
+    finish_benchmark(
+        &bencher.output_path,
+        &stack_name,
+        &if bencher.stat_response_time {
+            format!("Stack push {} (latency)", push_ratio)
+        } else {
+            format!("Stack push {}", push_ratio)
+        },
+        records,
+    );
+}
+
+fn start_benchmark<T>(
+    bencher: &Bencher,
+    concurrent_stack: impl ConcurrentStack<T>,
+    stack_name: &str,
+    push_ratio: f64,
+) -> Vec<Records>
+where
+    T: Send + Default,
+{
+    println!("Start benchmark for {} (push ratio {})", stack_name, push_ratio);
+
+    let stop_signal = Arc::new(AtomicBool::new(false));
+    let lock_ref = Arc::new(concurrent_stack);
+
+    let core_ids = client_core_ids(lock_ref.server_cpu());
+    let core_ids = core_ids.iter().take(bencher.num_thread);
+
+    thread::scope(move |scope| {
+        let handles = core_ids
+            .cycle()
+            .take(bencher.num_thread)
+            .enumerate()
+            .map(|(id, core_id)| {
+                let lock_ref = lock_ref.clone();
+                let core_id = *core_id;
+                let stop_signal = stop_signal.clone();
+                let stat_response_time = bencher.stat_response_time;
+
+                scope.spawn(move || {
+                    core_affinity::set_for_current(core_id);
+
+                    let stop_signal = black_box(stop_signal);
+                    let mut response_times = vec![];
+                    let mut loop_count = 0;
+                    let mut num_acquire = 0;
+                    let mut aux = 0;
+
+                    let rng = &mut rand::thread_rng();
+
+                    while !stop_signal.load(Ordering::Acquire) {
+                        let begin = if stat_response_time {
+                            unsafe { __rdtscp(&mut aux) }
+                        } else {
+                            0
+                        };
+
+                        if rng.gen_bool(push_ratio) {
+                            lock_ref.push(T::default());
+                        } else {
+                            lock_ref.pop();
+                        }
+
+                        num_acquire += 1;
+
+                        if stat_response_time {
+                            let end = unsafe { __rdtscp(&mut aux) };
+                            response_times.push(end - begin);
+                        }
+
+                        let non_cs_loop = rng.gen_range(1..=64);
+
+                        for i in 0..non_cs_loop {
+                            black_box(i);
+                        }
+
+                        loop_count += 1;
+                    }
+
+                    Records {
+                        id,
+                        cpu_id: core_id.id,
+                        loop_count,
+                        num_acquire,
+                        non_cs_length: Some(0),
+                        waiter_latency: response_times,
+                        locktype: stack_name.to_owned(),
+                        waiter_type: "".to_string(),
+                        ..Records::from_bencher(bencher)
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        thread::sleep(Duration::from_secs(bencher.duration));
+
+        stop_signal.store(true, Ordering::Release);
+
+        handles
+            .into_iter()
+            .map(move |h| h.join().unwrap())
+            .collect()
+    })
+}
+
+fn finish_benchmark(
+    output_path: &Path,
+    stack_name: &str,
+    file_name: &str,
+    records: impl AsRef<Vec<Records>>,
+) {
+    let folder = output_path.join(stack_name);
+
+    if !folder.exists() {
+        std::fs::create_dir_all(&folder).unwrap();
+    }
+
+    let records = records.as_ref();
+
+    write_results(&folder, file_name, records);
+
+    for record in records.iter() {
+        println!("{}", record.loop_count);
+    }
+
+    let total_loop_count: u64 = records.iter().map(|r| r.loop_count).sum();
+
+    println!("Total loop count: {}", total_loop_count);
+}
@@ -0,0 +1,289 @@
+use std::fmt::Display;
+use std::hint::spin_loop;
+use std::mem::MaybeUninit;
+use std::sync::atomic::Ordering::*;
+
+use clap::ValueEnum;
+use crossbeam::epoch::{self, Atomic, Guard, Owned, Shared};
+use crossbeam::utils::CachePadded;
+use libdlock::dlock2::DLock2;
+use rand::Rng;
+use strum::EnumIter;
+
+#[derive(Debug, Clone, Copy, ValueEnum, EnumIter, strum::Display)]
+pub enum LockFreeStack {
+    /// Treiber stack
+    TreiberStack,
+    /// Treiber stack with an elimination-backoff array
+    EliminationStack,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum StackData<T: Send> {
+    Push { data: T },
+    Pop,
+}
+
+pub unsafe trait ConcurrentStack<T>: Send + Sync
+where
+    T: Send,
+{
+    fn push(&self, value: T);
+    fn pop(&self) -> Option<T>;
+
+    /// CPU taken by a dedicated server thread, see `DLock2::server_cpu`
+    fn server_cpu(&self) -> Option<usize> {
+        None
+    }
+}
+
+pub trait SequentialStack<T> {
+    fn push(&mut self, value: T);
+    fn pop(&mut self) -> Option<T>;
+}
+
+unsafe impl<T, L> ConcurrentStack<T> for L
+where
+    T: Send,
+    L: DLock2<StackData<T>, Option<T>>,
+{
+    fn push(&self, value: T) {
+        self.lock(StackData::Push { data: value });
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.lock(StackData::Pop)
+    }
+
+    fn server_cpu(&self) -> Option<usize> {
+        DLock2::server_cpu(self)
+    }
+}
+
+impl<T: Send> SequentialStack<T> for Vec<T> {
+    fn push(&mut self, value: T) {
+        Vec::push(self, value);
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        Vec::pop(self)
+    }
+}
+
+struct StackNode<T> {
+    data: MaybeUninit<T>,
+    next: Atomic<StackNode<T>>,
+}
+
+impl<T> StackNode<T> {
+    fn new(value: T) -> Owned<Self> {
+        Owned::new(StackNode {
+            data: MaybeUninit::new(value),
+            next: Atomic::null(),
+        })
+    }
+}
+
+/// Treiber lock-free stack, with nodes reclaimed through crossbeam's epoch.
+pub struct TreiberStack<T> {
+    head: CachePadded<Atomic<StackNode<T>>>,
+}
+
+unsafe impl<T: Send> Send for TreiberStack<T> {}
+unsafe impl<T: Send> Sync for TreiberStack<T> {}
+
+impl<T> TreiberStack<T> {
+    pub fn new() -> Self {
+        Self {
+            head: Atomic::null().into(),
+        }
+    }
+
+    /// Try to push `node` with a single CAS.
+    fn try_push<'g>(&self, node: Shared<'g, StackNode<T>>, guard: &'g Guard) -> bool {
+        let head = self.head.load(Acquire, guard);
+
+        unsafe { node.deref() }.next.store(head, Relaxed);
+
+        self.head
+            .compare_exchange(head, node, Release, Relaxed, guard)
+            .is_ok()
+    }
+
+    /// Try to pop with a single CAS. `Err` means the CAS lost a race.
+    fn try_pop(&self, guard: &Guard) -> Result<Option<T>, ()> {
+        let head = self.head.load(Acquire, guard);
+
+        let Some(head_ref) = (unsafe { head.as_ref() }) else {
+            return Ok(None);
+        };
+
+        let next = head_ref.next.load(Relaxed, guard);
+
+        match self
+            .head
+            .compare_exchange(head, next, Release, Relaxed, guard)
+        {
+            Ok(_) => unsafe {
+                let data = head_ref.data.assume_init_read();
+                guard.defer_destroy(head);
+                Ok(Some(data))
+            },
+            Err(_) => Err(()),
+        }
+    }
+}
+
+impl<T> Drop for TreiberStack<T> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+
+            let mut current = self.head.load(Relaxed, guard);
+
+            while let Some(node) = current.as_ref() {
+                let next = node.next.load(Relaxed, guard);
+                current.into_owned().data.assume_init_drop();
+                current = next;
+            }
+        }
+    }
+}
+
+impl<T> Display for TreiberStack<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LockFree (Treiber)")
+    }
+}
+
+unsafe impl<T: Send> ConcurrentStack<T> for TreiberStack<T> {
+    fn push(&self, value: T) {
+        let guard = &epoch::pin();
+
+        let node = StackNode::new(value).into_shared(guard);
+
+        while !self.try_push(node, guard) {
+            spin_loop();
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let guard = &epoch::pin();
+
+        loop {
+            if let Ok(result) = self.try_pop(guard) {
+                return result;
+            }
+
+            spin_loop();
+        }
+    }
+}
+
+const ELIMINATION_SLOTS: usize = 16;
+const ELIMINATION_SPIN: usize = 128;
+
+/// Treiber stack that backs off into an elimination array on contention,
+/// where a push and a pop can cancel each other out without touching the stack.
+pub struct EliminationStack<T> {
+    stack: TreiberStack<T>,
+    slots: Box<[CachePadded<Atomic<StackNode<T>>>]>,
+}
+
+unsafe impl<T: Send> Send for EliminationStack<T> {}
+unsafe impl<T: Send> Sync for EliminationStack<T> {}
+
+impl<T> EliminationStack<T> {
+    pub fn new() -> Self {
+        Self {
+            stack: TreiberStack::new(),
+            slots: (0..ELIMINATION_SLOTS)
+                .map(|_| Atomic::null().into())
+                .collect(),
+        }
+    }
+
+    fn random_slot(&self) -> &Atomic<StackNode<T>> {
+        &self.slots[rand::thread_rng().gen_range(0..self.slots.len())]
+    }
+
+    /// Offer `node` to a concurrent pop. Returns whether it was taken.
+    ///
+    /// The caller stays pinned for the whole offer, so the node cannot be
+    /// freed and reused by the taker while it is still published.
+    fn offer<'g>(&self, node: Shared<'g, StackNode<T>>, guard: &'g Guard) -> bool {
+        let slot = self.random_slot();
+
+        if slot
+            .compare_exchange(Shared::null(), node, AcqRel, Relaxed, guard)
+            .is_err()
+        {
+            return false;
+        }
+
+        for _ in 0..ELIMINATION_SPIN {
+            if slot.load(Acquire, guard) != node {
+                return true;
+            }
+            spin_loop();
+        }
+
+        // withdraw the offer; failing means a pop took it in the meantime
+        slot.compare_exchange(node, Shared::null(), AcqRel, Acquire, guard)
+            .is_err()
+    }
+
+    /// Try to take a node offered by a concurrent push.
+    fn take(&self, guard: &Guard) -> Option<T> {
+        let slot = self.random_slot();
+
+        let node = slot.load(Acquire, guard);
+
+        if node.is_null() {
+            return None;
+        }
+
+        match slot.compare_exchange(node, Shared::null(), AcqRel, Relaxed, guard) {
+            Ok(_) => unsafe {
+                let data = node.deref().data.assume_init_read();
+                guard.defer_destroy(node);
+                Some(data)
+            },
+            Err(_) => None,
+        }
+    }
+}
+
+impl<T> Display for EliminationStack<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LockFree (Elimination)")
+    }
+}
+
+unsafe impl<T: Send> ConcurrentStack<T> for EliminationStack<T> {
+    fn push(&self, value: T) {
+        let guard = &epoch::pin();
+
+        let node = StackNode::new(value).into_shared(guard);
+
+        loop {
+            if self.stack.try_push(node, guard) || self.offer(node, guard) {
+                return;
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let guard = &epoch::pin();
+
+        loop {
+            if let Ok(result) = self.stack.try_pop(guard) {
+                return result;
+            }
+
+            if let Some(data) = self.take(guard) {
+                return Some(data);
+            }
+        }
+    }
+}
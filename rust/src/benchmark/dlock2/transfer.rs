@@ -0,0 +1,226 @@
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use libdlock::dlock2::{DLock2, DLock2Impl};
+use rand::Rng;
+
+use crate::{
+    benchmark::{
+        bencher::{client_core_ids, Bencher},
+        records::{write_results, Records},
+    },
+    lock_target::{DLock2Target, LockConfig},
+};
+
+type Bank = DLock2Impl<Vec<i64>, Op, i64, fn(&mut Vec<i64>, Op) -> i64>;
+
+#[derive(Clone)]
+enum Op {
+    Move {
+        from: usize,
+        to: usize,
+        amount: i64,
+    },
+    Deposit {
+        account: usize,
+        amount: i64,
+    },
+    /// Withdraw `amount` from `account` and deposit it to `remote_account`
+    /// of `remote` from inside the delegate
+    Transfer {
+        account: usize,
+        amount: i64,
+        remote: Arc<Bank>,
+        remote_account: usize,
+    },
+    Total,
+}
+
+impl Debug for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Op::Move { from, to, amount } => write!(f, "Move({} -> {}, {})", from, to, amount),
+            Op::Deposit { account, amount } => write!(f, "Deposit({}, {})", account, amount),
+            Op::Transfer {
+                account,
+                amount,
+                remote_account,
+                ..
+            } => write!(
+                f,
+                "Transfer({} -> remote {}, {})",
+                account, remote_account, amount
+            ),
+            Op::Total => write!(f, "Total"),
+        }
+    }
+}
+
+/// The sum of all accounts for `Total`, otherwise the new balance of the
+/// account the money was taken from or added to
+fn delegate(accounts: &mut Vec<i64>, op: Op) -> i64 {
+    match op {
+        Op::Move { from, to, amount } => {
+            accounts[from] -= amount;
+            accounts[to] += amount;
+            accounts[from]
+        }
+        Op::Deposit { account, amount } => {
+            accounts[account] += amount;
+            accounts[account]
+        }
+        Op::Transfer {
+            account,
+            amount,
+            remote,
+            remote_account,
+        } => {
+            accounts[account] -= amount;
+            remote.lock(Op::Deposit {
+                account: remote_account,
+                amount,
+            });
+            accounts[account]
+        }
+        Op::Total => accounts.iter().sum(),
+    }
+}
+
+/// Transfers between accounts spread over `banks` locks. A transfer between
+/// two banks is delegated to the bank with the lower index, whose delegate
+/// delegates the deposit to the other bank. Nesting always in that order
+/// keeps the lock order acyclic.
+pub fn transfer<'a>(
+    bencher: &Bencher,
+    file_name: &str,
+    targets: impl Iterator<Item = &'a DLock2Target>,
+    config: &LockConfig,
+    banks: usize,
+    accounts: usize,
+) {
+    for target in targets {
+        let locks = (0..banks)
+            .map(|_| {
+                target.to_locktype(
+                    vec![0i64; accounts],
+                    delegate as fn(&mut Vec<i64>, Op) -> i64,
+                    config,
+                )
+            })
+            .collect::<Option<Vec<_>>>();
+
+        if let Some(locks) = locks {
+            start_benchmark(
+                bencher,
+                file_name,
+                accounts,
+                locks.into_iter().map(Arc::new).collect(),
+            );
+        }
+    }
+}
+
+fn start_benchmark(bencher: &Bencher, file_name: &str, accounts: usize, banks: Vec<Arc<Bank>>) {
+    let lock_name = banks[0].to_string();
+    println!(
+        "Start benchmark for {} (transfer, {} banks)",
+        lock_name,
+        banks.len()
+    );
+
+    let stop_signal = Arc::new(AtomicBool::new(false));
+
+    let core_ids = client_core_ids(banks[0].server_cpu());
+    let core_ids = core_ids.iter().take(bencher.num_thread);
+
+    let records = thread::scope(|scope| {
+        let handles = core_ids
+            .cycle()
+            .take(bencher.num_thread)
+            .enumerate()
+            .map(|(id, core_id)| {
+                let banks = &banks;
+                let core_id = *core_id;
+                let stop_signal = stop_signal.clone();
+
+                scope.spawn(move || {
+                    core_affinity::set_for_current(core_id);
+
+                    let rng = &mut rand::thread_rng();
+
+                    let mut loop_count = 0;
+                    let mut num_acquire = 0;
+
+                    while !stop_signal.load(Ordering::Acquire) {
+                        let (from_bank, from) =
+                            (rng.gen_range(0..banks.len()), rng.gen_range(0..accounts));
+                        let (to_bank, to) =
+                            (rng.gen_range(0..banks.len()), rng.gen_range(0..accounts));
+                        let amount = rng.gen_range(1..=100);
+
+                        if from_bank == to_bank {
+                            banks[from_bank].lock(Op::Move { from, to, amount });
+                        } else {
+                            // the lower bank nests, a negative amount moves
+                            // the money towards it
+                            let (outer, account, inner, remote_account, amount) =
+                                if from_bank < to_bank {
+                                    (from_bank, from, to_bank, to, amount)
+                                } else {
+                                    (to_bank, to, from_bank, from, -amount)
+                                };
+
+                            banks[outer].lock(Op::Transfer {
+                                account,
+                                amount,
+                                remote: banks[inner].clone(),
+                                remote_account,
+                            });
+                            num_acquire += 1;
+                        }
+
+                        loop_count += 1;
+                    }
+
+                    Records {
+                        id,
+                        cpu_id: core_id.id,
+                        loop_count,
+                        num_acquire,
+                        combine_time: banks[0].get_combine_time(),
+                        locktype: banks[0].to_string(),
+                        ..Records::from_bencher(bencher)
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        thread::sleep(Duration::from_secs(bencher.duration));
+
+        stop_signal.store(true, Ordering::Release);
+
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    // money only moves between accounts
+    let total = banks.iter().map(|bank| bank.lock(Op::Total)).sum::<i64>();
+    assert_eq!(total, 0, "transfers lost money");
+
+    println!(
+        "Total transfers: {}, across banks: {}",
+        records.iter().map(|record| record.loop_count).sum::<u64>(),
+        records.iter().map(|record| record.num_acquire).sum::<u64>()
+    );
+
+    write_results(&bencher.output_path.join(&lock_name), file_name, &records);
+}
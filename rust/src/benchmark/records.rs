@@ -31,6 +31,18 @@ pub struct Records {
     pub waiter_latency: Vec<u64>,
     pub hold_time: u64,
     pub combine_time: Option<u64>,
+    pub soft_ban_count: Option<u64>,
+    /// Per-request latency target (tsc) of the thread and how many requests
+    /// completed after it
+    pub slo: Option<u64>,
+    pub deadline_miss_count: Option<u64>,
+    /// Share of read-only requests of the thread and how many of its
+    /// optimistic reads had to be delegated
+    pub read_ratio: Option<f64>,
+    pub read_fallback_count: Option<u64>,
+    /// Producer or consumer, in experiments that have both
+    pub role: Option<String>,
+    pub group: Option<usize>,
     pub locktype: String,
     pub waiter_type: String,
 }
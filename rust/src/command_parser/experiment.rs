@@ -1,10 +1,14 @@
 use std::{num::ParseIntError, sync::OnceLock, time::Duration};
 
 use clap::{Args, Subcommand, ValueEnum};
+use libdlock::{
+    dlock2::{ban_policy::AdaptiveBanConfig, fc_pq::admission::SoftBan, usage_decay::UsageDecay},
+    topology::{Topology, TopologyLevel},
+};
 use strum::{Display, EnumIter, IntoEnumIterator};
 
 use crate::{
-    benchmark::dlock2::queue::extension::LockFreeQueue,
+    benchmark::dlock2::{queue::extension::LockFreeQueue, stack::extension::LockFreeStack},
     lock_target::{DLock1Target, DLock2Target, WaiterType},
 };
 
@@ -24,6 +28,203 @@ pub struct DLock2Option {
     pub experiment: Option<DLock2Experiment>,
     #[arg(long, short, value_delimiter = ',')]
     pub lock_targets: Option<Vec<DLock2Target>>,
+    #[command(flatten)]
+    pub ban_controller: BanControllerOption,
+    #[command(flatten)]
+    pub soft_ban: SoftBanOption,
+    #[command(flatten)]
+    pub usage_decay: UsageDecayOption,
+    #[command(flatten)]
+    pub combiner_credit: CombinerCreditOption,
+    #[command(flatten)]
+    pub topology: TopologyOption,
+    #[command(flatten)]
+    pub locality: LocalityOption,
+    #[command(flatten)]
+    pub batch: BatchOption,
+}
+
+// Parameters of the adaptive ban controller used by `fc-ban-adaptive` and
+// `cc-ban-adaptive`
+#[derive(Args, Debug, Clone)]
+pub struct BanControllerOption {
+    /// Number of served requests per controller window
+    #[arg(global = true, long = "ban-window", default_value_t = AdaptiveBanConfig::default().window)]
+    pub window: u64,
+    /// Proportional gain applied to the unfairness error
+    #[arg(global = true, long = "ban-gain", default_value_t = AdaptiveBanConfig::default().gain)]
+    pub gain: f64,
+    /// Unfairness tolerated before the multiplier grows
+    #[arg(global = true, long = "ban-tolerance", default_value_t = AdaptiveBanConfig::default().tolerance)]
+    pub tolerance: f64,
+    #[arg(global = true, long = "ban-initial-multiplier", default_value_t = AdaptiveBanConfig::default().initial_multiplier)]
+    pub initial_multiplier: f64,
+    #[arg(global = true, long = "ban-min-multiplier", default_value_t = AdaptiveBanConfig::default().min_multiplier)]
+    pub min_multiplier: f64,
+    #[arg(global = true, long = "ban-max-multiplier", default_value_t = AdaptiveBanConfig::default().max_multiplier)]
+    pub max_multiplier: f64,
+    /// Number of most recent controller windows kept for the log
+    #[arg(global = true, long = "ban-history", default_value_t = AdaptiveBanConfig::default().history)]
+    pub history: usize,
+}
+
+impl Default for BanControllerOption {
+    fn default() -> Self {
+        AdaptiveBanConfig::default().into()
+    }
+}
+
+impl From<AdaptiveBanConfig> for BanControllerOption {
+    fn from(config: AdaptiveBanConfig) -> Self {
+        Self {
+            window: config.window,
+            gain: config.gain,
+            tolerance: config.tolerance,
+            initial_multiplier: config.initial_multiplier,
+            min_multiplier: config.min_multiplier,
+            max_multiplier: config.max_multiplier,
+            history: config.history,
+        }
+    }
+}
+
+// Parameters of the soft-ban admission used by `fc-pq-*-soft-ban`
+#[derive(Args, Debug, Clone)]
+pub struct SoftBanOption {
+    /// Usage, as a multiple of the average, above which a thread is held out
+    #[arg(global = true, long = "soft-ban-threshold", default_value_t = SoftBan::default().threshold)]
+    pub threshold: f64,
+    /// Number of combining passes a thread is held out for
+    #[arg(global = true, long = "soft-ban-passes", default_value_t = SoftBan::default().passes)]
+    pub passes: u64,
+}
+
+impl Default for SoftBanOption {
+    fn default() -> Self {
+        let soft_ban = SoftBan::default();
+        Self {
+            threshold: soft_ban.threshold,
+            passes: soft_ban.passes,
+        }
+    }
+}
+
+impl From<&SoftBanOption> for SoftBan {
+    fn from(option: &SoftBanOption) -> Self {
+        Self {
+            threshold: option.threshold,
+            passes: option.passes,
+        }
+    }
+}
+
+// Usage decay of `fc-edf`, `fc-sl` and `fc-pq-*`, usage never decays by default
+#[derive(Args, Debug, Clone, Default)]
+pub struct UsageDecayOption {
+    /// Halve the usage every given number of tsc cycles
+    #[arg(
+        global = true,
+        long = "usage-half-life",
+        conflicts_with = "usage_window"
+    )]
+    pub half_life: Option<u64>,
+    /// Only count usage within the given number of tsc cycles
+    #[arg(
+        global = true,
+        id = "usage_window",
+        long = "usage-window",
+        value_name = "WINDOW"
+    )]
+    pub window: Option<u64>,
+}
+
+impl From<&UsageDecayOption> for UsageDecay {
+    fn from(option: &UsageDecayOption) -> Self {
+        match (option.half_life, option.window) {
+            (Some(cycles), _) => UsageDecay::HalfLife { cycles },
+            (None, Some(cycles)) => UsageDecay::Window { cycles },
+            (None, None) => UsageDecay::None,
+        }
+    }
+}
+
+// Combining time credited back to the combiner by `fc-ban*`, `cc-ban*` and
+// `fc-pq-*`, nothing is credited by default
+#[derive(Args, Debug, Clone, Default)]
+pub struct CombinerCreditOption {
+    /// Fraction of the time spent serving other threads that is taken off
+    /// the combiner's usage or ban
+    #[arg(global = true, long = "combiner-credit", default_value_t = 0.0)]
+    pub fraction: f64,
+}
+
+// Clusters of `h-synch`, the cohort locks, `shfl-lock-numa` and the `fc-pq-*`
+// locality tie-breaking, read from sysfs unless a map is given
+#[derive(Args, Debug, Clone, Default)]
+pub struct TopologyOption {
+    /// CPUs that form a cluster
+    #[arg(global = true, long = "cluster-level", default_value = "llc")]
+    pub level: ClusterLevel,
+    /// Cluster of every CPU (cycled), e.g. `0,0,1,1` treats pairs of cores as
+    /// clusters
+    #[arg(global = true, long = "cluster-map", value_delimiter = ',')]
+    pub map: Vec<usize>,
+}
+
+// Locality-aware tie-breaking of `fc-pq-*` over the clusters above, off
+// unless an epsilon is given
+#[derive(Args, Debug, Clone, Default)]
+pub struct LocalityOption {
+    /// Largest key difference to the top of the job queue within which the
+    /// combiner prefers requests of its own cluster
+    #[arg(global = true, long = "locality-epsilon")]
+    pub epsilon: Option<u64>,
+}
+
+// Batch delegates of the counter and queue experiments, taken by `fc`, `cc`,
+// `dsm` and `fc-pq-*`
+#[derive(Args, Debug, Clone, Default)]
+pub struct BatchOption {
+    /// Let the combiner hand all requests of a pass to a batch delegate that
+    /// merges them
+    #[arg(global = true, long = "batch", default_value_t = false)]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum ClusterLevel {
+    /// CPUs sharing the last-level cache
+    #[default]
+    Llc,
+    /// CPUs of the same NUMA node
+    Numa,
+}
+
+impl From<&TopologyOption> for Topology {
+    fn from(option: &TopologyOption) -> Self {
+        if !option.map.is_empty() {
+            return Topology::from_map(option.map.clone());
+        }
+
+        Topology::detect_or_single(match option.level {
+            ClusterLevel::Llc => TopologyLevel::Llc,
+            ClusterLevel::Numa => TopologyLevel::Numa,
+        })
+    }
+}
+
+impl From<&BanControllerOption> for AdaptiveBanConfig {
+    fn from(option: &BanControllerOption) -> Self {
+        Self {
+            window: option.window,
+            gain: option.gain,
+            tolerance: option.tolerance,
+            initial_multiplier: option.initial_multiplier,
+            min_multiplier: option.min_multiplier,
+            max_multiplier: option.max_multiplier,
+            history: option.history,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Display, Subcommand)]
@@ -72,6 +273,96 @@ pub enum DLock2Experiment {
         include_lock_free: bool,
         #[arg(long = "stat-hold-time", default_value_t = true)]
         stat_hold_time: bool,
+        /// Group id of every thread (cycled), threads join no group if empty
+        #[arg(long = "group", value_delimiter = ',')]
+        thread_groups: Vec<usize>,
+        /// Weight of every group id (cycled)
+        #[arg(long = "group-weight", default_values_t = [1u64], value_delimiter = ',')]
+        group_weights: Vec<u64>,
+    },
+    CounterPhaseSwap {
+        #[arg(long = "cs", default_values_t = [1000u64, 3000u64], value_delimiter = ',')]
+        cs_loops: Vec<u64>,
+        #[arg(long = "non-cs", default_values_t = [0u64], value_delimiter = ',')]
+        non_cs_loops: Vec<u64>,
+        #[arg(long = "file-name")]
+        file_name: Option<String>,
+        /// Interval in milliseconds between two samples of the loop counts
+        #[arg(long = "sample-interval", default_value_t = 100)]
+        sample_interval: u64,
+    },
+    /// Proportional counter where every request is due a fixed number of
+    /// cycles after it is issued
+    CounterDeadline {
+        #[arg(long = "cs", default_values_t = [1000u64, 3000u64], value_delimiter = ',')]
+        cs_loops: Vec<u64>,
+        #[arg(long = "non-cs", default_values_t = [0u64], value_delimiter = ',')]
+        non_cs_loops: Vec<u64>,
+        /// Latency target in tsc cycles per thread (cycled), 0 means none
+        #[arg(long = "slo", default_values_t = [50000u64, 0u64], value_delimiter = ',')]
+        slos: Vec<u64>,
+        #[arg(long = "file-name")]
+        file_name: Option<String>,
+    },
+    /// Proportional counter on a CCSynch lock in shared memory, with the
+    /// threads spread over several processes
+    CounterMultiProcess {
+        /// Processes running the configured number of threads each
+        #[arg(long = "processes", default_value_t = 2)]
+        processes: usize,
+        #[arg(long = "cs", default_values_t = [1000u64], value_delimiter = ',')]
+        cs_loops: Vec<u64>,
+        #[arg(long = "non-cs", default_values_t = [0u64], value_delimiter = ',')]
+        non_cs_loops: Vec<u64>,
+        #[arg(long = "file-name")]
+        file_name: Option<String>,
+    },
+    /// Proportional counter whose threads submit requests without waiting
+    /// for each of them
+    CounterPipelined {
+        #[arg(long = "cs", default_values_t = [1000u64], value_delimiter = ',')]
+        cs_loops: Vec<u64>,
+        #[arg(long = "non-cs", default_values_t = [0u64], value_delimiter = ',')]
+        non_cs_loops: Vec<u64>,
+        /// Requests every thread keeps in flight, one run per value
+        #[arg(long = "in-flight", default_values_t = [1usize, 4usize], value_delimiter = ',')]
+        in_flight: Vec<usize>,
+        /// Also run with every request detached
+        #[arg(long = "detached", default_value_t = false)]
+        detached: bool,
+        #[arg(long = "file-name")]
+        file_name: Option<String>,
+    },
+    /// Hash map lookups and updates, with lookups delegated or read
+    /// optimistically next to the combiner
+    ReadMostlyMap {
+        /// Share of lookups among the operations, one run per value
+        #[arg(long = "read-ratio", default_values_t = [0.5f64, 0.9f64, 0.99f64], value_delimiter = ',')]
+        read_ratios: Vec<f64>,
+        #[arg(long = "keys", default_value_t = 1024)]
+        keys: u64,
+        #[arg(long = "file-name")]
+        file_name: Option<String>,
+    },
+    /// Producers and consumers on a bounded buffer, waiting for room or
+    /// items with `lock_when` or by retrying
+    BoundedBuffer {
+        #[arg(long = "capacity", default_value_t = 64)]
+        capacity: usize,
+        #[arg(long = "file-name")]
+        file_name: Option<String>,
+    },
+    /// Transfers between accounts spread over several locks, a transfer
+    /// across two locks delegates to one from the delegate of the other
+    Transfer {
+        /// Locks the accounts are spread over
+        #[arg(long = "banks", default_value_t = 4)]
+        banks: usize,
+        /// Accounts per lock
+        #[arg(long = "accounts", default_value_t = 64)]
+        accounts: usize,
+        #[arg(long = "file-name")]
+        file_name: Option<String>,
     },
     FetchAndMultiply {
         #[arg(long = "inlcude-lock-free", default_value_t = true)]
@@ -80,13 +371,19 @@ pub enum DLock2Experiment {
     Queue {
         #[arg(long = "sequencial-queue-type", default_value = "linked-list")]
         seq_queue_type: SeqQueueType,
-        #[arg(long = "lock-free-queues")]
+        #[arg(long = "lock-free-queues", value_delimiter = ',')]
         lock_free_queues: Vec<LockFreeQueue>,
     },
     PriorityQueue {
         #[arg(long = "sequencial-pq-type", default_value = "binary-heap")]
         sequencial_pq_type: SeqPQType,
     },
+    Stack {
+        #[arg(long = "push-ratio", default_values_t = [0.5f64], value_delimiter = ',')]
+        push_ratios: Vec<f64>,
+        #[arg(long = "lock-free-stacks", value_delimiter = ',')]
+        lock_free_stacks: Vec<LockFreeStack>,
+    },
 }
 
 #[derive(Default, Debug, Clone, ValueEnum)]
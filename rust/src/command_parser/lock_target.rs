@@ -13,11 +13,27 @@ use libdlock::{
         DLockType,
     },
     dlock2::{
-        self, fc::FC, fc_ban::FCBan, fc_pq::UsageNode, mutex::DLock2Mutex, spinlock::DLock2Wrapper,
-        uscl::DLock2USCL, DLock2Delegate, DLock2Impl,
+        self,
+        ban_policy::{AdaptiveBan, AdaptiveBanConfig},
+        fc::FC,
+        fc_ban::FCBan,
+        fc_pq::{
+            admission::SoftBan,
+            locality::Locality,
+            policy::{Fifo, Lottery, Stride},
+            UsageNode,
+        },
+        mutex::DLock2Mutex,
+        shfl::policy::{NoShuffle, NumaGrouping, UsageFair},
+        spinlock::DLock2Wrapper,
+        tclock::DLock2TCLock,
+        usage_decay::UsageDecay,
+        uscl::DLock2USCL,
+        DLock2Delegate, DLock2Impl,
     },
     parker::Parker,
-    spin_lock::SpinLock,
+    spin_lock::{RawSpinLock, SpinLock},
+    topology::Topology,
     u_scl::USCL,
 };
 use serde::Serialize;
@@ -110,21 +126,53 @@ pub enum DLock2Target {
     FC,
     /// Benchmark Flat-Combining Fair (Banning) Lock
     FCBan,
+    /// Benchmark Flat-Combining Fair (Banning) Lock with adaptive penalty
+    FCBanAdaptive,
 
     /// Benchmark CCSynch
     CC,
     /// Benchmark CCSynch (Ban)
     CCBan,
+    /// Benchmark CCSynch (Ban) with adaptive penalty
+    CCBanAdaptive,
     /// Benchmark DSMSynch
     DSM,
+    /// Benchmark H-Synch (one CCSynch queue per cluster)
+    HSynch,
+    /// Benchmark ffwd (dedicated server thread on the last core)
+    Ffwd,
+    /// Benchmark CCSynch in a shared memory segment
+    ShmCC,
+    /// Benchmark cohort lock (global backoff lock, MCS per cluster)
+    CBoMcs,
+    /// Benchmark cohort lock (ticket locks, global and per cluster)
+    CTktTkt,
+    /// Benchmark Flat-Combining Lock serving deadlines first (EDF)
+    FcEdf,
     /// Benchmark FC-SL
     FcSL,
     /// Benchmark FC-PQ (BTree)
     FcPqBTree,
     /// Benchmark FC-PQ (BinaryHeap)
     FcPqBHeap,
+    /// Benchmark FC-PQ (BTree) serving requests in arrival order
+    FcPqBTreeFifo,
+    /// Benchmark FC-PQ (BTree) with stride scheduling
+    FcPqBTreeStride,
+    /// Benchmark FC-PQ (BTree) with lottery scheduling
+    FcPqBTreeLottery,
+    /// Benchmark FC-PQ (BTree) with soft-ban admission
+    FcPqBTreeSoftBan,
+    /// Benchmark FC-PQ (BinaryHeap) with soft-ban admission
+    FcPqBHeapSoftBan,
     /// Benchmark Mutex
     Mutex,
+    /// Benchmark ShflLock without reordering (MCS order)
+    ShflLock,
+    /// Benchmark ShflLock grouping waiters of the shuffler's cluster
+    ShflLockNuma,
+    /// Benchmark ShflLock moving lighter users of the lock forward
+    ShflLockUsageFair,
     /// Benchmark Spinlock
     SpinLock,
     /// Benchmark U-SCL
@@ -133,6 +181,38 @@ pub enum DLock2Target {
     FcC,
     /// Benchmark CCSynch (C)
     CcC,
+    /// Benchmark TCLock (transparent delegation behind a guard)
+    TCLock,
+}
+
+/// Parameters of the targets that take more than data and delegate
+#[derive(Debug, Clone, Default)]
+pub struct LockConfig {
+    pub adaptive_ban: AdaptiveBanConfig,
+    pub soft_ban: SoftBan,
+    /// Applied to every FC-EDF, FC-SL and FC-PQ target
+    pub usage_decay: UsageDecay,
+    /// Applied to every FC-Ban, CC-Ban and FC-PQ target
+    pub combiner_credit: f64,
+    /// Clusters of the hierarchical locks
+    pub topology: Topology,
+    /// Locality tie-breaking of every FC-PQ target, off if `None`
+    pub locality_epsilon: Option<u64>,
+    /// Whether the experiments hand their batch delegates to the targets
+    pub batch: bool,
+}
+
+impl LockConfig {
+    fn locality(&self) -> Option<Locality> {
+        self.locality_epsilon.map(|epsilon| Locality {
+            topology: self.topology.clone(),
+            epsilon,
+        })
+    }
+
+    fn adaptive_ban(&self) -> AdaptiveBan {
+        AdaptiveBan::new(self.adaptive_ban)
+    }
 }
 
 impl DLock2Target {
@@ -140,42 +220,196 @@ impl DLock2Target {
         match self {
             DLock2Target::FC
             | DLock2Target::FCBan
+            | DLock2Target::FCBanAdaptive
             | DLock2Target::CC
             | DLock2Target::CCBan
+            | DLock2Target::CCBanAdaptive
             | DLock2Target::DSM
+            | DLock2Target::HSynch
+            | DLock2Target::Ffwd
+            | DLock2Target::ShmCC
             | DLock2Target::FcC
             | DLock2Target::CcC
+            | DLock2Target::FcEdf
             | DLock2Target::FcSL
             | DLock2Target::FcPqBHeap
-            | DLock2Target::FcPqBTree => true,
-            DLock2Target::Mutex | DLock2Target::SpinLock | DLock2Target::USCL => false,
+            | DLock2Target::FcPqBTree
+            | DLock2Target::FcPqBTreeFifo
+            | DLock2Target::FcPqBTreeStride
+            | DLock2Target::FcPqBTreeLottery
+            | DLock2Target::FcPqBHeapSoftBan
+            | DLock2Target::FcPqBTreeSoftBan
+            | DLock2Target::TCLock => true,
+            DLock2Target::Mutex
+            | DLock2Target::SpinLock
+            | DLock2Target::USCL
+            | DLock2Target::CBoMcs
+            | DLock2Target::CTktTkt
+            | DLock2Target::ShflLock
+            | DLock2Target::ShflLockNuma
+            | DLock2Target::ShflLockUsageFair => false,
         }
     }
 
-    pub fn to_locktype<T, I, F>(&self, data: T, _: I, f: F) -> Option<DLock2Impl<T, I, F>>
+    pub fn to_locktype<T, I, O, F>(
+        &self,
+        data: T,
+        f: F,
+        config: &LockConfig,
+    ) -> Option<DLock2Impl<T, I, O, F>>
     where
         T: Send + Sync,
         I: Send + Sync + Debug + 'static,
-        F: DLock2Delegate<T, I>,
+        O: Send + Sync + Debug + 'static,
+        F: DLock2Delegate<T, I, O>,
     {
-        Some::<DLock2Impl<T, I, F>>(match self {
+        Some::<DLock2Impl<T, I, O, F>>(match self {
             DLock2Target::FC => FC::new(data, f).into(),
-            DLock2Target::FCBan => FCBan::new(data, f).into(),
+            DLock2Target::FCBan => FCBan::new(data, f)
+                .with_combiner_credit(config.combiner_credit)
+                .into(),
+            DLock2Target::FCBanAdaptive => FCBan::with_ban_policy(data, f, config.adaptive_ban())
+                .with_combiner_credit(config.combiner_credit)
+                .into(),
             DLock2Target::CC => dlock2::cc::CCSynch::new(data, f).into(),
-            DLock2Target::CCBan => dlock2::cc_ban::CCBan::new(data, f).into(),
+            DLock2Target::CCBan => dlock2::cc_ban::CCBan::new(data, f)
+                .with_combiner_credit(config.combiner_credit)
+                .into(),
+            DLock2Target::CCBanAdaptive => {
+                dlock2::cc_ban::CCBan::with_ban_policy(data, f, config.adaptive_ban())
+                    .with_combiner_credit(config.combiner_credit)
+                    .into()
+            }
             DLock2Target::DSM => dlock2::dsm::DSMSynch::new(data, f).into(),
-            DLock2Target::FcSL => dlock2::fc_sl::FCSL::new(data, f).into(),
-            DLock2Target::FcPqBTree => {
-                dlock2::fc_pq::FCPQ::<T, I, BTreeSet<_>, F>::new(data, f).into()
+            DLock2Target::HSynch => {
+                dlock2::hsynch::HSynch::with_topology(data, f, config.topology.clone()).into()
             }
+            DLock2Target::Ffwd => dlock2::ffwd::Ffwd::with_server(
+                data,
+                f,
+                core_affinity::get_core_ids()
+                    .and_then(|core_ids| core_ids.last().map(|core_id| core_id.id)),
+                config.topology.clone(),
+            )
+            .into(),
+            DLock2Target::ShmCC => dlock2::shm::ShmCC::new(data, f).into(),
+            DLock2Target::CBoMcs => {
+                dlock2::cohort::CBoMcs::with_topology(data, f, config.topology.clone()).into()
+            }
+            DLock2Target::CTktTkt => {
+                dlock2::cohort::CTktTkt::with_topology(data, f, config.topology.clone()).into()
+            }
+            DLock2Target::FcEdf => dlock2::fc_edf::FCEDF::new(data, f)
+                .with_usage_decay(config.usage_decay)
+                .into(),
+            DLock2Target::FcSL => dlock2::fc_sl::FCSL::new(data, f)
+                .with_usage_decay(config.usage_decay)
+                .into(),
+            DLock2Target::FcPqBTree => dlock2::fc_pq::FCPQ::<T, I, O, BTreeSet<_>, F>::new(data, f)
+                .with_usage_decay(config.usage_decay)
+                .with_combiner_credit(config.combiner_credit)
+                .with_locality(config.locality())
+                .into(),
             DLock2Target::FcPqBHeap => {
-                dlock2::fc_pq::FCPQ::<T, I, BinaryHeap<_>, F>::new(data, f).into()
+                dlock2::fc_pq::FCPQ::<T, I, O, BinaryHeap<_>, F>::new(data, f)
+                    .with_usage_decay(config.usage_decay)
+                    .with_combiner_credit(config.combiner_credit)
+                    .with_locality(config.locality())
+                    .into()
+            }
+            DLock2Target::FcPqBTreeFifo => {
+                dlock2::fc_pq::FCPQ::<T, I, O, BTreeSet<_>, F, RawSpinLock, _, _>::with_policy(
+                    data,
+                    f,
+                    Fifo::default(),
+                )
+                .with_usage_decay(config.usage_decay)
+                .with_combiner_credit(config.combiner_credit)
+                .with_locality(config.locality())
+                .into()
+            }
+            DLock2Target::FcPqBTreeStride => {
+                dlock2::fc_pq::FCPQ::<T, I, O, BTreeSet<_>, F, RawSpinLock, _, _>::with_policy(
+                    data,
+                    f,
+                    Stride::default(),
+                )
+                .with_usage_decay(config.usage_decay)
+                .with_combiner_credit(config.combiner_credit)
+                .with_locality(config.locality())
+                .into()
+            }
+            DLock2Target::FcPqBTreeLottery => {
+                dlock2::fc_pq::FCPQ::<T, I, O, BTreeSet<_>, F, RawSpinLock, _, _>::with_policy(
+                    data,
+                    f,
+                    Lottery::default(),
+                )
+                .with_usage_decay(config.usage_decay)
+                .with_combiner_credit(config.combiner_credit)
+                .with_locality(config.locality())
+                .into()
+            }
+            DLock2Target::FcPqBTreeSoftBan => dlock2::fc_pq::FCPQ::<
+                T,
+                I,
+                O,
+                BTreeSet<_>,
+                F,
+                RawSpinLock,
+                SoftBan,
+            >::with_admission(
+                data, f, config.soft_ban
+            )
+            .with_usage_decay(config.usage_decay)
+            .with_combiner_credit(config.combiner_credit)
+            .with_locality(config.locality())
+            .into(),
+            DLock2Target::FcPqBHeapSoftBan => dlock2::fc_pq::FCPQ::<
+                T,
+                I,
+                O,
+                BinaryHeap<_>,
+                F,
+                RawSpinLock,
+                SoftBan,
+            >::with_admission(
+                data, f, config.soft_ban
+            )
+            .with_usage_decay(config.usage_decay)
+            .with_combiner_credit(config.combiner_credit)
+            .with_locality(config.locality())
+            .into(),
+            DLock2Target::ShflLock => {
+                dlock2::shfl::ShflLock::<_, _, _, _, NoShuffle>::with_topology(
+                    data,
+                    f,
+                    config.topology.clone(),
+                )
+                .into()
+            }
+            DLock2Target::ShflLockNuma => {
+                dlock2::shfl::ShflLock::<_, _, _, _, NumaGrouping>::with_topology(
+                    data,
+                    f,
+                    config.topology.clone(),
+                )
+                .into()
+            }
+            DLock2Target::ShflLockUsageFair => {
+                dlock2::shfl::ShflLock::<_, _, _, _, UsageFair>::with_topology(
+                    data,
+                    f,
+                    config.topology.clone(),
+                )
+                .into()
             }
-            DLock2Target::SpinLock => DLock2Wrapper::new(data, f).into(),
+            DLock2Target::SpinLock => DLock2Wrapper::<T, I, O, F, RawSpinLock>::new(data, f).into(),
             DLock2Target::Mutex => DLock2Mutex::new(data, f).into(),
             DLock2Target::USCL => DLock2USCL::new(data, f).into(),
             DLock2Target::FcC => CFlatCombining::new(data, f).into(),
             DLock2Target::CcC => CCCSynch::new(data, f).into(),
+            DLock2Target::TCLock => DLock2TCLock::new(data, f).into(),
         })
     }
 }